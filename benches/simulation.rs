@@ -0,0 +1,61 @@
+//! Benchmarks for stepping the simulation. Each is run against tracks of 1k,
+//! 10k and 100k lines; only the lines near the rider are in the physics world,
+//! so the time taken should stay about the same as the track grows.
+
+#![feature(test)]
+
+extern crate rust_rider;
+extern crate test;
+
+use rust_rider::simulation::{KEYFRAME_INTERVAL, Simulation};
+use rust_rider::track::{LineKind, LineSegment, Point, Track};
+
+/// How long each line of a generated track is, in world units.
+const LINE_LENGTH: f64 = 10.0;
+
+/// A long, gentle slope of `count` lines under the rider's start point, each
+/// starting where the last ended.
+fn slope_track(count: usize) -> Track {
+  let mut track = Track::new();
+  let top = Point::new(track.rider_start.x - 50.0, track.rider_start.y + 50.0);
+  let point = |index: usize| {
+    let x = index as f64 * LINE_LENGTH;
+    Point::new(top.x + x, top.y + x / 4.0)
+  };
+  for index in 0..count {
+    track.line_segments.push(
+      LineSegment::new(point(index), point(index + 1), LineKind::Normal),
+    );
+  }
+
+  track
+}
+
+/// A second of the ride from the start, including the rebuild of the world
+/// when the ride is put back to its start and on each keyframe.
+fn bench_ride(bencher: &mut test::Bencher, count: usize) {
+  let mut simulation = Simulation::new(&slope_track(count));
+  let start = simulation.snapshot();
+  bencher.iter(|| {
+    simulation.restore(&start);
+    for _ in 0..KEYFRAME_INTERVAL * 2 {
+      simulation.step();
+    }
+    test::black_box(simulation.checksum());
+  });
+}
+
+#[bench]
+fn ride_1k(bencher: &mut test::Bencher) {
+  bench_ride(bencher, 1_000);
+}
+
+#[bench]
+fn ride_10k(bencher: &mut test::Bencher) {
+  bench_ride(bencher, 10_000);
+}
+
+#[bench]
+fn ride_100k(bencher: &mut test::Bencher) {
+  bench_ride(bencher, 100_000);
+}
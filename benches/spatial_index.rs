@@ -0,0 +1,342 @@
+//! Benchmarks for the spatial index over a track's line segments, and for
+//! drawing the part of a track in view. Each is run against tracks of 1k, 10k
+//! and 100k lines; the time taken should stay about the same as the track
+//! grows, where a scan of every line grows with it.
+
+#![feature(test)]
+
+extern crate rust_rider;
+extern crate test;
+
+use rust_rider::camera::Camera;
+use rust_rider::geometry::Aabb;
+use rust_rider::history::Command;
+use rust_rider::render::{Color, Renderer, Transform};
+use rust_rider::scene::draw_visible_lines;
+use rust_rider::spatial_index::SpatialIndex;
+use rust_rider::track::{LineKind, LineSegment, Point, Track, Vector};
+
+/// How long each line of a generated track is, in world units.
+const LINE_LENGTH: f64 = 20.0;
+/// How many places along a generated track each benchmark queries.
+const QUERY_COUNT: usize = 64;
+/// How many lines, spread along a generated track, are deleted at once.
+const DELETE_COUNT: usize = 100;
+
+/// A winding track of `count` lines, each starting where the last ended, as a
+/// drawn track would. The same count always gives the same track.
+fn winding_track(count: usize) -> Vec<LineSegment> {
+  // A linear congruential generator, so that runs are repeatable.
+  let mut seed: u64 = 0x2545f4914f6cdd1d;
+  let mut random = || {
+    seed = seed
+      .wrapping_mul(6364136223846793005)
+      .wrapping_add(1442695040888963407);
+    (seed >> 11) as f64 / (1u64 << 53) as f64
+  };
+
+  let mut line_segments = Vec::with_capacity(count);
+  let mut point = Point::new(0.0, 0.0);
+  let mut angle: f64 = 0.0;
+  for _ in 0..count {
+    angle += (random() - 0.5) * 0.5;
+    let next = Point::new(
+      point.x + LINE_LENGTH * angle.cos(),
+      point.y + LINE_LENGTH * angle.sin(),
+    );
+    line_segments.push(LineSegment::new(point, next, LineKind::Normal));
+    point = next;
+  }
+
+  line_segments
+}
+
+/// Points spread evenly along a track, to query around.
+fn query_points(line_segments: &[LineSegment]) -> Vec<Point> {
+  let stride = (line_segments.len() / QUERY_COUNT).max(1);
+  (0..QUERY_COUNT.min(line_segments.len()))
+    .map(|query| *line_segments[query * stride].point1())
+    .collect()
+}
+
+fn bench_query_point(bencher: &mut test::Bencher, count: usize) {
+  let line_segments = winding_track(count);
+  let index = SpatialIndex::from_line_segments(&line_segments);
+  let points = query_points(&line_segments);
+  bencher.iter(|| for point in points.iter() {
+    test::black_box(index.query_point(point, 8.0));
+  });
+}
+
+fn bench_query_aabb(bencher: &mut test::Bencher, count: usize) {
+  let line_segments = winding_track(count);
+  let index = SpatialIndex::from_line_segments(&line_segments);
+  let points = query_points(&line_segments);
+  bencher.iter(|| for point in points.iter() {
+    // About what an 800x600 window shows.
+    let view = Aabb::from_corners(
+      &Point::new(point.x - 400.0, point.y - 300.0),
+      &Point::new(point.x + 400.0, point.y + 300.0),
+    );
+    test::black_box(index.query_aabb(&view));
+  });
+}
+
+fn bench_query_segment(bencher: &mut test::Bencher, count: usize) {
+  let line_segments = winding_track(count);
+  let index = SpatialIndex::from_line_segments(&line_segments);
+  let points = query_points(&line_segments);
+  bencher.iter(|| for point in points.iter() {
+    // About the sled's runners.
+    let end = Point::new(point.x + 24.0, point.y);
+    test::black_box(index.query_segment(point, &end, 1.0));
+  });
+}
+
+/// What a hit test cost before the index: a scan of every line.
+fn bench_scan_point(bencher: &mut test::Bencher, count: usize) {
+  let line_segments = winding_track(count);
+  let points = query_points(&line_segments);
+  bencher.iter(|| for point in points.iter() {
+    test::black_box(
+      line_segments
+        .iter()
+        .filter(|line_segment| line_segment.distance_to(point) <= 8.0)
+        .count(),
+    );
+  });
+}
+
+/// Drawing a line, then undoing it.
+fn bench_push_remove(bencher: &mut test::Bencher, count: usize) {
+  let line_segments = winding_track(count);
+  let mut index = SpatialIndex::from_line_segments(&line_segments);
+  let last = line_segments.last().unwrap();
+  bencher.iter(|| {
+    index.push(*last.point2(), *last.point1());
+    index.swap_remove(count);
+  });
+}
+
+/// Deleting a line from the middle of the track, then undoing it.
+fn bench_remove_insert_middle(bencher: &mut test::Bencher, count: usize) {
+  let line_segments = winding_track(count);
+  let mut index = SpatialIndex::from_line_segments(&line_segments);
+  let middle = count / 2;
+  let point1 = *line_segments[middle].point1();
+  let point2 = *line_segments[middle].point2();
+  bencher.iter(|| {
+    index.swap_remove(middle);
+    index.swap_insert(middle, point1, point2);
+  });
+}
+
+/// Deleting lines selected from all along the track, then undoing it, as the
+/// editor does: through the track's lines and their index together.
+fn bench_delete_undo(bencher: &mut test::Bencher, count: usize) {
+  let mut line_segments = winding_track(count);
+  let mut index = SpatialIndex::from_line_segments(&line_segments);
+  let stride = (count / DELETE_COUNT).max(1);
+  let delete = Command::Remove(
+    (0..DELETE_COUNT.min(count))
+      .map(|selected| {
+        let position = selected * stride;
+        (position, line_segments[position].clone())
+      })
+      .collect(),
+  );
+  let undo = delete.inverse();
+  bencher.iter(|| {
+    delete.apply(&mut line_segments, &mut index);
+    undo.apply(&mut line_segments, &mut index);
+  });
+}
+
+/// Dragging a line, as the editor does on every cursor move.
+fn bench_replace(bencher: &mut test::Bencher, count: usize) {
+  let line_segments = winding_track(count);
+  let mut index = SpatialIndex::from_line_segments(&line_segments);
+  let middle = count / 2;
+  let point1 = *line_segments[middle].point1();
+  let point2 = *line_segments[middle].point2();
+  let mut offset = 0.0;
+  bencher.iter(|| {
+    offset = (offset + 1.0) % 100.0;
+    index.replace(
+      middle,
+      Point::new(point1.x + offset, point1.y),
+      Point::new(point2.x + offset, point2.y),
+    );
+  });
+}
+
+/// A Renderer that only counts what it is asked to draw, so that drawing
+/// benchmarks measure finding and batching the lines rather than filling
+/// pixels.
+#[derive(Default)]
+struct CountingRenderer {
+  transform: Transform,
+  calls: usize,
+  vertices: usize,
+}
+
+impl Renderer for CountingRenderer {
+  fn transform(&self) -> Transform {
+    self.transform
+  }
+
+  fn set_transform(&mut self, transform: Transform) {
+    self.transform = transform;
+  }
+
+  fn clear(&mut self, _: Color) {
+    self.calls += 1;
+  }
+
+  fn rectangle(&mut self, _: f64, _: f64, _: f64, _: f64, _: Color) {
+    self.calls += 1;
+  }
+
+  fn circle(&mut self, _: &Point, _: f64, _: Color) {
+    self.calls += 1;
+  }
+
+  fn triangles(&mut self, vertices: &[Point], _: Color) {
+    self.calls += 1;
+    self.vertices += vertices.len();
+  }
+}
+
+/// Drawing one frame of the track, with an 800x600 window following along it.
+fn bench_draw_visible_lines(bencher: &mut test::Bencher, count: usize) {
+  let mut track = Track::new();
+  track.line_segments = winding_track(count);
+  let index = SpatialIndex::from_line_segments(&track.line_segments);
+  let points = query_points(&track.line_segments);
+  let screen_size = Vector::new(800.0, 600.0);
+  let mut camera = Camera::new();
+  let mut next = 0;
+  bencher.iter(|| {
+    camera.center_on(&points[next], &screen_size);
+    next = (next + 1) % points.len();
+
+    let mut renderer = CountingRenderer::default();
+    draw_visible_lines(&mut renderer, &track, &index, &camera, &screen_size);
+    test::black_box((renderer.calls, renderer.vertices));
+  });
+}
+
+#[bench]
+fn query_point_1k(bencher: &mut test::Bencher) {
+  bench_query_point(bencher, 1_000);
+}
+
+#[bench]
+fn query_point_10k(bencher: &mut test::Bencher) {
+  bench_query_point(bencher, 10_000);
+}
+
+#[bench]
+fn query_point_100k(bencher: &mut test::Bencher) {
+  bench_query_point(bencher, 100_000);
+}
+
+#[bench]
+fn query_aabb_1k(bencher: &mut test::Bencher) {
+  bench_query_aabb(bencher, 1_000);
+}
+
+#[bench]
+fn query_aabb_10k(bencher: &mut test::Bencher) {
+  bench_query_aabb(bencher, 10_000);
+}
+
+#[bench]
+fn query_aabb_100k(bencher: &mut test::Bencher) {
+  bench_query_aabb(bencher, 100_000);
+}
+
+#[bench]
+fn query_segment_1k(bencher: &mut test::Bencher) {
+  bench_query_segment(bencher, 1_000);
+}
+
+#[bench]
+fn query_segment_10k(bencher: &mut test::Bencher) {
+  bench_query_segment(bencher, 10_000);
+}
+
+#[bench]
+fn query_segment_100k(bencher: &mut test::Bencher) {
+  bench_query_segment(bencher, 100_000);
+}
+
+#[bench]
+fn scan_point_1k(bencher: &mut test::Bencher) {
+  bench_scan_point(bencher, 1_000);
+}
+
+#[bench]
+fn scan_point_10k(bencher: &mut test::Bencher) {
+  bench_scan_point(bencher, 10_000);
+}
+
+#[bench]
+fn scan_point_100k(bencher: &mut test::Bencher) {
+  bench_scan_point(bencher, 100_000);
+}
+
+#[bench]
+fn push_remove_1k(bencher: &mut test::Bencher) {
+  bench_push_remove(bencher, 1_000);
+}
+
+#[bench]
+fn push_remove_100k(bencher: &mut test::Bencher) {
+  bench_push_remove(bencher, 100_000);
+}
+
+#[bench]
+fn remove_insert_middle_1k(bencher: &mut test::Bencher) {
+  bench_remove_insert_middle(bencher, 1_000);
+}
+
+#[bench]
+fn remove_insert_middle_100k(bencher: &mut test::Bencher) {
+  bench_remove_insert_middle(bencher, 100_000);
+}
+
+#[bench]
+fn delete_undo_1k(bencher: &mut test::Bencher) {
+  bench_delete_undo(bencher, 1_000);
+}
+
+#[bench]
+fn delete_undo_100k(bencher: &mut test::Bencher) {
+  bench_delete_undo(bencher, 100_000);
+}
+
+#[bench]
+fn replace_1k(bencher: &mut test::Bencher) {
+  bench_replace(bencher, 1_000);
+}
+
+#[bench]
+fn replace_100k(bencher: &mut test::Bencher) {
+  bench_replace(bencher, 100_000);
+}
+
+#[bench]
+fn draw_visible_lines_1k(bencher: &mut test::Bencher) {
+  bench_draw_visible_lines(bencher, 1_000);
+}
+
+#[bench]
+fn draw_visible_lines_10k(bencher: &mut test::Bencher) {
+  bench_draw_visible_lines(bencher, 10_000);
+}
+
+#[bench]
+fn draw_visible_lines_100k(bencher: &mut test::Bencher) {
+  bench_draw_visible_lines(bencher, 100_000);
+}
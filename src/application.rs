@@ -1,36 +1,44 @@
 extern crate piston_window;
 
-use std::cell::RefCell;
 use std::collections::BTreeMap;
-use std::rc::Rc;
 
 use error;
+use event_source;
 use handler;
+use replay;
 
-pub struct Application<Window, EventHandler>
+pub struct Application<Source, EventHandler>
 where
-  Window: piston_window::Window,
+  Source: event_source::EventSource,
   EventHandler: handler::EventHandler,
 {
-  window: Rc<RefCell<piston_window::PistonWindow<Window>>>,
+  event_source: Source,
+  recorder: Option<replay::Recorder>,
   application_modes: BTreeMap<String, Box<EventHandler>>,
   active_application_mode: Option<String>,
 }
 
-impl<Window, EventHandler> Application<Window, EventHandler>
+impl<Source, EventHandler> Application<Source, EventHandler>
 where
-  Window: piston_window::Window,
+  Source: event_source::EventSource,
   EventHandler: handler::EventHandler,
 {
-  /// Create a Application with default values.
-  pub fn new(window: Rc<RefCell<piston_window::PistonWindow<Window>>>) -> Self {
+  /// Create a Application with default values, taking its events from
+  /// `event_source`.
+  pub fn new(event_source: Source) -> Self {
     Application {
-      window: window,
+      event_source: event_source,
+      recorder: None,
       application_modes: BTreeMap::new(),
       active_application_mode: None,
     }
   }
 
+  /// Record every event passed to an application mode from now on.
+  pub fn record(&mut self, recorder: replay::Recorder) {
+    self.recorder = Some(recorder);
+  }
+
   pub fn add_application_mode(
     &mut self,
     name: &str,
@@ -83,6 +91,9 @@ where
     }
   }
 
+  /// Make the named application mode the recipient of all future events.
+  /// The previously active mode is notified through EventHandler::on_exit
+  /// before the named mode is notified through EventHandler::on_enter.
   pub fn set_active_application_mode(
     &mut self,
     name: &str,
@@ -98,19 +109,33 @@ where
       None => {},
     }
 
+    if !self.application_modes.contains_key(name) {
+      return Err(error::Error::from(
+          format!("No application mode named {}", name),
+          ));
+    }
+
+    if let Some(application_mode) = self.get_active_application_mode() {
+      application_mode.on_exit()?;
+    }
+    self.active_application_mode = Some(String::from(name));
+
     match self.get_application_mode(name) {
-      Some(application_mode) => Ok(application_mode),
+      Some(application_mode) => {
+        application_mode.on_enter()?;
+        Ok(application_mode)
+      },
       None => Err(error::Error::from(
           format!("No application mode named {}", name),
           )),
     }
   }
 
-  pub fn next(&mut self) -> Option<piston_window::Event> {
-    self.window.borrow_mut().next()
+  pub fn next(&mut self) -> error::Result<Option<piston_window::Event>> {
+    self.event_source.next_event()
   }
 
-  /// Process events from window until termination.
+  /// Process events from the event source until termination.
   /// Returns early if active_application_mode is None at any time.
   pub fn spin(&mut self) -> error::Result<()> {
     if self.active_application_mode.is_none() {
@@ -119,16 +144,30 @@ where
           ));
     }
 
-    while let Some(event) = self.next() {
-      match self.get_active_application_mode() {
-        Some(application_mode) => {
-          application_mode.on_event(&event)?;
-        },
-        None => {
-          return Err(error::Error::from(
-              "Cannot handle event without active application mode",
-              ));
-        },
+    while let Some(event) = self.next()? {
+      let tick = self
+        .get_active_application_mode()
+        .and_then(|application_mode| application_mode.simulation_tick());
+      self.event_source.check_tick(tick)?;
+      if let Some(ref mut recorder) = self.recorder {
+        recorder.record(&event, tick)?;
+      }
+
+      let requested_application_mode =
+        match self.get_active_application_mode() {
+          Some(application_mode) => {
+            application_mode.on_event(&event)?;
+            application_mode.requested_application_mode()
+          },
+          None => {
+            return Err(error::Error::from(
+                "Cannot handle event without active application mode",
+                ));
+          },
+        };
+
+      if let Some(name) = requested_application_mode {
+        self.set_active_application_mode(&name)?;
       }
     }
 
@@ -0,0 +1,92 @@
+#![cfg_attr(feature = "strict", deny(missing_docs))]
+#![cfg_attr(feature = "strict", deny(warnings))]
+#![recursion_limit = "1024"]
+
+//! Rust Rider Simulator
+//!
+//! Rides a saved track without a window, and prints the rider's trajectory,
+//! whether the rider crashed, and whether and when the rider finished the
+//! track or fell off it, as JSON. The report's `outcome` sums this up as one of
+//! "completed", "crashed", "fell_off" and "timed_out". Every sample carries a
+//! checksum of the rider's state, which is the same on every run of the track
+//! by the same build, so a run can be checked against another.
+//!
+//! Usage: rust_rider_sim TRACK [--duration SECONDS] [--sample-interval TICKS]
+
+extern crate error_chain;
+extern crate rust_rider;
+extern crate serde_json;
+
+use rust_rider::{error, headless, track_file};
+
+/// Parse the value following a command line flag.
+fn parse_flag_value<T>(flag: &str, value: Option<String>) -> error::Result<T>
+where
+  T: std::str::FromStr,
+{
+  match value {
+    Some(value) => value.parse::<T>().map_err(|_| {
+      error::Error::from(format!("Invalid value {} for {}", value, flag))
+    }),
+    None => Err(error::Error::from(format!("Missing value for {}", flag))),
+  }
+}
+
+fn run() -> error::Result<()> {
+  use error::ResultExt; // chain_err
+
+  let mut track_path = None;
+  let mut settings = headless::RunSettings::default();
+
+  let mut args = std::env::args().skip(1);
+  while let Some(arg) = args.next() {
+    match arg.as_str() {
+      "--duration" => {
+        settings.duration = parse_flag_value(&arg, args.next())?;
+      },
+      "--sample-interval" => {
+        settings.sample_interval = parse_flag_value(&arg, args.next())?;
+      },
+      _ => {
+        if track_path.is_some() {
+          return Err(error::Error::from(
+            format!("Unexpected argument {}", arg),
+          ));
+        }
+        track_path = Some(std::path::PathBuf::from(arg));
+      },
+    }
+  }
+  let track_path = match track_path {
+    Some(track_path) => track_path,
+    None => {
+      return Err(error::Error::from(
+        "Usage: rust_rider_sim TRACK [--duration SECONDS] \
+         [--sample-interval TICKS]",
+      ));
+    },
+  };
+
+  let track = track_file::load(&track_path).chain_err(|| {
+    format!("Failed to open track from {}", track_path.display())
+  })?;
+  let report = headless::run(&track, &settings);
+
+  let stdout = std::io::stdout();
+  serde_json::to_writer_pretty(stdout.lock(), &report)
+    .chain_err(|| "Failed to write report")?;
+  println!("");
+
+  Ok(())
+}
+
+fn main() {
+  if let Err(ref e) = run() {
+    use std::io::Write; // writeln
+    use error_chain::ChainedError; // display_chain
+
+    writeln!(std::io::stderr(), "{}", e.display_chain())
+      .expect("Error writing to stderr");
+    std::process::exit(1);
+  }
+}
@@ -0,0 +1,292 @@
+use geometry;
+use render;
+use track::{Point, Vector};
+
+/// How a Camera keeps up with the rider during playback.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FollowMode {
+  /// Keep the rider's lead point exactly in the middle of the screen.
+  Locked,
+  /// Let the rider's lead point roam within the dead zone, and ease towards it
+  /// once it leaves.
+  Free,
+}
+
+/// How a Camera follows the rider during playback.
+#[derive(Clone, Copy, Debug)]
+pub struct FollowSettings {
+  pub mode: FollowMode,
+  /// How far ahead of the rider to look, in seconds of the rider's velocity.
+  pub lead: f64,
+  /// Roughly how many seconds a FollowMode::Free camera takes to catch up
+  /// with the rider. Zero catches up immediately.
+  pub smoothing: f64,
+  /// The size, in screen pixels, of the region in the middle of the screen
+  /// that a FollowMode::Free camera lets the rider roam in.
+  pub dead_zone: Vector,
+}
+
+/// The closest a Camera may zoom in.
+pub const MAX_ZOOM: f64 = 16.0;
+/// The furthest a Camera may zoom out.
+pub const MIN_ZOOM: f64 = 1.0 / 16.0;
+/// How much one notch of the scroll wheel zooms by.
+pub const ZOOM_STEP: f64 = 1.1;
+
+/// The view onto a track. Converts between world coordinates, in which the
+/// track is built, and screen coordinates, in window pixels.
+///
+/// The world point at `offset` is shown in the top-left corner of the window,
+/// and one world unit spans `zoom` pixels.
+#[derive(Clone, Copy, Debug)]
+pub struct Camera {
+  offset: Vector,
+  zoom: f64,
+}
+
+impl Default for Camera {
+  fn default() -> Camera {
+    Camera::new()
+  }
+}
+
+impl Camera {
+  /// Create a Camera where world and screen coordinates coincide.
+  pub fn new() -> Camera {
+    Camera {
+      offset: Vector::new(0.0, 0.0),
+      zoom: 1.0,
+    }
+  }
+
+  pub fn offset(&self) -> &Vector {
+    &self.offset
+  }
+
+  pub fn zoom(&self) -> f64 {
+    self.zoom
+  }
+
+  /// The world point shown at a screen point.
+  pub fn to_world(&self, screen_point: &Point) -> Point {
+    Point::new(
+      screen_point.x / self.zoom + self.offset.x,
+      screen_point.y / self.zoom + self.offset.y,
+    )
+  }
+
+  /// The screen point showing a world point.
+  pub fn to_screen(&self, world_point: &Point) -> Point {
+    Point::new(
+      (world_point.x - self.offset.x) * self.zoom,
+      (world_point.y - self.offset.y) * self.zoom,
+    )
+  }
+
+  /// Move the view so that the world follows the cursor by `screen_delta`.
+  pub fn pan(&mut self, screen_delta: &Vector) {
+    self.offset -= *screen_delta / self.zoom;
+  }
+
+  /// The part of the world shown on a screen of the given size.
+  pub fn visible_area(&self, screen_size: &Vector) -> geometry::Aabb {
+    geometry::Aabb::from_corners(
+      &self.to_world(&Point::origin()),
+      &self.to_world(&Point::from_coordinates(*screen_size)),
+    )
+  }
+
+  /// The world point shown in the middle of a screen of the given size.
+  pub fn center(&self, screen_size: &Vector) -> Point {
+    Point::from_coordinates(self.offset + *screen_size / (2.0 * self.zoom))
+  }
+
+  /// Show the world point `center` in the middle of a screen of the given
+  /// size.
+  pub fn center_on(&mut self, center: &Point, screen_size: &Vector) {
+    self.offset = center.coords - *screen_size / (2.0 * self.zoom);
+  }
+
+  /// Multiply the zoom by `factor`, keeping the world point under
+  /// `screen_point` in place.
+  pub fn zoom_at(&mut self, screen_point: &Point, factor: f64) {
+    let anchor = self.to_world(screen_point);
+    self.zoom = (self.zoom * factor).max(MIN_ZOOM).min(MAX_ZOOM);
+    self.offset = Vector::new(
+      anchor.x - screen_point.x / self.zoom,
+      anchor.y - screen_point.y / self.zoom,
+    );
+  }
+
+  /// Move the view `dt` seconds further along in following a rider at
+  /// `position` travelling at `velocity`.
+  pub fn follow(
+    &mut self,
+    settings: &FollowSettings,
+    position: &Point,
+    velocity: &Vector,
+    screen_size: &Vector,
+    dt: f64,
+  ) {
+    let target = *position + *velocity * settings.lead;
+
+    match settings.mode {
+      FollowMode::Locked => {
+        self.center_on(&target, screen_size);
+      },
+      FollowMode::Free => {
+        // Only the part of the target's offset that lies outside the dead zone
+        // needs to be made up.
+        let center = self.center(screen_size);
+        let offset = target - center;
+        let half_dead_zone = settings.dead_zone / (2.0 * self.zoom);
+        let overshoot = Vector::new(
+          offset.x - offset.x.max(-half_dead_zone.x).min(half_dead_zone.x),
+          offset.y - offset.y.max(-half_dead_zone.y).min(half_dead_zone.y),
+        );
+
+        // Exponential easing closes the same fraction of the distance in the
+        // same time, regardless of how that time is split into updates.
+        let easing = if settings.smoothing > 0.0 {
+          1.0 - (-dt / settings.smoothing).exp()
+        } else {
+          1.0
+        };
+        self.center_on(&(center + overshoot * easing), screen_size);
+      },
+    }
+  }
+
+  /// The world-to-screen transform, with which anything drawn can be
+  /// positioned in world coordinates.
+  pub fn transform(&self) -> render::Transform {
+    render::Transform::identity()
+      .zoom(self.zoom)
+      .trans(-self.offset.x, -self.offset.y)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn screen_and_world_points_convert_both_ways() {
+    let mut camera = Camera::new();
+    camera.pan(&Vector::new(-30.0, 20.0));
+    camera.zoom_at(&Point::new(0.0, 0.0), 2.0);
+
+    let world = Point::new(45.0, -7.5);
+    let screen = camera.to_screen(&world);
+    assert_eq!(screen, Point::new(30.0, 25.0));
+    assert_eq!(camera.to_world(&screen), world);
+  }
+
+  #[test]
+  fn zooming_keeps_the_point_under_the_cursor_in_place() {
+    let mut camera = Camera::new();
+    let cursor = Point::new(200.0, 150.0);
+    let under_cursor = camera.to_world(&cursor);
+
+    camera.zoom_at(&cursor, 4.0);
+    assert_eq!(camera.zoom(), 4.0);
+    assert_eq!(camera.to_world(&cursor), under_cursor);
+  }
+
+  #[test]
+  fn zoom_is_limited() {
+    let mut camera = Camera::new();
+    camera.zoom_at(&Point::origin(), 1000.0);
+    assert_eq!(camera.zoom(), MAX_ZOOM);
+    camera.zoom_at(&Point::origin(), 1.0e-6);
+    assert_eq!(camera.zoom(), MIN_ZOOM);
+  }
+
+  #[test]
+  fn centering_shows_a_point_in_the_middle_of_the_screen() {
+    let screen_size = Vector::new(800.0, 600.0);
+    let mut camera = Camera::new();
+    camera.zoom_at(&Point::origin(), 2.0);
+    camera.center_on(&Point::new(1000.0, -50.0), &screen_size);
+
+    assert_eq!(camera.center(&screen_size), Point::new(1000.0, -50.0));
+    assert_eq!(
+      camera.to_screen(&Point::new(1000.0, -50.0)),
+      Point::new(400.0, 300.0)
+    );
+    let visible_area = camera.visible_area(&screen_size);
+    assert_eq!(visible_area.min, Point::new(800.0, -200.0));
+    assert_eq!(visible_area.max, Point::new(1200.0, 100.0));
+  }
+
+  fn follow_settings(mode: FollowMode, smoothing: f64) -> FollowSettings {
+    FollowSettings {
+      mode: mode,
+      lead: 0.5,
+      smoothing: smoothing,
+      dead_zone: Vector::new(200.0, 100.0),
+    }
+  }
+
+  #[test]
+  fn a_locked_camera_centers_ahead_of_the_rider() {
+    let screen_size = Vector::new(800.0, 600.0);
+    let mut camera = Camera::new();
+    camera.follow(
+      &follow_settings(FollowMode::Locked, 1.0),
+      &Point::new(100.0, 100.0),
+      &Vector::new(40.0, -20.0),
+      &screen_size,
+      1.0 / 60.0,
+    );
+    assert_eq!(camera.center(&screen_size), Point::new(120.0, 90.0));
+  }
+
+  #[test]
+  fn a_free_camera_lets_the_rider_roam_the_dead_zone() {
+    let screen_size = Vector::new(800.0, 600.0);
+    let settings = follow_settings(FollowMode::Free, 0.0);
+    let mut camera = Camera::new();
+    camera.center_on(&Point::origin(), &screen_size);
+
+    camera.follow(
+      &settings,
+      &Point::new(90.0, -40.0),
+      &Vector::new(0.0, 0.0),
+      &screen_size,
+      1.0 / 60.0,
+    );
+    assert_eq!(camera.center(&screen_size), Point::origin());
+
+    // Without smoothing, the camera moves just enough to bring the rider
+    // back to the edge of the dead zone.
+    camera.follow(
+      &settings,
+      &Point::new(130.0, 0.0),
+      &Vector::new(0.0, 0.0),
+      &screen_size,
+      1.0 / 60.0,
+    );
+    assert_eq!(camera.center(&screen_size), Point::new(30.0, 0.0));
+  }
+
+  #[test]
+  fn a_smoothed_camera_eases_towards_the_rider() {
+    let screen_size = Vector::new(800.0, 600.0);
+    let settings = follow_settings(FollowMode::Free, 0.5);
+    let mut camera = Camera::new();
+    camera.center_on(&Point::origin(), &screen_size);
+
+    camera.follow(
+      &settings,
+      &Point::new(200.0, 0.0),
+      &Vector::new(0.0, 0.0),
+      &screen_size,
+      0.5,
+    );
+    // The rider is 100 units beyond the dead zone, and one smoothing time
+    // closes all but 1/e of that.
+    let expected = 100.0 * (1.0 - (-1.0f64).exp());
+    assert!((camera.center(&screen_size).x - expected).abs() < 1e-9);
+  }
+}
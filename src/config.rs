@@ -1,8 +1,15 @@
+#[cfg(feature = "window")]
 extern crate piston_window;
 extern crate serde_json;
 extern crate std;
 
+use std::collections::BTreeMap;
+
+use camera;
 use error;
+use keybindings;
+use snap;
+use track;
 
 fn default_window_size_width() -> u32 {
   1600
@@ -52,6 +59,36 @@ fn default_event_bench_mode() -> bool {
 fn default_event_lazy() -> bool {
   false
 }
+fn default_track_path() -> String {
+  String::from("untitled.track.json")
+}
+fn default_camera_follow() -> CameraFollowConfig {
+  CameraFollowConfig::Free
+}
+fn default_camera_lead() -> f64 {
+  0.25
+}
+fn default_camera_smoothing() -> f64 {
+  0.3
+}
+fn default_camera_dead_zone_width() -> f64 {
+  400.0
+}
+fn default_camera_dead_zone_height() -> f64 {
+  250.0
+}
+fn default_snap_endpoint_radius() -> f64 {
+  snap::SnapSettings::default().endpoint_radius
+}
+fn default_snap_grid_size() -> f64 {
+  snap::SnapSettings::default().grid_size
+}
+fn default_snap_grid() -> bool {
+  snap::SnapSettings::default().grid
+}
+fn default_snap_angle_steps() -> Vec<f64> {
+  snap::SnapSettings::default().angle_steps
+}
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 struct WindowConfigSize {
@@ -88,6 +125,9 @@ struct WindowConfig {
 struct EventConfig {
   #[serde(default = "default_event_max_fps")]
   max_fps: u64,
+  /// How many update events are sent per second. The simulation always steps
+  /// by simulation::TIME_STEP, however many updates that takes, so this and
+  /// ups_reset never change the outcome of a ride.
   #[serde(default = "default_event_ups")]
   ups: u64,
   #[serde(default = "default_event_ups_reset")]
@@ -100,12 +140,107 @@ struct EventConfig {
   lazy: bool,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct TrackConfig {
+  #[serde(default = "default_track_path")]
+  path: String,
+}
+
+impl Default for TrackConfig {
+  fn default() -> TrackConfig {
+    TrackConfig { path: default_track_path() }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum CameraFollowConfig {
+  Locked,
+  Free,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CameraDeadZoneConfig {
+  #[serde(default = "default_camera_dead_zone_width")]
+  width: f64,
+  #[serde(default = "default_camera_dead_zone_height")]
+  height: f64,
+}
+
+impl Default for CameraDeadZoneConfig {
+  fn default() -> CameraDeadZoneConfig {
+    CameraDeadZoneConfig {
+      width: default_camera_dead_zone_width(),
+      height: default_camera_dead_zone_height(),
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CameraConfig {
+  #[serde(default = "default_camera_follow")]
+  follow: CameraFollowConfig,
+  #[serde(default = "default_camera_lead")]
+  lead: f64,
+  #[serde(default = "default_camera_smoothing")]
+  smoothing: f64,
+  #[serde(default)]
+  dead_zone: CameraDeadZoneConfig,
+}
+
+impl Default for CameraConfig {
+  fn default() -> CameraConfig {
+    CameraConfig {
+      follow: default_camera_follow(),
+      lead: default_camera_lead(),
+      smoothing: default_camera_smoothing(),
+      dead_zone: CameraDeadZoneConfig::default(),
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SnapConfig {
+  #[serde(default = "default_snap_endpoint_radius")]
+  endpoint_radius: f64,
+  #[serde(default = "default_snap_grid_size")]
+  grid_size: f64,
+  #[serde(default = "default_snap_grid")]
+  grid: bool,
+  #[serde(default = "default_snap_angle_steps")]
+  angle_steps: Vec<f64>,
+}
+
+impl Default for SnapConfig {
+  fn default() -> SnapConfig {
+    SnapConfig {
+      endpoint_radius: default_snap_endpoint_radius(),
+      grid_size: default_snap_grid_size(),
+      grid: default_snap_grid(),
+      angle_steps: default_snap_angle_steps(),
+    }
+  }
+}
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Config {
   #[serde(default)]
   window: WindowConfig,
   #[serde(default)]
   event: EventConfig,
+  #[serde(default)]
+  track: TrackConfig,
+  #[serde(default)]
+  camera: CameraConfig,
+  #[serde(default)]
+  snap: SnapConfig,
+  /// Maps action names to the keys and mouse buttons bound to them. Actions
+  /// not named keep their default bindings.
+  #[serde(default)]
+  keybindings: BTreeMap<String, keybindings::BindingNames>,
+  /// The keybindings section, checked and parsed.
+  #[serde(skip)]
+  parsed_keybindings: keybindings::Keybindings,
 }
 
 impl Config {
@@ -116,15 +251,48 @@ impl Config {
   pub fn from_path(path: &std::path::Path) -> error::Result<Config> {
     let file = std::fs::File::open(path)?;
     let config = serde_json::from_reader(file)?;
-    Ok(config)
+    Self::with_keybindings(config)
   }
 
   pub fn from_json_str(json_str: &str) -> error::Result<Config> {
     let config = serde_json::from_str(json_str)?;
+    Self::with_keybindings(config)
+  }
+
+  /// Parse the keybindings section of a freshly loaded config, reporting any
+  /// bindings that conflict.
+  fn with_keybindings(mut config: Config) -> error::Result<Config> {
+    use error::ResultExt; // chain_err
+
+    config.parsed_keybindings =
+      keybindings::Keybindings::from_names(&config.keybindings)
+        .chain_err(|| "Invalid keybindings")?;
     Ok(config)
   }
+
+  /// Which keys and mouse buttons trigger which actions.
+  pub fn keybindings(&self) -> &keybindings::Keybindings {
+    &self.parsed_keybindings
+  }
+
+  /// The width and height of the window, in pixels.
+  pub fn window_size(&self) -> [u32; 2] {
+    [self.window.size.width, self.window.size.height]
+  }
+
+  /// Change the width and height of windows built from this config.
+  pub fn set_window_size(&mut self, size: [u32; 2]) {
+    self.window.size.width = size[0];
+    self.window.size.height = size[1];
+  }
+
+  /// The track file that is saved to and opened from when no other is named.
+  pub fn track_path(&self) -> &std::path::Path {
+    std::path::Path::new(&self.track.path)
+  }
 }
 
+#[cfg(feature = "window")]
 impl<'config> From<&'config Config> for piston_window::WindowSettings {
   fn from(config: &'config Config) -> piston_window::WindowSettings {
     let window_settings = piston_window::WindowSettings::new(
@@ -147,6 +315,7 @@ impl<'config> From<&'config Config> for piston_window::WindowSettings {
   }
 }
 
+#[cfg(feature = "window")]
 impl<'config> From<&'config Config> for piston_window::EventSettings {
   fn from(config: &'config Config) -> piston_window::EventSettings {
     piston_window::EventSettings {
@@ -160,6 +329,35 @@ impl<'config> From<&'config Config> for piston_window::EventSettings {
   }
 }
 
+impl<'config> From<&'config Config> for camera::FollowSettings {
+  fn from(config: &'config Config) -> camera::FollowSettings {
+    camera::FollowSettings {
+      mode: match config.camera.follow {
+        CameraFollowConfig::Locked => camera::FollowMode::Locked,
+        CameraFollowConfig::Free => camera::FollowMode::Free,
+      },
+      lead: config.camera.lead,
+      smoothing: config.camera.smoothing,
+      dead_zone: track::Vector::new(
+        config.camera.dead_zone.width,
+        config.camera.dead_zone.height,
+      ),
+    }
+  }
+}
+
+impl<'config> From<&'config Config> for snap::SnapSettings {
+  fn from(config: &'config Config) -> snap::SnapSettings {
+    snap::SnapSettings {
+      endpoint_radius: config.snap.endpoint_radius,
+      grid_size: config.snap.grid_size,
+      grid: config.snap.grid,
+      angle_steps: config.snap.angle_steps.clone(),
+    }
+  }
+}
+
+#[cfg(feature = "window")]
 impl<'config> std::convert::TryFrom<&'config Config>
   for piston_window::PistonWindow {
   type Error = error::Error;
@@ -1,6 +1,7 @@
 extern crate error_chain;
 extern crate serde_json;
 extern crate std;
+extern crate xml;
 
 // Create the Error, ErrorKind, ResultExt, and Result types
 error_chain!{
@@ -8,5 +9,30 @@ error_chain!{
     SerdeJson(serde_json::error::Error);
     StdFmt(std::fmt::Error);
     StdIo(std::io::Error) #[cfg(unix)];
+    XmlReader(xml::reader::Error);
+    XmlWriter(xml::writer::Error);
   }
+
+  errors {
+    /// A track from another game uses features that Rust Rider cannot
+    /// represent, so it would not ride the way it was built to.
+    UnsupportedFeatures(format: String, features: Vec<String>) {
+      description("track uses unsupported features")
+      display(
+        "{} track uses features Rust Rider does not support: {}",
+        format,
+        features.join(", ")
+      )
+    }
+  }
+}
+
+/// Write an error and its causes to stderr. Used for failures that should be
+/// brought to the player's attention without ending the game.
+pub fn report(error: &Error) {
+  use std::io::Write; // writeln
+  use error_chain::ChainedError; // display_chain
+
+  writeln!(std::io::stderr(), "{}", error.display_chain())
+    .expect("Error writing to stderr");
 }
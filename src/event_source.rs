@@ -0,0 +1,48 @@
+extern crate piston_window;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use error;
+
+/// Where an Application gets the events it passes to the active application
+/// mode.
+pub trait EventSource {
+  /// The next event, or None once there are no more.
+  fn next_event(&mut self) -> error::Result<Option<piston_window::Event>>;
+
+  /// Check the active application mode's simulation tick just before it
+  /// handles the last event from next_event. Sources that know which tick
+  /// each event belongs on report any other tick as an error.
+  fn check_tick(&mut self, _tick: Option<u64>) -> error::Result<()> {
+    Ok(())
+  }
+}
+
+/// An EventSource that takes events from a window as they happen.
+pub struct WindowEventSource<Window>
+where
+  Window: piston_window::Window,
+{
+  window: Rc<RefCell<piston_window::PistonWindow<Window>>>,
+}
+
+impl<Window> WindowEventSource<Window>
+where
+  Window: piston_window::Window,
+{
+  pub fn new(
+    window: Rc<RefCell<piston_window::PistonWindow<Window>>>,
+  ) -> WindowEventSource<Window> {
+    WindowEventSource { window: window }
+  }
+}
+
+impl<Window> EventSource for WindowEventSource<Window>
+where
+  Window: piston_window::Window,
+{
+  fn next_event(&mut self) -> error::Result<Option<piston_window::Event>> {
+    Ok(self.window.borrow_mut().next())
+  }
+}
@@ -0,0 +1,315 @@
+extern crate gif;
+extern crate image;
+extern crate std;
+
+use camera;
+use error;
+use scene;
+use simulation;
+use software_renderer::SoftwareRenderer;
+use spatial_index;
+use track;
+
+/// The most frames an export may have: over four and a half hours at 60 frames
+/// per second.
+const MAX_FRAME_COUNT: u64 = 1_000_000;
+
+/// The most pixels a frame may have: 16384 by 16384, a gigabyte of RGBA.
+const MAX_FRAME_PIXELS: u64 = 1 << 28;
+
+/// The fewest digits PNG frames are numbered with.
+const MIN_FRAME_DIGITS: usize = 5;
+
+/// The highest frame rate a GIF can show, as its frame delays are whole
+/// hundredths of a second.
+const MAX_GIF_FPS: f64 = 100.0;
+
+/// How a ride is exported.
+#[derive(Clone, Copy, Debug)]
+pub struct ExportSettings {
+  /// Frames per second of simulated time.
+  pub fps: f64,
+  /// How many seconds of the ride to export.
+  pub duration: f64,
+  /// The size of each frame, in pixels.
+  pub width: u32,
+  pub height: u32,
+}
+
+impl Default for ExportSettings {
+  fn default() -> ExportSettings {
+    ExportSettings {
+      fps: 60.0,
+      duration: 30.0,
+      width: 800,
+      height: 600,
+    }
+  }
+}
+
+/// Whether a ride exported to `out` is written as an animated GIF, rather than
+/// as a directory of PNG frames.
+fn is_gif(out: &std::path::Path) -> bool {
+  match out.extension().and_then(|extension| extension.to_str()) {
+    Some(extension) => extension.eq_ignore_ascii_case("gif"),
+    None => false,
+  }
+}
+
+/// The number of frames exported with `settings`.
+fn frame_count(settings: &ExportSettings) -> f64 {
+  (settings.duration * settings.fps).ceil()
+}
+
+/// The file name of PNG frame `frame` of `frame_count`. Every frame is
+/// numbered with as many digits as the last, so that the names sort in the
+/// order of the frames.
+fn png_frame_name(frame: u64, frame_count: u64) -> String {
+  let digits = frame_count.saturating_sub(1).to_string().len();
+  format!("frame_{:01$}.png", frame, digits.max(MIN_FRAME_DIGITS))
+}
+
+/// The delay after GIF frame `frame`, in hundredths of a second. Rather than
+/// rounding every frame's length the same way, each frame's end time is
+/// rounded, so that the frames' delays add up to the ride's duration.
+fn gif_delay(fps: f64, frame: u64) -> u16 {
+  let end_time = |frame: u64| (frame as f64 * 100.0 / fps).round();
+  (end_time(frame + 1) - end_time(frame)).min(u16::max_value() as f64) as u16
+}
+
+/// Ride `track` without a window, drawing each frame offscreen as PlayMode
+/// would, starting from `camera`, and passing it to `write_frame` along with
+/// its number.
+fn render_frames<F>(
+  track: &track::Track,
+  camera: &camera::Camera,
+  follow_settings: &camera::FollowSettings,
+  settings: &ExportSettings,
+  mut write_frame: F,
+) -> error::Result<()>
+where
+  F: FnMut(u64, &SoftwareRenderer) -> error::Result<()>,
+{
+  let screen_size =
+    track::Vector::new(settings.width as f64, settings.height as f64);
+  let frame_time = 1.0 / settings.fps;
+  let frame_count = frame_count(settings) as u64;
+
+  let spatial_index =
+    spatial_index::SpatialIndex::from_line_segments(&track.line_segments);
+  let mut simulation = simulation::Simulation::new(track);
+  let mut camera = *camera;
+
+  for frame in 0..frame_count {
+    if frame > 0 {
+      // Step to each frame's time directly, rather than through
+      // Simulation::update, so that a low frame rate never drops steps.
+      let tick =
+        (frame as f64 * frame_time / simulation::TIME_STEP).round() as u64;
+      while simulation.tick() < tick {
+        simulation.step();
+      }
+      scene::follow_rider(
+        &mut camera,
+        follow_settings,
+        &simulation,
+        &screen_size,
+        frame_time,
+      );
+    }
+
+    let mut renderer = SoftwareRenderer::new(settings.width, settings.height);
+    scene::draw_ride(
+      &mut renderer,
+      track,
+      &spatial_index,
+      Some(&simulation),
+      &camera,
+      &screen_size,
+    );
+    write_frame(frame, &renderer)?;
+  }
+
+  Ok(())
+}
+
+/// Write numbered PNG frames into the directory `out`, creating it if needed.
+fn export_png_frames(
+  track: &track::Track,
+  camera: &camera::Camera,
+  follow_settings: &camera::FollowSettings,
+  settings: &ExportSettings,
+  out: &std::path::Path,
+) -> error::Result<()> {
+  use error::ResultExt; // chain_err
+
+  std::fs::create_dir_all(out).chain_err(|| {
+    format!("Failed to create {}", out.display())
+  })?;
+
+  let frame_count = frame_count(settings) as u64;
+  render_frames(track, camera, follow_settings, settings, |frame, renderer| {
+    let path = out.join(png_frame_name(frame, frame_count));
+    let file = std::fs::File::create(&path).chain_err(|| {
+      format!("Failed to create {}", path.display())
+    })?;
+    image::png::PNGEncoder::new(std::io::BufWriter::new(file))
+      .encode(
+        renderer.pixels(),
+        renderer.width(),
+        renderer.height(),
+        image::ColorType::RGBA(8),
+      )
+      .chain_err(|| format!("Failed to write {}", path.display()))
+  })
+}
+
+/// Write a looping animated GIF to the file `out`.
+fn export_gif(
+  track: &track::Track,
+  camera: &camera::Camera,
+  follow_settings: &camera::FollowSettings,
+  settings: &ExportSettings,
+  out: &std::path::Path,
+) -> error::Result<()> {
+  use error::ResultExt; // chain_err
+  use self::gif::SetParameter; // set
+
+  if settings.fps > MAX_GIF_FPS {
+    return Err(error::Error::from(format!(
+      "A GIF may have at most {} frames per second",
+      MAX_GIF_FPS
+    )));
+  }
+  if settings.width > u16::max_value() as u32 ||
+    settings.height > u16::max_value() as u32
+  {
+    return Err(error::Error::from(format!(
+      "A GIF may be at most {} pixels wide and high",
+      u16::max_value()
+    )));
+  }
+  let (width, height) = (settings.width as u16, settings.height as u16);
+
+  let file = std::fs::File::create(out).chain_err(|| {
+    format!("Failed to create {}", out.display())
+  })?;
+  let mut encoder =
+    gif::Encoder::new(std::io::BufWriter::new(file), width, height, &[])
+      .chain_err(|| format!("Failed to write {}", out.display()))?;
+  encoder.set(gif::Repeat::Infinite).chain_err(|| {
+    format!("Failed to write {}", out.display())
+  })?;
+
+  render_frames(track, camera, follow_settings, settings, |number, renderer| {
+    let delay = gif_delay(settings.fps, number);
+    let mut pixels = renderer.pixels().to_vec();
+    let mut frame = gif::Frame::from_rgba(width, height, &mut pixels);
+    frame.delay = delay;
+    encoder.write_frame(&frame).chain_err(|| {
+      format!("Failed to write {}", out.display())
+    })
+  })
+}
+
+/// Ride `track` from its start, following the rider with the camera as live
+/// playback does, and write what is seen to `out`. Playback starts from the
+/// editor's camera; `camera` stands in for it. A path ending in .gif gets an
+/// animated GIF; any other path is a directory of numbered PNG frames.
+pub fn export(
+  track: &track::Track,
+  camera: &camera::Camera,
+  follow_settings: &camera::FollowSettings,
+  settings: &ExportSettings,
+  out: &std::path::Path,
+) -> error::Result<()> {
+  if !settings.fps.is_finite() || settings.fps <= 0.0 {
+    return Err(error::Error::from("The frame rate must be positive"));
+  }
+  if !settings.duration.is_finite() || settings.duration < 0.0 {
+    return Err(error::Error::from("The duration must not be negative"));
+  }
+  if frame_count(settings) > MAX_FRAME_COUNT as f64 {
+    return Err(error::Error::from(format!(
+      "An export may have at most {} frames",
+      MAX_FRAME_COUNT
+    )));
+  }
+  if settings.width == 0 || settings.height == 0 {
+    return Err(error::Error::from("Frames must be at least one pixel"));
+  }
+  if settings.width as u64 * settings.height as u64 > MAX_FRAME_PIXELS {
+    return Err(error::Error::from(format!(
+      "A frame may have at most {} pixels",
+      MAX_FRAME_PIXELS
+    )));
+  }
+
+  if is_gif(out) {
+    export_gif(track, camera, follow_settings, settings, out)
+  } else {
+    export_png_frames(track, camera, follow_settings, settings, out)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// The delays of the first `count` frames of a GIF at `fps`.
+  fn gif_delays(fps: f64, count: u64) -> Vec<u16> {
+    (0..count).map(|frame| gif_delay(fps, frame)).collect()
+  }
+
+  #[test]
+  fn gif_delays_add_up_to_the_duration() {
+    assert_eq!(gif_delays(60.0, 6), vec![2, 1, 2, 2, 1, 2]);
+    let total: u64 =
+      gif_delays(60.0, 600).into_iter().map(|delay| delay as u64).sum();
+    assert_eq!(total, 1000);
+    assert_eq!(gif_delays(25.0, 3), vec![4, 4, 4]);
+    assert_eq!(gif_delays(MAX_GIF_FPS, 3), vec![1, 1, 1]);
+  }
+
+  #[test]
+  fn png_frame_names_sort_in_frame_order() {
+    assert_eq!(png_frame_name(0, 60), "frame_00000.png");
+    assert_eq!(png_frame_name(99_999, 100_000), "frame_99999.png");
+    assert_eq!(png_frame_name(0, MAX_FRAME_COUNT), "frame_000000.png");
+    assert!(
+      png_frame_name(99_999, MAX_FRAME_COUNT) <
+        png_frame_name(100_000, MAX_FRAME_COUNT)
+    );
+  }
+
+  #[test]
+  fn settings_that_are_not_finite_are_rejected() {
+    let track = track::Track::new();
+    let follow_settings = camera::FollowSettings {
+      mode: camera::FollowMode::Locked,
+      lead: 0.0,
+      smoothing: 0.0,
+      dead_zone: track::Vector::new(0.0, 0.0),
+    };
+    let out = std::path::Path::new("unused.gif");
+    let infinite = std::f64::INFINITY;
+    for settings in [
+      ExportSettings { fps: infinite, ..ExportSettings::default() },
+      ExportSettings { fps: std::f64::NAN, ..ExportSettings::default() },
+      ExportSettings { duration: infinite, ..ExportSettings::default() },
+      ExportSettings { duration: 1.0e12, ..ExportSettings::default() },
+      ExportSettings { fps: 120.0, ..ExportSettings::default() },
+      ExportSettings {
+        width: u32::max_value(),
+        height: u32::max_value(),
+        ..ExportSettings::default()
+      },
+    ].iter()
+    {
+      assert!(
+        export(&track, &camera::Camera::new(), &follow_settings, settings, out)
+          .is_err()
+      );
+    }
+  }
+}
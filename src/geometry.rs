@@ -0,0 +1,435 @@
+extern crate nalgebra;
+
+use track::Point;
+
+/// The point on the segment from `point1` to `point2` nearest to `point`.
+pub fn closest_point_on_segment(
+  point: &Point,
+  point1: &Point,
+  point2: &Point,
+) -> Point {
+  let tangent = point2 - point1;
+  let length_squared = tangent.norm_squared();
+  if length_squared == 0.0 {
+    return *point1;
+  }
+
+  let t = (point - point1).dot(&tangent) / length_squared;
+  point1 + tangent * t.max(0.0).min(1.0)
+}
+
+/// The distance from `point` to the segment from `point1` to `point2`.
+pub fn distance_to_segment(
+  point: &Point,
+  point1: &Point,
+  point2: &Point,
+) -> f64 {
+  nalgebra::distance(point, &closest_point_on_segment(point, point1, point2))
+}
+
+/// Whether the segment from `a1` to `a2` crosses or touches the segment from
+/// `b1` to `b2`.
+pub fn segments_intersect(
+  a1: &Point,
+  a2: &Point,
+  b1: &Point,
+  b2: &Point,
+) -> bool {
+  // The signed area of the triangle p, q, r: positive if r is to the left of
+  // the line from p to q, negative if to the right, and zero if on it.
+  let orientation = |p: &Point, q: &Point, r: &Point| {
+    (q.x - p.x) * (r.y - p.y) - (q.y - p.y) * (r.x - p.x)
+  };
+  // Whether r, known to be on the line through p and q, is between them.
+  let within = |p: &Point, q: &Point, r: &Point| {
+    p.x.min(q.x) <= r.x && r.x <= p.x.max(q.x) && p.y.min(q.y) <= r.y &&
+      r.y <= p.y.max(q.y)
+  };
+
+  let d1 = orientation(b1, b2, a1);
+  let d2 = orientation(b1, b2, a2);
+  let d3 = orientation(a1, a2, b1);
+  let d4 = orientation(a1, a2, b2);
+  if ((d1 > 0.0 && d2 < 0.0) || (d1 < 0.0 && d2 > 0.0)) &&
+    ((d3 > 0.0 && d4 < 0.0) || (d3 < 0.0 && d4 > 0.0))
+  {
+    return true;
+  }
+
+  (d1 == 0.0 && within(b1, b2, a1)) || (d2 == 0.0 && within(b1, b2, a2)) ||
+    (d3 == 0.0 && within(a1, a2, b1)) || (d4 == 0.0 && within(a1, a2, b2))
+}
+
+/// The shortest distance between the segment from `a1` to `a2` and the
+/// segment from `b1` to `b2`.
+pub fn distance_between_segments(
+  a1: &Point,
+  a2: &Point,
+  b1: &Point,
+  b2: &Point,
+) -> f64 {
+  if segments_intersect(a1, a2, b1, b2) {
+    return 0.0;
+  }
+
+  // Segments that do not cross are closest at an endpoint of one of them.
+  distance_to_segment(a1, b1, b2)
+    .min(distance_to_segment(a2, b1, b2))
+    .min(distance_to_segment(b1, a1, a2))
+    .min(distance_to_segment(b2, a1, a2))
+}
+
+/// The center and radius of the circle through `a`, `b` and `c`, or None if
+/// they are on one line.
+pub fn circle_through(a: &Point, b: &Point, c: &Point) -> Option<(Point, f64)> {
+  // Work relative to `a` to keep the numbers small.
+  let ab = b - a;
+  let ac = c - a;
+  let denominator = 2.0 * (ab.x * ac.y - ab.y * ac.x);
+  if denominator.abs() <= 1e-9 * (ab.norm_squared() + ac.norm_squared()) {
+    return None;
+  }
+
+  let center = Point::new(
+    a.x +
+      (ac.y * ab.norm_squared() - ab.y * ac.norm_squared()) / denominator,
+    a.y +
+      (ab.x * ac.norm_squared() - ac.x * ab.norm_squared()) / denominator,
+  );
+  Some((center, nalgebra::distance(&center, a)))
+}
+
+/// Simplify the polyline through `points` with the Ramer-Douglas-Peucker
+/// algorithm: drop every point that the remaining polyline passes within
+/// `tolerance` of. The first and last points are always kept.
+pub fn simplify_polyline(points: &[Point], tolerance: f64) -> Vec<Point> {
+  if points.len() < 3 {
+    return points.to_vec();
+  }
+
+  let mut keep = vec![false; points.len()];
+  keep[0] = true;
+  keep[points.len() - 1] = true;
+  // Ranges of points whose ends are kept, and whose middles are undecided.
+  let mut ranges = vec![(0, points.len() - 1)];
+  while let Some((first, last)) = ranges.pop() {
+    let mut farthest = first;
+    let mut farthest_distance = 0.0;
+    for index in first + 1..last {
+      let distance =
+        distance_to_segment(&points[index], &points[first], &points[last]);
+      if distance > farthest_distance {
+        farthest = index;
+        farthest_distance = distance;
+      }
+    }
+
+    // Keep the point that strays furthest, and look again on either side.
+    if farthest_distance > tolerance {
+      keep[farthest] = true;
+      ranges.push((first, farthest));
+      ranges.push((farthest, last));
+    }
+  }
+
+  points
+    .iter()
+    .zip(keep.iter())
+    .filter(|&(_, &keep)| keep)
+    .map(|(point, _)| *point)
+    .collect()
+}
+
+/// How many times a curve may be split in half while flattening it.
+const MAX_FLATTENING_DEPTH: u32 = 16;
+
+/// Append points along the cubic Bézier curve from `point0` to `point3`, with
+/// control points `point1` and `point2`, to `points`. No part of the curve is
+/// further than `tolerance` from the polyline through them. `point0` itself is
+/// not appended, so that curves can be chained.
+pub fn flatten_cubic(
+  point0: &Point,
+  point1: &Point,
+  point2: &Point,
+  point3: &Point,
+  tolerance: f64,
+  points: &mut Vec<Point>,
+) {
+  flatten_cubic_part(point0, point1, point2, point3, tolerance, 0, points);
+}
+
+/// Append points along the quadratic Bézier curve from `point0` to `point2`,
+/// with control point `point1`, to `points`, as `flatten_cubic` does.
+pub fn flatten_quadratic(
+  point0: &Point,
+  point1: &Point,
+  point2: &Point,
+  tolerance: f64,
+  points: &mut Vec<Point>,
+) {
+  // Every quadratic curve is also a cubic curve.
+  let control1 = point0 + (point1 - point0) * (2.0 / 3.0);
+  let control2 = point2 + (point1 - point2) * (2.0 / 3.0);
+  flatten_cubic(point0, &control1, &control2, point2, tolerance, points);
+}
+
+fn flatten_cubic_part(
+  point0: &Point,
+  point1: &Point,
+  point2: &Point,
+  point3: &Point,
+  tolerance: f64,
+  depth: u32,
+  points: &mut Vec<Point>,
+) {
+  // The curve lies within the hull of its control points, so it is flat
+  // enough once they are all close to the line between its ends.
+  if depth >= MAX_FLATTENING_DEPTH ||
+    (distance_to_segment(point1, point0, point3) <= tolerance &&
+      distance_to_segment(point2, point0, point3) <= tolerance)
+  {
+    points.push(*point3);
+    return;
+  }
+
+  // Split the curve in half with de Casteljau's algorithm.
+  let middle = |p: &Point, q: &Point| {
+    Point::new((p.x + q.x) / 2.0, (p.y + q.y) / 2.0)
+  };
+  let point01 = middle(point0, point1);
+  let point12 = middle(point1, point2);
+  let point23 = middle(point2, point3);
+  let point012 = middle(&point01, &point12);
+  let point123 = middle(&point12, &point23);
+  let halfway = middle(&point012, &point123);
+  flatten_cubic_part(
+    point0,
+    &point01,
+    &point012,
+    &halfway,
+    tolerance,
+    depth + 1,
+    points,
+  );
+  flatten_cubic_part(
+    &halfway,
+    &point123,
+    &point23,
+    point3,
+    tolerance,
+    depth + 1,
+    points,
+  );
+}
+
+/// An axis-aligned bounding box.
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+  pub min: Point,
+  pub max: Point,
+}
+
+impl Aabb {
+  /// Create the smallest Aabb containing two opposite corners.
+  pub fn from_corners(corner1: &Point, corner2: &Point) -> Aabb {
+    Aabb {
+      min: Point::new(corner1.x.min(corner2.x), corner1.y.min(corner2.y)),
+      max: Point::new(corner1.x.max(corner2.x), corner1.y.max(corner2.y)),
+    }
+  }
+
+  /// Grow to contain `point`.
+  pub fn include(&mut self, point: &Point) {
+    self.min = Point::new(self.min.x.min(point.x), self.min.y.min(point.y));
+    self.max = Point::new(self.max.x.max(point.x), self.max.y.max(point.y));
+  }
+
+  /// A copy grown by `margin` on every side.
+  pub fn expanded(&self, margin: f64) -> Aabb {
+    Aabb {
+      min: Point::new(self.min.x - margin, self.min.y - margin),
+      max: Point::new(self.max.x + margin, self.max.y + margin),
+    }
+  }
+
+  /// Whether this Aabb and `other` overlap or touch.
+  pub fn intersects(&self, other: &Aabb) -> bool {
+    self.min.x <= other.max.x && other.min.x <= self.max.x &&
+      self.min.y <= other.max.y && other.min.y <= self.max.y
+  }
+
+  /// Whether the segment from `point1` to `point2` passes through this Aabb.
+  pub fn intersects_segment(&self, point1: &Point, point2: &Point) -> bool {
+    if self.contains(point1) || self.contains(point2) {
+      return true;
+    }
+    if !self.intersects(&Aabb::from_corners(point1, point2)) {
+      return false;
+    }
+
+    // A segment that enters the box without ending inside it crosses an edge.
+    let corners = [
+      self.min,
+      Point::new(self.max.x, self.min.y),
+      self.max,
+      Point::new(self.min.x, self.max.y),
+    ];
+    (0..4).any(|index| {
+      segments_intersect(
+        point1,
+        point2,
+        &corners[index],
+        &corners[(index + 1) % 4],
+      )
+    })
+  }
+
+  pub fn contains(&self, point: &Point) -> bool {
+    self.min.x <= point.x && point.x <= self.max.x && self.min.y <= point.y &&
+      point.y <= self.max.y
+  }
+
+  pub fn width(&self) -> f64 {
+    self.max.x - self.min.x
+  }
+
+  pub fn height(&self) -> f64 {
+    self.max.y - self.min.y
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn point(x: f64, y: f64) -> Point {
+    Point::new(x, y)
+  }
+
+  #[test]
+  fn crossing_segments_intersect() {
+    assert!(segments_intersect(
+      &point(0.0, 0.0),
+      &point(10.0, 10.0),
+      &point(0.0, 10.0),
+      &point(10.0, 0.0),
+    ));
+  }
+
+  #[test]
+  fn touching_segments_intersect() {
+    // At an endpoint of one segment.
+    assert!(segments_intersect(
+      &point(0.0, 0.0),
+      &point(10.0, 0.0),
+      &point(5.0, 0.0),
+      &point(5.0, 10.0),
+    ));
+    // Overlapping along one line.
+    assert!(segments_intersect(
+      &point(0.0, 0.0),
+      &point(10.0, 0.0),
+      &point(5.0, 0.0),
+      &point(15.0, 0.0),
+    ));
+  }
+
+  #[test]
+  fn separate_segments_do_not_intersect() {
+    // Parallel.
+    assert!(!segments_intersect(
+      &point(0.0, 0.0),
+      &point(10.0, 0.0),
+      &point(0.0, 1.0),
+      &point(10.0, 1.0),
+    ));
+    // On one line, but apart.
+    assert!(!segments_intersect(
+      &point(0.0, 0.0),
+      &point(10.0, 0.0),
+      &point(11.0, 0.0),
+      &point(20.0, 0.0),
+    ));
+    // Where the lines through them would cross, but beyond one's end.
+    assert!(!segments_intersect(
+      &point(0.0, 0.0),
+      &point(10.0, 0.0),
+      &point(5.0, 1.0),
+      &point(5.0, 10.0),
+    ));
+  }
+
+  #[test]
+  fn distance_between_segments_is_zero_only_when_they_intersect() {
+    let distance = distance_between_segments(
+      &point(0.0, 0.0),
+      &point(10.0, 0.0),
+      &point(5.0, 3.0),
+      &point(5.0, 10.0),
+    );
+    assert_eq!(distance, 3.0);
+    assert_eq!(
+      distance_between_segments(
+        &point(0.0, 0.0),
+        &point(10.0, 10.0),
+        &point(0.0, 10.0),
+        &point(10.0, 0.0),
+      ),
+      0.0
+    );
+  }
+
+  #[test]
+  fn simplifying_drops_points_within_tolerance() {
+    let points = vec![
+      point(0.0, 0.0),
+      point(5.0, 0.5),
+      point(10.0, 0.0),
+      point(10.5, 5.0),
+      point(10.0, 10.0),
+    ];
+    assert_eq!(
+      simplify_polyline(&points, 1.0),
+      vec![point(0.0, 0.0), point(10.0, 0.0), point(10.0, 10.0)]
+    );
+    assert_eq!(simplify_polyline(&points, 0.1), points);
+  }
+
+  #[test]
+  fn simplifying_keeps_the_ends() {
+    let points = vec![point(0.0, 0.0), point(5.0, 0.0), point(10.0, 0.0)];
+    assert_eq!(
+      simplify_polyline(&points, 1.0),
+      vec![point(0.0, 0.0), point(10.0, 0.0)]
+    );
+    assert_eq!(simplify_polyline(&points[..1], 1.0), vec![point(0.0, 0.0)]);
+    assert_eq!(simplify_polyline(&[], 1.0), vec![]);
+  }
+
+  #[test]
+  fn circle_through_three_points() {
+    let (center, radius) =
+      circle_through(&point(10.0, 0.0), &point(0.0, 10.0), &point(-10.0, 0.0))
+        .unwrap();
+    assert!(nalgebra::distance(&center, &point(0.0, 0.0)) < 1e-9);
+    assert!((radius - 10.0).abs() < 1e-9);
+
+    let (center, radius) =
+      circle_through(&point(105.0, 1.0), &point(103.0, 5.0), &point(96.0, 4.0))
+        .unwrap();
+    assert!(nalgebra::distance(&center, &point(100.0, 1.0)) < 1e-9);
+    assert!((radius - 5.0).abs() < 1e-9);
+  }
+
+  #[test]
+  fn no_circle_through_points_on_one_line() {
+    assert_eq!(
+      circle_through(&point(0.0, 0.0), &point(1.0, 1.0), &point(5.0, 5.0)),
+      None
+    );
+    assert_eq!(
+      circle_through(&point(3.0, 4.0), &point(3.0, 4.0), &point(3.0, 4.0)),
+      None
+    );
+  }
+}
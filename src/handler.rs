@@ -178,4 +178,29 @@ pub trait EventHandler: InputHandler + UpdateHandler + WindowHandler {
 
     Err(error::Error::from("Failed to discover event type"))
   }
+
+  /// Called when Application makes this handler the active application mode.
+  fn on_enter(&mut self) -> error::Result<()> {
+    Ok(())
+  }
+
+  /// Called when Application replaces this handler as the active application
+  /// mode.
+  fn on_exit(&mut self) -> error::Result<()> {
+    Ok(())
+  }
+
+  /// The name of the application mode this handler would like to hand off
+  /// to, if any. Application polls this after every call to on_event and
+  /// performs the switch, so a handler requests a switch by recording the name
+  /// during on_event. A request is only reported once.
+  fn requested_application_mode(&mut self) -> Option<String> {
+    None
+  }
+
+  /// The number of steps the simulation this handler runs has taken, if it
+  /// runs one. Recorded alongside each event in a replay.
+  fn simulation_tick(&self) -> Option<u64> {
+    None
+  }
 }
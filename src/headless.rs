@@ -0,0 +1,227 @@
+use geometry;
+use simulation;
+use track;
+
+/// How far outside the track the rider must travel to have fallen off it.
+const TRACK_MARGIN: f64 = 200.0;
+
+/// How a headless run is carried out.
+#[derive(Clone, Copy, Debug)]
+pub struct RunSettings {
+  /// The most simulated time to run for, in seconds.
+  pub duration: f64,
+  /// How many steps apart the samples of the rider's trajectory are.
+  pub sample_interval: u64,
+}
+
+impl Default for RunSettings {
+  fn default() -> RunSettings {
+    RunSettings {
+      duration: 60.0,
+      sample_interval: 1,
+    }
+  }
+}
+
+/// The rider's state at one step of a run.
+#[derive(Clone, Debug, Serialize)]
+pub struct Sample {
+  pub tick: u64,
+  pub time: f64,
+  pub position: [f64; 2],
+  pub velocity: [f64; 2],
+  /// Simulation::checksum, in hexadecimal.
+  pub checksum: String,
+}
+
+/// How a headless run ended. A run ends when the rider passes the far end of
+/// the track, falls off it, or runs out of time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+  /// The rider passed the far end of the track without crashing.
+  Completed,
+  /// The rider crashed before the run ended.
+  Crashed,
+  /// The rider left the track some other way than past its far end, such as
+  /// by falling off it, without crashing.
+  FellOff,
+  /// The run's duration was up first, without a crash.
+  TimedOut,
+}
+
+/// The outcome of a headless run.
+#[derive(Clone, Debug, Serialize)]
+pub struct Report {
+  pub time_step: f64,
+  pub ticks: u64,
+  pub outcome: Outcome,
+  pub crashed: bool,
+  pub crash_time: Option<f64>,
+  /// Whether the rider passed the far end of the track, the right-hand end of
+  /// its rightmost solid line, without crashing.
+  pub completed: bool,
+  pub completion_time: Option<f64>,
+  /// Whether the rider left the track some other way than past its far end,
+  /// going further than a margin from its solid lines and start point.
+  pub fell_off: bool,
+  pub fell_off_time: Option<f64>,
+  /// Simulation::checksum at the last tick, in hexadecimal.
+  pub checksum: String,
+  pub trajectory: Vec<Sample>,
+}
+
+fn sample(simulation: &simulation::Simulation) -> Sample {
+  let position = simulation.rider_position();
+  let velocity = simulation.rider_velocity();
+  Sample {
+    tick: simulation.tick(),
+    time: simulation.time(),
+    position: [position.x, position.y],
+    velocity: [velocity.x, velocity.y],
+    checksum: format!("{:016x}", simulation.checksum()),
+  }
+}
+
+/// The region the rider must stay in to be on a track: every solid line
+/// segment and the rider's start point, plus a margin. Scenery is left out, as
+/// the rider passes through it.
+fn track_bounds(track: &track::Track) -> geometry::Aabb {
+  let start = &track.rider_start;
+  let mut bounds = geometry::Aabb::from_corners(start, start);
+  for line_segment in track.line_segments.iter().filter(is_solid) {
+    bounds.include(line_segment.point1());
+    bounds.include(line_segment.point2());
+  }
+
+  bounds.expanded(TRACK_MARGIN)
+}
+
+/// Whether the rider can ride on a line segment.
+fn is_solid(line_segment: &&track::LineSegment) -> bool {
+  line_segment.kind() != track::LineKind::Scenery
+}
+
+/// How far right the rider must go to have finished a track: the right-hand
+/// end of its rightmost solid line. A track without solid lines cannot be
+/// finished.
+fn finish_line(track: &track::Track) -> Option<f64> {
+  track
+    .line_segments
+    .iter()
+    .filter(is_solid)
+    .map(|line_segment| line_segment.point1().x.max(line_segment.point2().x))
+    .fold(None, |finish: Option<f64>, x| {
+      Some(finish.map_or(x, |finish| finish.max(x)))
+    })
+}
+
+/// Ride a track at the simulation's fixed time step, without a window, until
+/// the rider passes the far end of the track, leaves it some other way, or
+/// the run's duration is up.
+pub fn run(track: &track::Track, settings: &RunSettings) -> Report {
+  let mut simulation = simulation::Simulation::new(track);
+  let bounds = track_bounds(track);
+  let finish_line = finish_line(track);
+  let max_ticks = (settings.duration / simulation::TIME_STEP).ceil() as u64;
+  let sample_interval = settings.sample_interval.max(1);
+
+  let mut trajectory = vec![sample(&simulation)];
+  let mut finished = false;
+  let mut fell_off = false;
+  while simulation.tick() < max_ticks {
+    simulation.step();
+    if simulation.tick() % sample_interval == 0 {
+      trajectory.push(sample(&simulation));
+    }
+
+    let position = simulation.rider_position();
+    if finish_line.map_or(false, |finish_line| position.x > finish_line) {
+      finished = true;
+      break;
+    }
+    if !bounds.contains(&position) {
+      fell_off = true;
+      break;
+    }
+  }
+
+  let crash_time = simulation
+    .crash_tick()
+    .map(|tick| tick as f64 * simulation::TIME_STEP);
+  let completed = finished && crash_time.is_none();
+  let end_time = Some(simulation.time());
+  let outcome = if completed {
+    Outcome::Completed
+  } else if crash_time.is_some() {
+    Outcome::Crashed
+  } else if fell_off {
+    Outcome::FellOff
+  } else {
+    Outcome::TimedOut
+  };
+  Report {
+    time_step: simulation::TIME_STEP,
+    ticks: simulation.tick(),
+    outcome: outcome,
+    crashed: crash_time.is_some(),
+    crash_time: crash_time,
+    completed: completed,
+    completion_time: if completed { end_time } else { None },
+    fell_off: fell_off,
+    fell_off_time: if fell_off { end_time } else { None },
+    checksum: format!("{:016x}", simulation.checksum()),
+    trajectory: trajectory,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use track::Point;
+
+  #[test]
+  fn riding_off_the_far_end_completes_the_track() {
+    let track = track::Track::with_line(
+      Point::new(50.0, 110.0),
+      Point::new(400.0, 160.0),
+    );
+    let report = run(&track, &RunSettings::default());
+
+    assert_eq!(report.outcome, Outcome::Completed);
+    assert!(report.completed);
+    assert!(!report.crashed);
+    assert!(!report.fell_off);
+    assert!(report.completion_time.is_some());
+    assert!(report.trajectory.last().unwrap().position[0] > 400.0);
+  }
+
+  #[test]
+  fn falling_off_does_not_complete_the_track() {
+    let report = run(&track::Track::new(), &RunSettings::default());
+
+    assert_eq!(report.outcome, Outcome::FellOff);
+    assert!(!report.completed);
+    assert_eq!(report.completion_time, None);
+    assert!(report.fell_off);
+    assert!(report.fell_off_time.is_some());
+  }
+
+  #[test]
+  fn resting_on_a_flat_line_times_out() {
+    let track = track::Track::with_line(
+      Point::new(0.0, 110.0),
+      Point::new(400.0, 110.0),
+    );
+    let settings = RunSettings {
+      duration: 1.0,
+      sample_interval: 1,
+    };
+    let report = run(&track, &settings);
+
+    assert_eq!(report.outcome, Outcome::TimedOut);
+    assert!(!report.completed && !report.fell_off && !report.crashed);
+    assert_eq!(report.trajectory.len() as u64, report.ticks + 1);
+    assert_eq!(report.trajectory.last().unwrap().checksum, report.checksum);
+  }
+}
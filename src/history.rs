@@ -0,0 +1,354 @@
+use std::collections::VecDeque;
+
+use spatial_index::SpatialIndex;
+use track::LineSegment;
+
+/// How many commands a History remembers by default.
+pub const DEFAULT_CAPACITY: usize = 256;
+
+/// A reversible change to the line segments of a track. Indices always refer
+/// to positions in the track after an insertion or before a removal, and are
+/// listed in ascending order.
+///
+/// Segments are removed as Vec::swap_remove does, from the last index to the
+/// first, with the last segment of the track moving into each one's place.
+/// Insertion undoes that: each segment is inserted in ascending order, and the
+/// segment that was at its index moves to the end. Neither moves any other
+/// segment, so an edit costs the same however long the track is.
+#[derive(Clone, Debug)]
+pub enum Command {
+  /// Insert each segment at its index.
+  Insert(Vec<(usize, LineSegment)>),
+  /// Remove the segment at each index. The removed segments are kept so that
+  /// the removal can be reverted.
+  Remove(Vec<(usize, LineSegment)>),
+  /// Replace the segment at each index, going from the first segment to the
+  /// second.
+  Replace(Vec<(usize, LineSegment, LineSegment)>),
+  /// Apply each command in order, as one change. The indices of each command
+  /// refer to the track as the commands before it left it.
+  Batch(Vec<Command>),
+}
+
+impl Command {
+  /// Make this command's change to `line_segments`, and to `spatial_index`,
+  /// which indexes them.
+  pub fn apply(
+    &self,
+    line_segments: &mut Vec<LineSegment>,
+    spatial_index: &mut SpatialIndex,
+  ) {
+    match *self {
+      Command::Insert(ref insertions) => {
+        for &(index, ref line_segment) in insertions.iter() {
+          line_segments.push(line_segment.clone());
+          let last = line_segments.len() - 1;
+          line_segments.swap(index, last);
+          spatial_index.swap_insert(
+            index,
+            *line_segment.point1(),
+            *line_segment.point2(),
+          );
+        }
+      },
+      Command::Remove(ref removals) => {
+        // Remove from the back, so that the segment moved into each place is
+        // never one still to be removed.
+        for &(index, _) in removals.iter().rev() {
+          line_segments.swap_remove(index);
+          spatial_index.swap_remove(index);
+        }
+      },
+      Command::Replace(ref replacements) => {
+        for &(index, _, ref after) in replacements.iter() {
+          line_segments[index] = after.clone();
+          spatial_index.replace(index, *after.point1(), *after.point2());
+        }
+      },
+      Command::Batch(ref commands) => {
+        for command in commands.iter() {
+          command.apply(line_segments, spatial_index);
+        }
+      },
+    }
+  }
+
+  /// The command that undoes this one.
+  pub fn inverse(&self) -> Command {
+    match *self {
+      Command::Insert(ref insertions) => Command::Remove(insertions.clone()),
+      Command::Remove(ref removals) => Command::Insert(removals.clone()),
+      Command::Replace(ref replacements) => Command::Replace(
+        replacements
+          .iter()
+          .map(|&(index, ref before, ref after)| {
+            (index, after.clone(), before.clone())
+          })
+          .collect(),
+      ),
+      Command::Batch(ref commands) => Command::Batch(
+        commands.iter().rev().map(|command| command.inverse()).collect(),
+      ),
+    }
+  }
+}
+
+/// A bounded record of the commands applied to a track, which can be undone
+/// and redone in order. Once full, the oldest command is forgotten.
+#[derive(Debug)]
+pub struct History {
+  undo_stack: VecDeque<Command>,
+  redo_stack: Vec<Command>,
+  capacity: usize,
+}
+
+impl Default for History {
+  fn default() -> History {
+    History::new(DEFAULT_CAPACITY)
+  }
+}
+
+impl History {
+  /// Create an empty History that remembers up to `capacity` commands.
+  pub fn new(capacity: usize) -> History {
+    History {
+      undo_stack: VecDeque::with_capacity(capacity),
+      redo_stack: Vec::new(),
+      capacity: capacity,
+    }
+  }
+
+  /// Apply a command to `line_segments` and their `spatial_index`, and
+  /// remember it.
+  pub fn execute(
+    &mut self,
+    command: Command,
+    line_segments: &mut Vec<LineSegment>,
+    spatial_index: &mut SpatialIndex,
+  ) {
+    command.apply(line_segments, spatial_index);
+    self.record(command);
+  }
+
+  /// Remember a command whose change has already been made, such as a drag
+  /// that moved segments as the cursor moved. Redoing is no longer possible
+  /// once a new command is recorded.
+  pub fn record(&mut self, command: Command) {
+    if self.capacity == 0 {
+      return;
+    }
+
+    self.redo_stack.clear();
+    if self.undo_stack.len() == self.capacity {
+      self.undo_stack.pop_front();
+    }
+    self.undo_stack.push_back(command);
+  }
+
+  /// Revert the most recent command. Returns false if there was none.
+  pub fn undo(
+    &mut self,
+    line_segments: &mut Vec<LineSegment>,
+    spatial_index: &mut SpatialIndex,
+  ) -> bool {
+    match self.undo_stack.pop_back() {
+      Some(command) => {
+        command.inverse().apply(line_segments, spatial_index);
+        self.redo_stack.push(command);
+        true
+      },
+      None => false,
+    }
+  }
+
+  /// Reapply the most recently undone command. Returns false if there was
+  /// none.
+  pub fn redo(
+    &mut self,
+    line_segments: &mut Vec<LineSegment>,
+    spatial_index: &mut SpatialIndex,
+  ) -> bool {
+    match self.redo_stack.pop() {
+      Some(command) => {
+        command.apply(line_segments, spatial_index);
+        self.undo_stack.push_back(command);
+        true
+      },
+      None => false,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use track::{LineKind, Point};
+
+  /// A short horizontal segment starting at (`x`, `y`).
+  fn segment(x: f64, y: f64) -> LineSegment {
+    LineSegment::new(
+      Point::new(x, y),
+      Point::new(x + 10.0, y),
+      LineKind::Normal,
+    )
+  }
+
+  /// Segments each 100 units to the right of the last, so that each is in
+  /// cells of its own.
+  fn row(count: usize) -> Vec<LineSegment> {
+    (0..count).map(|index| segment(index as f64 * 100.0, 0.0)).collect()
+  }
+
+  /// Check that `spatial_index` finds each of `line_segments` at its index,
+  /// and nothing else.
+  fn assert_in_sync(
+    line_segments: &[LineSegment],
+    spatial_index: &SpatialIndex,
+  ) {
+    assert_eq!(spatial_index.len(), line_segments.len());
+    for (index, line_segment) in line_segments.iter().enumerate() {
+      let middle = Point::new(
+        (line_segment.point1().x + line_segment.point2().x) / 2.0,
+        (line_segment.point1().y + line_segment.point2().y) / 2.0,
+      );
+      assert_eq!(spatial_index.query_point(&middle, 1.0), vec![index]);
+    }
+  }
+
+  /// Apply `command` through `history`, and remember the segments after.
+  fn execute(
+    history: &mut History,
+    command: Command,
+    line_segments: &mut Vec<LineSegment>,
+    spatial_index: &mut SpatialIndex,
+    states: &mut Vec<Vec<LineSegment>>,
+  ) {
+    history.execute(command, line_segments, spatial_index);
+    assert_in_sync(line_segments, spatial_index);
+    states.push(line_segments.clone());
+  }
+
+  #[test]
+  fn insert_moves_the_segment_in_the_way_to_the_end() {
+    let original = row(5);
+    let mut line_segments = original.clone();
+    let mut spatial_index = SpatialIndex::from_line_segments(&line_segments);
+    let inserted = [segment(1000.0, 0.0), segment(1100.0, 0.0)];
+    let command = Command::Insert(vec![
+      (1, inserted[0].clone()),
+      (4, inserted[1].clone()),
+    ]);
+
+    command.apply(&mut line_segments, &mut spatial_index);
+    assert_eq!(
+      line_segments,
+      vec![
+        original[0].clone(),
+        inserted[0].clone(),
+        original[2].clone(),
+        original[3].clone(),
+        inserted[1].clone(),
+        original[1].clone(),
+        original[4].clone(),
+      ]
+    );
+    assert_in_sync(&line_segments, &spatial_index);
+
+    command.inverse().apply(&mut line_segments, &mut spatial_index);
+    assert_eq!(line_segments, original);
+    assert_in_sync(&line_segments, &spatial_index);
+  }
+
+  #[test]
+  fn undo_and_redo_keep_the_index_in_sync() {
+    let original = row(5);
+    let mut line_segments = original.clone();
+    let mut spatial_index = SpatialIndex::from_line_segments(&line_segments);
+    let mut history = History::default();
+    let mut states = vec![line_segments.clone()];
+
+    let insert = Command::Insert(vec![
+      (1, segment(1000.0, 0.0)),
+      (4, segment(1100.0, 0.0)),
+    ]);
+    execute(
+      &mut history,
+      insert,
+      &mut line_segments,
+      &mut spatial_index,
+      &mut states,
+    );
+    let remove = Command::Remove(vec![
+      (0, line_segments[0].clone()),
+      (3, line_segments[3].clone()),
+    ]);
+    execute(
+      &mut history,
+      remove,
+      &mut line_segments,
+      &mut spatial_index,
+      &mut states,
+    );
+    let batch = Command::Batch(vec![
+      Command::Replace(vec![
+        (2, line_segments[2].clone(), segment(0.0, 500.0)),
+      ]),
+      Command::Remove(vec![(0, line_segments[0].clone())]),
+    ]);
+    execute(
+      &mut history,
+      batch,
+      &mut line_segments,
+      &mut spatial_index,
+      &mut states,
+    );
+
+    for state in states.iter().rev().skip(1) {
+      assert!(history.undo(&mut line_segments, &mut spatial_index));
+      assert_eq!(&line_segments, state);
+      assert_in_sync(&line_segments, &spatial_index);
+    }
+    assert!(!history.undo(&mut line_segments, &mut spatial_index));
+    assert_eq!(line_segments, original);
+
+    for state in states.iter().skip(1) {
+      assert!(history.redo(&mut line_segments, &mut spatial_index));
+      assert_eq!(&line_segments, state);
+      assert_in_sync(&line_segments, &spatial_index);
+    }
+    assert!(!history.redo(&mut line_segments, &mut spatial_index));
+  }
+
+  #[test]
+  fn recording_a_command_forgets_undone_commands() {
+    let mut line_segments = row(2);
+    let mut spatial_index = SpatialIndex::from_line_segments(&line_segments);
+    let mut history = History::default();
+
+    let remove = Command::Remove(vec![(1, line_segments[1].clone())]);
+    history.execute(remove, &mut line_segments, &mut spatial_index);
+    assert!(history.undo(&mut line_segments, &mut spatial_index));
+    let remove = Command::Remove(vec![(0, line_segments[0].clone())]);
+    history.execute(remove, &mut line_segments, &mut spatial_index);
+
+    assert!(!history.redo(&mut line_segments, &mut spatial_index));
+    assert_eq!(line_segments, vec![row(2)[1].clone()]);
+  }
+
+  #[test]
+  fn a_full_history_forgets_the_oldest_command() {
+    let mut line_segments = Vec::new();
+    let mut spatial_index = SpatialIndex::from_line_segments(&line_segments);
+    let mut history = History::new(2);
+
+    for index in 0..3 {
+      let insert = Command::Insert(vec![(index, row(3)[index].clone())]);
+      history.execute(insert, &mut line_segments, &mut spatial_index);
+    }
+    assert!(history.undo(&mut line_segments, &mut spatial_index));
+    assert!(history.undo(&mut line_segments, &mut spatial_index));
+    assert!(!history.undo(&mut line_segments, &mut spatial_index));
+    assert_eq!(line_segments, row(1));
+    assert_in_sync(&line_segments, &spatial_index);
+  }
+}
@@ -0,0 +1,612 @@
+extern crate piston;
+extern crate std;
+
+use std::collections::BTreeMap;
+
+use error;
+
+/// Something the player can do with a key or mouse button.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+  /// Draw a line, or while Select is held, select and drag lines.
+  Draw,
+  /// Held to select and drag lines rather than draw them.
+  Select,
+  /// Held to pan the view as the mouse moves.
+  Pan,
+  Undo,
+  Redo,
+  /// Ride the track.
+  Play,
+  Save,
+  Open,
+  /// Delete the selected lines.
+  Delete,
+  NormalLine,
+  AccelerationLine,
+  SceneryLine,
+  /// Draw straight lines from where the mouse is pressed to where it is
+  /// released.
+  LineTool,
+  /// Draw freehand lines that follow the mouse while it is pressed.
+  PencilTool,
+  /// Draw quadratic Bézier curves.
+  QuadraticTool,
+  /// Draw cubic Bézier curves.
+  CubicTool,
+  /// Draw circular arcs.
+  ArcTool,
+  /// Reshape the curve under the cursor.
+  EditCurve,
+  /// Tessellate the curve being drawn into more line segments.
+  MoreSegments,
+  /// Tessellate the curve being drawn into fewer line segments.
+  FewerSegments,
+  /// Turn snapping to the grid on or off.
+  ToggleGridSnap,
+  /// Snap lines to the next of the configured angles, or stop snapping them
+  /// after the last.
+  CycleAngleSnap,
+  /// Stop riding and go back to editing.
+  Stop,
+  Pause,
+  StepForward,
+  StepBack,
+  /// Drag the timeline slider.
+  Scrub,
+}
+
+const ACTIONS: [Action; 27] = [
+  Action::Draw,
+  Action::Select,
+  Action::Pan,
+  Action::Undo,
+  Action::Redo,
+  Action::Play,
+  Action::Save,
+  Action::Open,
+  Action::Delete,
+  Action::NormalLine,
+  Action::AccelerationLine,
+  Action::SceneryLine,
+  Action::LineTool,
+  Action::PencilTool,
+  Action::QuadraticTool,
+  Action::CubicTool,
+  Action::ArcTool,
+  Action::EditCurve,
+  Action::MoreSegments,
+  Action::FewerSegments,
+  Action::ToggleGridSnap,
+  Action::CycleAngleSnap,
+  Action::Stop,
+  Action::Pause,
+  Action::StepForward,
+  Action::StepBack,
+  Action::Scrub,
+];
+
+/// Where an Action is used. A key may be bound to one action while editing
+/// and to another while riding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Context {
+  Edit,
+  Play,
+}
+
+impl Action {
+  /// The name the action is bound by in config.json.
+  pub fn name(&self) -> &'static str {
+    match *self {
+      Action::Draw => "draw",
+      Action::Select => "select",
+      Action::Pan => "pan",
+      Action::Undo => "undo",
+      Action::Redo => "redo",
+      Action::Play => "play",
+      Action::Save => "save",
+      Action::Open => "open",
+      Action::Delete => "delete",
+      Action::NormalLine => "normal_line",
+      Action::AccelerationLine => "acceleration_line",
+      Action::SceneryLine => "scenery_line",
+      Action::LineTool => "line_tool",
+      Action::PencilTool => "pencil_tool",
+      Action::QuadraticTool => "quadratic_tool",
+      Action::CubicTool => "cubic_tool",
+      Action::ArcTool => "arc_tool",
+      Action::EditCurve => "edit_curve",
+      Action::MoreSegments => "more_segments",
+      Action::FewerSegments => "fewer_segments",
+      Action::ToggleGridSnap => "toggle_grid_snap",
+      Action::CycleAngleSnap => "cycle_angle_snap",
+      Action::Stop => "stop",
+      Action::Pause => "pause",
+      Action::StepForward => "step_forward",
+      Action::StepBack => "step_back",
+      Action::Scrub => "scrub",
+    }
+  }
+
+  pub fn context(&self) -> Context {
+    match *self {
+      Action::Stop |
+      Action::Pause |
+      Action::StepForward |
+      Action::StepBack |
+      Action::Scrub => Context::Play,
+      _ => Context::Edit,
+    }
+  }
+
+  /// What the action is bound to unless config.json says otherwise.
+  fn default_bindings(&self) -> &'static [&'static str] {
+    match *self {
+      Action::Draw => &["mouse_left"],
+      Action::Select => &["shift"],
+      Action::Pan => &["mouse_middle"],
+      Action::Undo => &["ctrl+z"],
+      Action::Redo => &["ctrl+shift+z"],
+      Action::Play => &["return"],
+      Action::Save => &["ctrl+s"],
+      Action::Open => &["ctrl+o"],
+      Action::Delete => &["delete", "backspace"],
+      Action::NormalLine => &["1"],
+      Action::AccelerationLine => &["2"],
+      Action::SceneryLine => &["3"],
+      Action::LineTool => &["l"],
+      Action::PencilTool => &["p"],
+      Action::QuadraticTool => &["q"],
+      Action::CubicTool => &["c"],
+      Action::ArcTool => &["a"],
+      Action::EditCurve => &["e"],
+      Action::MoreSegments => &["]"],
+      Action::FewerSegments => &["["],
+      Action::ToggleGridSnap => &["g"],
+      Action::CycleAngleSnap => &["r"],
+      Action::Stop => &["escape"],
+      Action::Pause => &["space"],
+      Action::StepForward => &["right"],
+      Action::StepBack => &["left"],
+      Action::Scrub => &["mouse_left"],
+    }
+  }
+}
+
+/// A modifier, held with the key on either side of the keyboard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Modifier {
+  Ctrl,
+  Shift,
+  Alt,
+}
+
+impl Modifier {
+  fn of_key(key: piston::input::Key) -> Option<Modifier> {
+    match key {
+      piston::input::Key::LCtrl | piston::input::Key::RCtrl => {
+        Some(Modifier::Ctrl)
+      },
+      piston::input::Key::LShift | piston::input::Key::RShift => {
+        Some(Modifier::Shift)
+      },
+      piston::input::Key::LAlt | piston::input::Key::RAlt => {
+        Some(Modifier::Alt)
+      },
+      _ => None,
+    }
+  }
+}
+
+/// Which modifiers are held down.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Modifiers {
+  pub ctrl: bool,
+  pub shift: bool,
+  pub alt: bool,
+}
+
+impl Modifiers {
+  fn set(&mut self, modifier: Modifier, held: bool) {
+    match modifier {
+      Modifier::Ctrl => self.ctrl = held,
+      Modifier::Shift => self.shift = held,
+      Modifier::Alt => self.alt = held,
+    }
+  }
+
+  /// Whether every modifier in `other` is held here too.
+  fn contains(&self, other: &Modifiers) -> bool {
+    (self.ctrl || !other.ctrl) && (self.shift || !other.shift) &&
+      (self.alt || !other.alt)
+  }
+
+  fn count(&self) -> usize {
+    self.ctrl as usize + self.shift as usize + self.alt as usize
+  }
+
+  /// Note that `button` was pressed.
+  pub fn press(&mut self, button: &piston::input::Button) {
+    if let piston::input::Button::Keyboard(key) = *button {
+      if let Some(modifier) = Modifier::of_key(key) {
+        self.set(modifier, true);
+      }
+    }
+  }
+
+  /// Note that `button` was released.
+  pub fn release(&mut self, button: &piston::input::Button) {
+    if let piston::input::Button::Keyboard(key) = *button {
+      if let Some(modifier) = Modifier::of_key(key) {
+        self.set(modifier, false);
+      }
+    }
+  }
+}
+
+/// The key or mouse button that triggers a Binding.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Input {
+  Key(piston::input::Key),
+  Modifier(Modifier),
+  Mouse(piston::input::MouseButton),
+}
+
+impl Input {
+  fn matches(&self, button: &piston::input::Button) -> bool {
+    match (*self, *button) {
+      (Input::Key(key), piston::input::Button::Keyboard(pressed)) => {
+        key == pressed
+      },
+      (Input::Modifier(modifier), piston::input::Button::Keyboard(pressed)) => {
+        Modifier::of_key(pressed) == Some(modifier)
+      },
+      (Input::Mouse(mouse_button), piston::input::Button::Mouse(pressed)) => {
+        mouse_button == pressed
+      },
+      _ => false,
+    }
+  }
+}
+
+/// The key or mouse button called `name`, if there is one. Besides the names
+/// below, any single character names the key that types it on a US layout.
+fn parse_input(name: &str) -> Option<Input> {
+  use piston::input::Key;
+  use piston::input::MouseButton;
+
+  let input = match name {
+    "ctrl" | "control" => Input::Modifier(Modifier::Ctrl),
+    "shift" => Input::Modifier(Modifier::Shift),
+    "alt" => Input::Modifier(Modifier::Alt),
+    "mouse_left" => Input::Mouse(MouseButton::Left),
+    "mouse_right" => Input::Mouse(MouseButton::Right),
+    "mouse_middle" => Input::Mouse(MouseButton::Middle),
+    "mouse_x1" => Input::Mouse(MouseButton::X1),
+    "mouse_x2" => Input::Mouse(MouseButton::X2),
+    "space" => Input::Key(Key::Space),
+    "return" | "enter" => Input::Key(Key::Return),
+    "escape" | "esc" => Input::Key(Key::Escape),
+    "tab" => Input::Key(Key::Tab),
+    "backspace" => Input::Key(Key::Backspace),
+    "delete" => Input::Key(Key::Delete),
+    "insert" => Input::Key(Key::Insert),
+    "home" => Input::Key(Key::Home),
+    "end" => Input::Key(Key::End),
+    "page_up" => Input::Key(Key::PageUp),
+    "page_down" => Input::Key(Key::PageDown),
+    "left" => Input::Key(Key::Left),
+    "right" => Input::Key(Key::Right),
+    "up" => Input::Key(Key::Up),
+    "down" => Input::Key(Key::Down),
+    "f1" => Input::Key(Key::F1),
+    "f2" => Input::Key(Key::F2),
+    "f3" => Input::Key(Key::F3),
+    "f4" => Input::Key(Key::F4),
+    "f5" => Input::Key(Key::F5),
+    "f6" => Input::Key(Key::F6),
+    "f7" => Input::Key(Key::F7),
+    "f8" => Input::Key(Key::F8),
+    "f9" => Input::Key(Key::F9),
+    "f10" => Input::Key(Key::F10),
+    "f11" => Input::Key(Key::F11),
+    "f12" => Input::Key(Key::F12),
+    _ => {
+      // Piston's key codes for printable keys are the ASCII codes of the
+      // characters they type.
+      let mut characters = name.chars();
+      match (characters.next(), characters.next()) {
+        (Some(character), None) if (character as u32) < 0x80 => {
+          match Key::from(character as u32) {
+            Key::Unknown => return None,
+            key => Input::Key(key),
+          }
+        },
+        _ => return None,
+      }
+    },
+  };
+
+  Some(input)
+}
+
+/// A key or mouse button, along with the modifiers that must be held with it.
+#[derive(Clone, Debug)]
+pub struct Binding {
+  modifiers: Modifiers,
+  input: Input,
+  /// The binding as written, for error messages.
+  text: String,
+}
+
+impl Binding {
+  /// Parse a binding such as "ctrl+shift+z", "mouse_left" or "space". Names
+  /// are not case-sensitive.
+  pub fn parse(text: &str) -> error::Result<Binding> {
+    let lowercase = text.to_lowercase();
+    let mut names: Vec<&str> = lowercase.split('+').map(str::trim).collect();
+    let input_name = names.pop().unwrap_or("");
+
+    let mut modifiers = Modifiers::default();
+    for name in names.into_iter() {
+      match parse_input(name) {
+        Some(Input::Modifier(modifier)) => modifiers.set(modifier, true),
+        _ => {
+          return Err(error::Error::from(format!(
+            "{} in keybinding {} is not ctrl, shift or alt",
+            name,
+            text
+          )));
+        },
+      }
+    }
+
+    match parse_input(input_name) {
+      Some(input) => Ok(Binding {
+        modifiers: modifiers,
+        input: input,
+        text: String::from(text),
+      }),
+      None => Err(error::Error::from(format!(
+        "Unknown key {} in keybinding {}",
+        input_name,
+        text
+      ))),
+    }
+  }
+
+  /// Whether this and `other` are triggered in exactly the same way.
+  fn same_as(&self, other: &Binding) -> bool {
+    self.modifiers == other.modifiers && self.input == other.input
+  }
+}
+
+/// The bindings for an action, as written in config.json: one, or a list.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum BindingNames {
+  One(String),
+  Many(Vec<String>),
+}
+
+/// Which keys and mouse buttons trigger which actions.
+#[derive(Clone, Debug)]
+pub struct Keybindings {
+  bindings: Vec<(Action, Binding)>,
+}
+
+impl Default for Keybindings {
+  fn default() -> Keybindings {
+    Keybindings::from_names(&BTreeMap::new())
+      .expect("The default keybindings are invalid")
+  }
+}
+
+impl Keybindings {
+  /// Create Keybindings from the `keybindings` section of config.json, which
+  /// maps action names to bindings. Actions it does not name keep their
+  /// default bindings. Unknown actions and keys are errors, as are two
+  /// actions used in the same context with the same binding.
+  pub fn from_names(
+    names: &BTreeMap<String, BindingNames>,
+  ) -> error::Result<Keybindings> {
+    for name in names.keys() {
+      if !ACTIONS.iter().any(|action| action.name() == name.as_str()) {
+        return Err(error::Error::from(
+          format!("Unknown action {} in keybindings", name),
+        ));
+      }
+    }
+
+    let mut bindings = Vec::new();
+    for &action in ACTIONS.iter() {
+      let texts: Vec<&str> = match names.get(action.name()) {
+        Some(&BindingNames::One(ref text)) => vec![text.as_str()],
+        Some(&BindingNames::Many(ref texts)) => {
+          texts.iter().map(String::as_str).collect()
+        },
+        None => action.default_bindings().to_vec(),
+      };
+      for text in texts.into_iter() {
+        bindings.push((action, Binding::parse(text)?));
+      }
+    }
+
+    let mut conflicts = Vec::new();
+    for (index, &(action, ref binding)) in bindings.iter().enumerate() {
+      for &(other_action, ref other_binding) in bindings[..index].iter() {
+        if other_action != action &&
+          other_action.context() == action.context() &&
+          other_binding.same_as(binding)
+        {
+          conflicts.push(format!(
+            "{} is bound to both {} and {}",
+            binding.text,
+            other_action.name(),
+            action.name()
+          ));
+        }
+      }
+    }
+    if !conflicts.is_empty() {
+      return Err(error::Error::from(format!(
+        "Conflicting keybindings: {}",
+        conflicts.join("; ")
+      )));
+    }
+
+    Ok(Keybindings { bindings: bindings })
+  }
+
+  /// The action in `context` that pressing `button` triggers while
+  /// `modifiers` are held, if any. A binding is triggered if at least its
+  /// modifiers are held, and the binding with the most modifiers wins, so
+  /// that ctrl+shift+z is not also taken as ctrl+z.
+  pub fn action(
+    &self,
+    context: Context,
+    button: &piston::input::Button,
+    modifiers: &Modifiers,
+  ) -> Option<Action> {
+    let mut best: Option<(Action, usize)> = None;
+    for &(action, ref binding) in self.bindings.iter() {
+      if action.context() != context || !binding.input.matches(button) ||
+        !modifiers.contains(&binding.modifiers)
+      {
+        continue;
+      }
+
+      let count = binding.modifiers.count();
+      if best.map_or(true, |(_, best_count)| count > best_count) {
+        best = Some((action, count));
+      }
+    }
+
+    best.map(|(action, _)| action)
+  }
+
+  /// Whether releasing `button` ends `action`, if it is held. Modifiers are
+  /// ignored, so that letting go of them first does not leave it held.
+  pub fn releases(
+    &self,
+    action: Action,
+    button: &piston::input::Button,
+  ) -> bool {
+    self.bindings.iter().any(|&(bound_action, ref binding)| {
+      bound_action == action && binding.input.matches(button)
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use piston::input::{Button, Key, MouseButton};
+
+  /// Keybindings with the defaults, except for `bindings`.
+  fn with(bindings: &[(&str, &str)]) -> error::Result<Keybindings> {
+    let names = bindings
+      .iter()
+      .map(|&(action, binding)| {
+        (String::from(action), BindingNames::One(String::from(binding)))
+      })
+      .collect();
+    Keybindings::from_names(&names)
+  }
+
+  fn held(ctrl: bool, shift: bool, alt: bool) -> Modifiers {
+    Modifiers {
+      ctrl: ctrl,
+      shift: shift,
+      alt: alt,
+    }
+  }
+
+  #[test]
+  fn bindings_parse_modifiers_and_inputs() {
+    let binding = Binding::parse("ctrl+shift+z").unwrap();
+    assert_eq!(binding.modifiers, held(true, true, false));
+    assert_eq!(binding.input, Input::Key(Key::Z));
+
+    let binding = Binding::parse(" Alt + Mouse_Left ").unwrap();
+    assert_eq!(binding.modifiers, held(false, false, true));
+    assert_eq!(binding.input, Input::Mouse(MouseButton::Left));
+
+    assert_eq!(
+      Binding::parse("]").unwrap().input,
+      Input::Key(Key::RightBracket)
+    );
+    assert_eq!(
+      Binding::parse("shift").unwrap().input,
+      Input::Modifier(Modifier::Shift)
+    );
+  }
+
+  #[test]
+  fn unknown_keys_and_modifiers_are_errors() {
+    for text in ["", "ctrl+", "hyper+z", "z+s", "ctrl+nope"].iter() {
+      assert!(Binding::parse(text).is_err(), "{} parsed", text);
+    }
+  }
+
+  #[test]
+  fn the_default_bindings_do_not_conflict() {
+    assert!(Keybindings::from_names(&BTreeMap::new()).is_ok());
+  }
+
+  #[test]
+  fn bindings_shared_within_a_context_conflict() {
+    let error = with(&[("save", "ctrl+z")]).unwrap_err();
+    assert_eq!(
+      error.to_string(),
+      "Conflicting keybindings: ctrl+z is bound to both undo and save"
+    );
+    // The order modifiers are written in does not matter.
+    assert!(with(&[("undo", "shift+ctrl+z")]).is_err());
+    // Neither do bindings in different contexts.
+    assert!(with(&[("pause", "ctrl+z")]).is_ok());
+  }
+
+  #[test]
+  fn unknown_actions_are_errors() {
+    assert!(with(&[("fly", "f")]).is_err());
+  }
+
+  #[test]
+  fn the_binding_with_the_most_modifiers_wins() {
+    let keybindings = Keybindings::default();
+    let z = Button::Keyboard(Key::Z);
+    let action = |modifiers| keybindings.action(Context::Edit, &z, &modifiers);
+
+    assert_eq!(action(held(true, false, false)), Some(Action::Undo));
+    assert_eq!(action(held(true, true, false)), Some(Action::Redo));
+    // Extra modifiers still trigger a binding.
+    assert_eq!(action(held(true, true, true)), Some(Action::Redo));
+    assert_eq!(action(held(false, false, false)), None);
+  }
+
+  #[test]
+  fn actions_are_found_in_their_context() {
+    let keybindings = Keybindings::default();
+    let left = Button::Mouse(MouseButton::Left);
+    let none = Modifiers::default();
+
+    assert_eq!(
+      keybindings.action(Context::Edit, &left, &none),
+      Some(Action::Draw)
+    );
+    assert_eq!(
+      keybindings.action(Context::Play, &left, &none),
+      Some(Action::Scrub)
+    );
+  }
+
+  #[test]
+  fn releasing_the_input_ends_an_action_whatever_the_modifiers() {
+    let keybindings = with(&[("pan", "ctrl+mouse_right")]).unwrap();
+    let right = Button::Mouse(MouseButton::Right);
+
+    assert!(keybindings.releases(Action::Pan, &right));
+    assert!(!keybindings.releases(Action::Draw, &right));
+  }
+}
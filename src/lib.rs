@@ -0,0 +1,72 @@
+#![cfg_attr(feature = "strict", deny(missing_docs))]
+#![cfg_attr(feature = "strict", deny(warnings))]
+#![cfg_attr(feature = "window", feature(try_from))]
+#![recursion_limit = "1024"]
+
+//! Rust Rider
+//!
+//! An exploratory clone of Line Rider.
+//!
+//! Rust Rider is a sandbox-style game where players build courses for their
+//! character to ride. The player's only building block in creating their
+//! courses are line segments. These segments can be of any length, orientation,
+//! or quantity. Together they comprise a course that the character, propelled
+//! by gravity, can ride.
+//!
+//! The game's window, and the editor and playback modes shown in it
+//! (`application`, `event_source`, `handler`, `mode`, `piston_renderer`,
+//! `play_mode`, `replay`, `rust_rider`), are behind the default `window`
+//! feature. Everything else builds without it: the track model (`track`,
+//! `track_file`, `geometry`, `spatial_index`, `snap`), the importers and
+//! exporters for other formats (`lra_json`, `trk_file`, `svg_file`), the
+//! simulation (`simulation`, `headless`, `timeline`), `history`, `camera`,
+//! `keybindings` and `config`. So does drawing: `render`, which the game is
+//! drawn through, `scene`, which draws the track and the ride,
+//! `software_renderer`, which draws to an in-memory image, and `export`, which
+//! writes rides to image files.
+
+#[macro_use]
+extern crate error_chain;
+extern crate piston;
+#[cfg(feature = "window")]
+extern crate piston_window;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+#[cfg(feature = "window")]
+pub mod application;
+pub mod camera;
+pub mod config;
+pub mod error;
+#[cfg(feature = "window")]
+pub mod event_source;
+pub mod export;
+pub mod geometry;
+#[cfg(feature = "window")]
+pub mod handler;
+pub mod headless;
+pub mod history;
+pub mod keybindings;
+pub mod lra_json;
+#[cfg(feature = "window")]
+pub mod mode;
+#[cfg(feature = "window")]
+pub mod piston_renderer;
+#[cfg(feature = "window")]
+pub mod play_mode;
+pub mod render;
+#[cfg(feature = "window")]
+pub mod replay;
+#[cfg(feature = "window")]
+pub mod rust_rider;
+pub mod scene;
+pub mod simulation;
+pub mod snap;
+pub mod software_renderer;
+pub mod spatial_index;
+pub mod svg_file;
+pub mod timeline;
+pub mod track;
+pub mod track_file;
+pub mod trk_file;
@@ -0,0 +1,459 @@
+//! Tracks in the JSON format exported by Line Rider Advanced.
+//!
+//! Coordinates are taken as they are: one Line Rider unit is one world unit,
+//! and the track's start position becomes the rider's start point. Lines may
+//! be listed as objects in `lines`, or as arrays in `linesArray`. Line
+//! extensions, layers and audio only change how Line Rider draws a track, so
+//! they are ignored. Anything else that Rust Rider cannot represent, such as a
+//! rider that starts out moving, is reported as
+//! ErrorKind::UnsupportedFeatures.
+
+extern crate serde_json;
+extern crate std;
+
+use std::collections::BTreeSet;
+
+use error;
+use track;
+use track::{LineKind, LineSegment, Point};
+use track_file;
+
+/// The name of the format, as used in error messages.
+const FORMAT: &'static str = "Line Rider Advanced JSON";
+
+/// The Line Rider Advanced version written by `to_writer`.
+const VERSION: &'static str = "6.2";
+
+/// Line types, as numbered in the format.
+const NORMAL_LINE: u8 = 0;
+const ACCELERATION_LINE: u8 = 1;
+const SCENERY_LINE: u8 = 2;
+
+/// The bits of an entry of `linesArray` set when the line is extended at its
+/// first or second point.
+const LEFT_EXTENDED: u64 = 1;
+const RIGHT_EXTENDED: u64 = 2;
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+struct LraPoint {
+  x: f64,
+  y: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LraLine {
+  #[serde(default)]
+  id: i64,
+  #[serde(rename = "type")]
+  line_type: u8,
+  x1: f64,
+  y1: f64,
+  x2: f64,
+  y2: f64,
+  /// Whether the solid side of the line, and the direction an acceleration
+  /// line accelerates in, are reversed.
+  #[serde(default)]
+  flipped: bool,
+  #[serde(default)]
+  left_extended: bool,
+  #[serde(default)]
+  right_extended: bool,
+  /// How strongly an acceleration line accelerates, as a multiple of the
+  /// usual.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  multiplier: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LraRider {
+  start_position: LraPoint,
+  #[serde(default)]
+  start_velocity: LraPoint,
+  #[serde(default)]
+  remountable: bool,
+}
+
+/// The parts of the format Rust Rider reads or writes, and those it must know
+/// about to report as unsupported.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LraTrack {
+  #[serde(default)]
+  label: String,
+  #[serde(default)]
+  creator: String,
+  #[serde(default)]
+  description: String,
+  #[serde(default)]
+  version: Option<String>,
+  #[serde(default)]
+  start_position: LraPoint,
+  /// Whether the rider starts at rest, for tracks without `riders`. Rust
+  /// Rider's rider always does.
+  #[serde(default)]
+  zero_start: bool,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  riders: Vec<LraRider>,
+  #[serde(default)]
+  lines: Vec<LraLine>,
+  /// A compact form of `lines` used by some versions of the format, read by
+  /// `line_from_array`.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  lines_array: Option<Vec<Vec<serde_json::Value>>>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  game_triggers: Vec<serde_json::Value>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  triggers: Vec<serde_json::Value>,
+}
+
+/// Read one entry of `linesArray`: `[type, id, x1, y1, x2, y2]`, followed for
+/// solid lines by the bits of the ends that are extended, whether the line is
+/// flipped, the IDs of the lines joined to either end, and the multiplier.
+fn line_from_array(entry: &[serde_json::Value]) -> error::Result<LraLine> {
+  let invalid = || {
+    error::Error::from(format!(
+      "Invalid linesArray entry {}",
+      serde_json::Value::from(entry.to_vec())
+    ))
+  };
+  let number = |index: usize| -> error::Result<f64> {
+    entry.get(index).and_then(|value| value.as_f64()).ok_or_else(&invalid)
+  };
+  // Flags are written as booleans or as numbers, depending on the version.
+  let flag = |index: usize| match entry.get(index) {
+    Some(&serde_json::Value::Bool(flag)) => flag,
+    Some(value) => value.as_f64().map_or(false, |flag| flag != 0.0),
+    None => false,
+  };
+
+  let line_type = entry
+    .first()
+    .and_then(|value| value.as_u64())
+    .ok_or_else(&invalid)?;
+  if line_type > std::u8::MAX as u64 {
+    return Err(invalid());
+  }
+  let mut line = LraLine {
+    id: number(1)? as i64,
+    line_type: line_type as u8,
+    x1: number(2)?,
+    y1: number(3)?,
+    x2: number(4)?,
+    y2: number(5)?,
+    flipped: false,
+    left_extended: false,
+    right_extended: false,
+    multiplier: None,
+  };
+  if line.line_type != SCENERY_LINE {
+    let extended = entry.get(6).and_then(|value| value.as_u64()).unwrap_or(0);
+    line.left_extended = extended & LEFT_EXTENDED != 0;
+    line.right_extended = extended & RIGHT_EXTENDED != 0;
+    line.flipped = flag(7);
+    line.multiplier = entry.get(10).and_then(|value| value.as_f64());
+  }
+
+  Ok(line)
+}
+
+/// Convert a track in the format to a Track, or report every feature it uses
+/// that a Track cannot represent.
+fn to_track(lra_track: LraTrack) -> error::Result<track::Track> {
+  let mut unsupported = BTreeSet::new();
+  if lra_track.riders.len() > 1 {
+    unsupported.insert(format!("{} riders", lra_track.riders.len()));
+  }
+  if lra_track.riders.iter().any(|rider| rider.remountable) {
+    unsupported.insert(String::from("remounting"));
+  }
+  let moving = match lra_track.riders.first() {
+    Some(rider) => {
+      rider.start_velocity.x != 0.0 || rider.start_velocity.y != 0.0
+    },
+    // Without zeroStart, Line Rider starts the rider moving forward.
+    None => !lra_track.zero_start,
+  };
+  if moving {
+    unsupported.insert(String::from("a moving start"));
+  }
+  if !lra_track.game_triggers.is_empty() || !lra_track.triggers.is_empty() {
+    unsupported.insert(String::from("triggers"));
+  }
+
+  let mut lines = lra_track.lines;
+  if let Some(ref entries) = lra_track.lines_array {
+    for entry in entries.iter() {
+      lines.push(line_from_array(entry)?);
+    }
+  }
+
+  let mut line_segments = Vec::with_capacity(lines.len());
+  for line in lines.iter() {
+    let kind = match line.line_type {
+      NORMAL_LINE => LineKind::Normal,
+      ACCELERATION_LINE => LineKind::Acceleration,
+      SCENERY_LINE => LineKind::Scenery,
+      line_type => {
+        unsupported.insert(format!("line type {}", line_type));
+        continue;
+      },
+    };
+    if kind == LineKind::Acceleration &&
+      line.multiplier.map_or(false, |multiplier| multiplier != 1.0)
+    {
+      unsupported.insert(String::from("acceleration multipliers"));
+    }
+
+    // Rust Rider's lines are solid on both sides, so flipping a line only
+    // matters for which way it accelerates the rider: from its second point
+    // to its first.
+    let point1 = Point::new(line.x1, line.y1);
+    let point2 = Point::new(line.x2, line.y2);
+    line_segments.push(if line.flipped {
+      LineSegment::new(point2, point1, kind)
+    } else {
+      LineSegment::new(point1, point2, kind)
+    });
+  }
+
+  if !unsupported.is_empty() {
+    return Err(
+      error::ErrorKind::UnsupportedFeatures(
+        String::from(FORMAT),
+        unsupported.into_iter().collect(),
+      ).into(),
+    );
+  }
+
+  let start = match lra_track.riders.first() {
+    Some(rider) => rider.start_position,
+    None => lra_track.start_position,
+  };
+  Ok(track::Track {
+    metadata: track::Metadata {
+      name: lra_track.label,
+      author: lra_track.creator,
+      description: lra_track.description,
+    },
+    line_segments: line_segments,
+    rider_start: Point::new(start.x, start.y),
+    ..track::Track::new()
+  })
+}
+
+/// Convert a Track to the format.
+fn from_track(track: &track::Track) -> LraTrack {
+  let lines = track
+    .line_segments
+    .iter()
+    .enumerate()
+    .map(|(index, line_segment)| {
+      let line_type = match line_segment.kind() {
+        LineKind::Normal => NORMAL_LINE,
+        LineKind::Acceleration => ACCELERATION_LINE,
+        LineKind::Scenery => SCENERY_LINE,
+      };
+      LraLine {
+        id: index as i64 + 1,
+        line_type: line_type,
+        x1: line_segment.point1().x,
+        y1: line_segment.point1().y,
+        x2: line_segment.point2().x,
+        y2: line_segment.point2().y,
+        flipped: false,
+        left_extended: false,
+        right_extended: false,
+        multiplier: if line_type == ACCELERATION_LINE {
+          Some(1.0)
+        } else {
+          None
+        },
+      }
+    })
+    .collect();
+
+  LraTrack {
+    label: track.metadata.name.clone(),
+    creator: track.metadata.author.clone(),
+    description: track.metadata.description.clone(),
+    version: Some(String::from(VERSION)),
+    start_position: LraPoint {
+      x: track.rider_start.x,
+      y: track.rider_start.y,
+    },
+    zero_start: true,
+    riders: Vec::new(),
+    lines: lines,
+    lines_array: None,
+    game_triggers: Vec::new(),
+    triggers: Vec::new(),
+  }
+}
+
+/// Write a Track to a writer in the format.
+pub fn to_writer<Writer>(
+  writer: Writer,
+  track: &track::Track,
+) -> error::Result<()>
+where
+  Writer: std::io::Write,
+{
+  serde_json::to_writer_pretty(writer, &from_track(track))?;
+  Ok(())
+}
+
+/// Read a track in the format from a reader.
+pub fn from_reader<Reader>(reader: Reader) -> error::Result<track::Track>
+where
+  Reader: std::io::Read,
+{
+  let lra_track: LraTrack = serde_json::from_reader(reader)?;
+  let track = to_track(lra_track)?;
+  track_file::check_points(&track)?;
+  Ok(track)
+}
+
+/// Save a Track to the file at `path` in the format.
+pub fn save(
+  path: &std::path::Path,
+  track: &track::Track,
+) -> error::Result<()> {
+  let file = std::fs::File::create(path)?;
+  to_writer(std::io::BufWriter::new(file), track)
+}
+
+/// Load a Track from a file in the format at `path`.
+pub fn load(path: &std::path::Path) -> error::Result<track::Track> {
+  let file = std::fs::File::open(path)?;
+  from_reader(std::io::BufReader::new(file))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// The features `from_reader` reports as unsupported in `json`.
+  fn unsupported(json: &str) -> Vec<String> {
+    let error = from_reader(json.as_bytes()).unwrap_err();
+    match *error.kind() {
+      error::ErrorKind::UnsupportedFeatures(_, ref features) => {
+        features.clone()
+      },
+      ref kind => panic!("Expected unsupported features, got {}", kind),
+    }
+  }
+
+  #[test]
+  fn round_trip_keeps_lines_start_and_metadata() {
+    let mut track = track::Track::new();
+    track.metadata.name = String::from("Hill");
+    track.metadata.author = String::from("Someone");
+    track.rider_start = Point::new(10.0, -20.0);
+    track.line_segments = vec![
+      LineSegment::new(
+        Point::new(0.0, 0.0),
+        Point::new(100.0, 50.0),
+        LineKind::Normal,
+      ),
+      LineSegment::new(
+        Point::new(100.0, 50.0),
+        Point::new(200.0, 50.0),
+        LineKind::Acceleration,
+      ),
+      LineSegment::new(
+        Point::new(0.0, -50.0),
+        Point::new(10.0, -60.0),
+        LineKind::Scenery,
+      ),
+    ];
+
+    let mut bytes = Vec::new();
+    to_writer(&mut bytes, &track).unwrap();
+    let read = from_reader(bytes.as_slice()).unwrap();
+
+    assert_eq!(read.metadata.name, track.metadata.name);
+    assert_eq!(read.metadata.author, track.metadata.author);
+    assert_eq!(read.rider_start, track.rider_start);
+    assert_eq!(read.line_segments, track.line_segments);
+  }
+
+  #[test]
+  fn lines_array_is_read() {
+    let json = r#"{
+      "zeroStart": true,
+      "startPosition": {"x": 0, "y": 0},
+      "linesArray": [
+        [0, 1, 0, 0, 100, 50, 3, false, 0, 2],
+        [1, 2, 100, 50, 200, 50, 0, true, 1, 0, 1],
+        [2, 3, 0, -50, 10, -60, 1]
+      ]
+    }"#;
+    let track = from_reader(json.as_bytes()).unwrap();
+
+    assert_eq!(
+      track.line_segments,
+      vec![
+        LineSegment::new(
+          Point::new(0.0, 0.0),
+          Point::new(100.0, 50.0),
+          LineKind::Normal,
+        ),
+        // Flipped, so it accelerates from its second point to its first.
+        LineSegment::new(
+          Point::new(200.0, 50.0),
+          Point::new(100.0, 50.0),
+          LineKind::Acceleration,
+        ),
+        LineSegment::new(
+          Point::new(0.0, -50.0),
+          Point::new(10.0, -60.0),
+          LineKind::Scenery,
+        ),
+      ]
+    );
+  }
+
+  #[test]
+  fn lines_array_multipliers_are_unsupported() {
+    let json = r#"{
+      "zeroStart": true,
+      "linesArray": [[1, 1, 0, 0, 100, 0, 0, false, 0, 0, 2]]
+    }"#;
+    assert_eq!(
+      unsupported(json),
+      vec![String::from("acceleration multipliers")]
+    );
+  }
+
+  #[test]
+  fn short_lines_array_entries_are_errors() {
+    let json = r#"{"zeroStart": true, "linesArray": [[0, 1, 0, 0, 100]]}"#;
+    assert!(from_reader(json.as_bytes()).is_err());
+  }
+
+  #[test]
+  fn moving_starts_are_unsupported() {
+    let moving = vec![String::from("a moving start")];
+    assert_eq!(unsupported(r#"{"lines": []}"#), moving);
+    assert_eq!(unsupported(r#"{"zeroStart": false}"#), moving);
+    assert_eq!(
+      unsupported(
+        r#"{"riders": [{
+          "startPosition": {"x": 0, "y": 0},
+          "startVelocity": {"x": 0.4, "y": 0}
+        }]}"#
+      ),
+      moving
+    );
+    assert!(
+      from_reader(
+        r#"{"riders": [{
+          "startPosition": {"x": 0, "y": 0},
+          "startVelocity": {"x": 0, "y": 0}
+        }]}"#.as_bytes()
+      ).is_ok()
+    );
+  }
+}
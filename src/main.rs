@@ -5,62 +5,333 @@
 
 //! Rust Rider
 //!
-//! An exploratory clone of Line Rider.
+//! Opens the track editor on the track named on the command line, or on the
+//! configured track if none is named.
 //!
-//! Rust Rider is a sandbox-style game where players build courses for their
-//! character to ride. The player's only building block in creating their
-//! courses are line segments. These segments can be of any length, orientation,
-//! or quantity. Together they comprise a course that the character, propelled
-//! by gravity, can ride.
+//! Usage: rust_rider [--record REPLAY] [TRACK]
+//!        rust_rider replay REPLAY
+//!        rust_rider export --track TRACK --out PATH [--fps FPS]
+//!          [--duration SECONDS] [--width PIXELS] [--height PIXELS]
+//!
+//! A TRACK ending in .trk, or in .json but not .track.json, is read and saved
+//! in Line Rider Advanced's format of that name. A TRACK ending in .svg is read
+//! from the lines and paths of an SVG document, and saved as one.
+//!
+//! `--record` writes every event the game handles to REPLAY, and `replay`
+//! feeds them back to the game in place of the window's events, starting from
+//! the track the recording started with in a window of the recorded size. A
+//! replay stops with an error if an event arrives on a different simulation
+//! tick than it was recorded on.
+//!
+//! `export` rides a track without a window and writes what the camera sees to
+//! PATH: an animated GIF if PATH ends in .gif, and numbered PNG frames in the
+//! directory PATH otherwise.
 
-#[macro_use]
 extern crate error_chain;
 extern crate piston_window;
-#[macro_use]
-extern crate serde_derive;
-extern crate serde_json;
-
-mod application;
-mod config;
-mod error;
-mod handler;
-mod rust_rider;
+extern crate rust_rider;
 
 use std::cell::RefCell;
 use std::rc::Rc;
 
-fn run() -> error::Result<()> {
+use rust_rider::{application, camera, config, error, event_source, export,
+                 mode, play_mode, replay, snap, track_file};
+use rust_rider::rust_rider::{EDIT_APPLICATION_MODE, GameMode,
+                             PLAY_APPLICATION_MODE, State};
+
+/// The value following a command line flag.
+fn flag_value(
+  flag: &str,
+  value: Option<std::ffi::OsString>,
+) -> error::Result<std::ffi::OsString> {
+  value.ok_or_else(|| {
+    error::Error::from(format!("Missing value for {}", flag))
+  })
+}
+
+/// Parse the value following a command line flag.
+fn parse_flag_value<T>(
+  flag: &str,
+  value: Option<std::ffi::OsString>,
+) -> error::Result<T>
+where
+  T: std::str::FromStr,
+{
+  let value = flag_value(flag, value)?;
+  match value.to_str().and_then(|value| value.parse::<T>().ok()) {
+    Some(value) => Ok(value),
+    None => Err(error::Error::from(format!(
+      "Invalid value {} for {}",
+      value.to_string_lossy(),
+      flag
+    ))),
+  }
+}
+
+/// Export a ride of a track to images, as described by the arguments that
+/// followed `export`.
+fn run_export<Args>(
+  config: &config::Config,
+  mut args: Args,
+) -> error::Result<()>
+where
+  Args: Iterator<Item = std::ffi::OsString>,
+{
   use error::ResultExt; // chain_err
-  use std::convert::TryFrom; // try_from
 
-  let config = config::Config::from_path_str("config.json").chain_err(|| {
-    "Failed to create config"
+  let mut track_path = None;
+  let mut out = None;
+  let window_size = config.window_size();
+  let mut settings = export::ExportSettings {
+    width: window_size[0],
+    height: window_size[1],
+    ..export::ExportSettings::default()
+  };
+
+  while let Some(arg) = args.next() {
+    let arg = arg.to_string_lossy().into_owned();
+    match arg.as_str() {
+      "--track" => {
+        track_path = Some(std::path::PathBuf::from(
+          flag_value(&arg, args.next())?,
+        ));
+      },
+      "--out" => {
+        out = Some(std::path::PathBuf::from(flag_value(&arg, args.next())?));
+      },
+      "--fps" => {
+        settings.fps = parse_flag_value(&arg, args.next())?;
+      },
+      "--duration" => {
+        settings.duration = parse_flag_value(&arg, args.next())?;
+      },
+      "--width" => {
+        settings.width = parse_flag_value(&arg, args.next())?;
+      },
+      "--height" => {
+        settings.height = parse_flag_value(&arg, args.next())?;
+      },
+      _ => {
+        return Err(error::Error::from(
+          format!("Unexpected argument {}", arg),
+        ));
+      },
+    }
+  }
+  let (track_path, out) = match (track_path, out) {
+    (Some(track_path), Some(out)) => (track_path, out),
+    _ => {
+      return Err(error::Error::from(
+        "Usage: rust_rider export --track TRACK --out PATH [--fps FPS] \
+         [--duration SECONDS] [--width PIXELS] [--height PIXELS]",
+      ));
+    },
+  };
+
+  let track = track_file::load(&track_path).chain_err(|| {
+    format!("Failed to open track from {}", track_path.display())
   })?;
-  let window = Rc::new(RefCell::new(
-    piston_window::PistonWindow::try_from(&config).chain_err(
+  // The editor opens a track with a new camera, and playback starts from it.
+  export::export(
+    &track,
+    &camera::Camera::new(),
+    &camera::FollowSettings::from(config),
+    &settings,
+    &out,
+  ).chain_err(|| format!("Failed to export to {}", out.display()))
+}
+
+fn build_window(
+  config: &config::Config,
+) -> error::Result<Rc<RefCell<piston_window::PistonWindow>>> {
+  use error::ResultExt; // chain_err
+  use std::convert::TryFrom; // try_from
+
+  Ok(Rc::new(RefCell::new(
+    piston_window::PistonWindow::try_from(config).chain_err(
       || {
         "Failed to build window"
       },
     )?,
-  ));
+  )))
+}
 
-  let mut app = application::Application::<_, _>::new(window.clone());
+/// Run the editor and play modes on `state` until the window closes, taking
+/// events from `event_source`.
+fn spin<Source>(
+  config: &config::Config,
+  window: Rc<RefCell<piston_window::PistonWindow>>,
+  event_source: Source,
+  recorder: Option<replay::Recorder>,
+  track_path: &std::path::Path,
+  state: State,
+) -> error::Result<()>
+where
+  Source: event_source::EventSource,
+{
+  use error::ResultExt; // chain_err
+
+  // The track is shared by every application mode.
+  let state = Rc::new(RefCell::new(state));
+
+  let mut app = application::Application::<_, _>::new(event_source);
+  if let Some(recorder) = recorder {
+    app.record(recorder);
+  }
   app
     .add_application_mode(
-      "rust_rider",
-      Box::new(rust_rider::GameMode::<_>::new(window.clone())),
+      EDIT_APPLICATION_MODE,
+      Box::new(mode::Mode::Edit(GameMode::<_>::new_with_state(
+        window.clone(),
+        state.clone(),
+        track_path,
+        config.keybindings().clone(),
+        snap::SnapSettings::from(config),
+      ))),
     )
     .chain_err(|| "Failed to add rust rider application mode")?;
-  app.set_active_application_mode("rust_rider").chain_err(
-    || {
-      "Failed to activate rust rider application mode"
-    },
-  )?;
+  app
+    .add_application_mode(
+      PLAY_APPLICATION_MODE,
+      Box::new(mode::Mode::Play(play_mode::PlayMode::<_>::new(
+        window.clone(),
+        state.clone(),
+        camera::FollowSettings::from(config),
+        config.keybindings().clone(),
+      ))),
+    )
+    .chain_err(|| "Failed to add play application mode")?;
+  app
+    .set_active_application_mode(EDIT_APPLICATION_MODE)
+    .chain_err(|| "Failed to activate rust rider application mode")?;
   app.spin().chain_err(|| "Failed to spin")?;
 
   Ok(())
 }
 
+/// Open the track editor, as described by the command line arguments.
+fn run_editor<Args>(
+  config: &config::Config,
+  mut args: Args,
+) -> error::Result<()>
+where
+  Args: Iterator<Item = std::ffi::OsString>,
+{
+  use error::ResultExt; // chain_err
+
+  let mut track_path = None;
+  let mut replay_path = None;
+
+  while let Some(arg) = args.next() {
+    if arg.to_str() == Some("--record") {
+      replay_path = Some(std::path::PathBuf::from(
+        flag_value("--record", args.next())?,
+      ));
+    } else if track_path.is_none() {
+      track_path = Some(std::path::PathBuf::from(arg));
+    } else {
+      return Err(error::Error::from(format!(
+        "Unexpected argument {}",
+        arg.to_string_lossy()
+      )));
+    }
+  }
+
+  // A track named on the command line replaces the configured track, and is
+  // opened immediately.
+  let (track_path, state) = match track_path {
+    Some(track_path) => {
+      let track = track_file::load(&track_path).chain_err(|| {
+        format!("Failed to open track from {}", track_path.display())
+      })?;
+      (track_path, State::new_with_track(track))
+    },
+    None => (config.track_path().to_path_buf(), State::new()),
+  };
+
+  let window = build_window(config)?;
+  let recorder = match replay_path {
+    Some(replay_path) => {
+      use piston_window::Window; // size
+
+      let size = window.borrow().size();
+      Some(
+        replay::Recorder::create(
+          &replay_path,
+          &track_path,
+          state.track(),
+          [size.width, size.height],
+        ).chain_err(|| {
+          format!("Failed to start replay {}", replay_path.display())
+        })?,
+      )
+    },
+    None => None,
+  };
+
+  spin(
+    config,
+    window.clone(),
+    event_source::WindowEventSource::new(window),
+    recorder,
+    &track_path,
+    state,
+  )
+}
+
+/// Play back a replay in the editor, as described by the command line
+/// arguments that followed `replay`. The window is the size the replay was
+/// recorded at, whatever `config` says.
+fn run_replay<Args>(
+  mut config: config::Config,
+  mut args: Args,
+) -> error::Result<()>
+where
+  Args: Iterator<Item = std::ffi::OsString>,
+{
+  use error::ResultExt; // chain_err
+
+  let replay_path = match (args.next(), args.next()) {
+    (Some(replay_path), None) => std::path::PathBuf::from(replay_path),
+    _ => return Err(error::Error::from("Usage: rust_rider replay REPLAY")),
+  };
+
+  let replay = replay::Replay::open(&replay_path)
+    .chain_err(|| format!("Failed to open replay {}", replay_path.display()))?;
+  let track_path = replay.track_path().to_path_buf();
+  let state = State::new_with_track(replay.track().clone());
+  config.set_window_size(replay.window_size());
+
+  let window = build_window(&config)?;
+  spin(
+    &config,
+    window.clone(),
+    replay::ReplaySource::new(replay, window),
+    None,
+    &track_path,
+    state,
+  )
+}
+
+fn run() -> error::Result<()> {
+  use error::ResultExt; // chain_err
+
+  let config = config::Config::from_path_str("config.json").chain_err(|| {
+    "Failed to create config"
+  })?;
+
+  let mut args = std::env::args_os().skip(1);
+  match args.next() {
+    Some(ref command) if command.to_str() == Some("export") => {
+      run_export(&config, args)
+    },
+    Some(ref command) if command.to_str() == Some("replay") => {
+      run_replay(config, args)
+    },
+    first_arg => run_editor(&config, first_arg.into_iter().chain(args)),
+  }
+}
+
 fn main() {
   if let Err(ref e) = run() {
     use std::io::Write; // writeln
@@ -0,0 +1,263 @@
+extern crate piston_window;
+
+use error;
+use handler;
+// Bring the handler traits into scope to call them on the wrapped modes.
+use handler::{EventHandler, InputHandler, UpdateHandler, WindowHandler};
+use play_mode;
+use rust_rider;
+
+/// Every application mode of the Rust Rider game. Application holds a single
+/// EventHandler type, so each mode is wrapped here and events are forwarded to
+/// whichever mode is wrapped.
+pub enum Mode<Window>
+where
+  Window: piston_window::Window,
+{
+  Edit(rust_rider::GameMode<Window>),
+  Play(play_mode::PlayMode<Window>),
+}
+
+/// Forward input-events to the wrapped mode.
+impl<Window> handler::InputHandler for Mode<Window>
+where Window: piston_window::OpenGLWindow,
+{
+  fn on_button<Event: piston_window::GenericEvent>(
+    &mut self,
+    event: &Event,
+    button_args: &piston_window::ButtonArgs,
+  ) -> error::Result<()> {
+    match *self {
+      Mode::Edit(ref mut mode) => mode.on_button(event, button_args),
+      Mode::Play(ref mut mode) => mode.on_button(event, button_args),
+    }
+  }
+
+  fn on_controller_axis<Event: piston_window::GenericEvent>(
+    &mut self,
+    event: &Event,
+    controller_axis_args: &piston_window::ControllerAxisArgs,
+  ) -> error::Result<()> {
+    match *self {
+      Mode::Edit(ref mut mode) => {
+        mode.on_controller_axis(event, controller_axis_args)
+      },
+      Mode::Play(ref mut mode) => {
+        mode.on_controller_axis(event, controller_axis_args)
+      },
+    }
+  }
+
+  fn on_mouse_cursor<Event: piston_window::GenericEvent>(
+    &mut self,
+    event: &Event,
+    position: &[f64; 2],
+  ) -> error::Result<()> {
+    match *self {
+      Mode::Edit(ref mut mode) => mode.on_mouse_cursor(event, position),
+      Mode::Play(ref mut mode) => mode.on_mouse_cursor(event, position),
+    }
+  }
+
+  fn on_mouse_relative<Event: piston_window::GenericEvent>(
+    &mut self,
+    event: &Event,
+    relative: &[f64; 2],
+  ) -> error::Result<()> {
+    match *self {
+      Mode::Edit(ref mut mode) => mode.on_mouse_relative(event, relative),
+      Mode::Play(ref mut mode) => mode.on_mouse_relative(event, relative),
+    }
+  }
+
+  fn on_mouse_scroll<Event: piston_window::GenericEvent>(
+    &mut self,
+    event: &Event,
+    scroll: &[f64; 2],
+  ) -> error::Result<()> {
+    match *self {
+      Mode::Edit(ref mut mode) => mode.on_mouse_scroll(event, scroll),
+      Mode::Play(ref mut mode) => mode.on_mouse_scroll(event, scroll),
+    }
+  }
+
+  fn on_press<Event: piston_window::GenericEvent>(
+    &mut self,
+    event: &Event,
+    button: &piston_window::Button,
+  ) -> error::Result<()> {
+    match *self {
+      Mode::Edit(ref mut mode) => mode.on_press(event, button),
+      Mode::Play(ref mut mode) => mode.on_press(event, button),
+    }
+  }
+
+  fn on_release<Event: piston_window::GenericEvent>(
+    &mut self,
+    event: &Event,
+    button: &piston_window::Button,
+  ) -> error::Result<()> {
+    match *self {
+      Mode::Edit(ref mut mode) => mode.on_release(event, button),
+      Mode::Play(ref mut mode) => mode.on_release(event, button),
+    }
+  }
+
+  fn on_text<Event: piston_window::GenericEvent>(
+    &mut self,
+    event: &Event,
+    text: &String,
+  ) -> error::Result<()> {
+    match *self {
+      Mode::Edit(ref mut mode) => mode.on_text(event, text),
+      Mode::Play(ref mut mode) => mode.on_text(event, text),
+    }
+  }
+
+  fn on_touch<Event: piston_window::GenericEvent>(
+    &mut self,
+    event: &Event,
+    touch_args: &piston_window::TouchArgs,
+  ) -> error::Result<()> {
+    match *self {
+      Mode::Edit(ref mut mode) => mode.on_touch(event, touch_args),
+      Mode::Play(ref mut mode) => mode.on_touch(event, touch_args),
+    }
+  }
+}
+
+/// Forward update-events to the wrapped mode.
+impl<Window> handler::UpdateHandler for Mode<Window>
+where Window: piston_window::OpenGLWindow,
+{
+  fn on_idle<Event: piston_window::GenericEvent>(
+    &mut self,
+    event: &Event,
+    idle_args: &piston_window::IdleArgs,
+  ) -> error::Result<()> {
+    match *self {
+      Mode::Edit(ref mut mode) => mode.on_idle(event, idle_args),
+      Mode::Play(ref mut mode) => mode.on_idle(event, idle_args),
+    }
+  }
+
+  fn on_update<Event: piston_window::GenericEvent>(
+    &mut self,
+    event: &Event,
+    update_args: &piston_window::UpdateArgs,
+  ) -> error::Result<()> {
+    match *self {
+      Mode::Edit(ref mut mode) => mode.on_update(event, update_args),
+      Mode::Play(ref mut mode) => mode.on_update(event, update_args),
+    }
+  }
+}
+
+/// Forward window-events to the wrapped mode.
+impl<Window> handler::WindowHandler for Mode<Window>
+where Window: piston_window::OpenGLWindow,
+{
+  fn on_after_render<Event: piston_window::GenericEvent>(
+    &mut self,
+    event: &Event,
+    after_render_args: &piston_window::AfterRenderArgs,
+  ) -> error::Result<()> {
+    match *self {
+      Mode::Edit(ref mut mode) => {
+        mode.on_after_render(event, after_render_args)
+      },
+      Mode::Play(ref mut mode) => {
+        mode.on_after_render(event, after_render_args)
+      },
+    }
+  }
+
+  fn on_close<Event: piston_window::GenericEvent>(
+    &mut self,
+    event: &Event,
+    close_args: &piston_window::CloseArgs,
+  ) -> error::Result<()> {
+    match *self {
+      Mode::Edit(ref mut mode) => mode.on_close(event, close_args),
+      Mode::Play(ref mut mode) => mode.on_close(event, close_args),
+    }
+  }
+
+  fn on_cursor<Event: piston_window::GenericEvent>(
+    &mut self,
+    event: &Event,
+    cursor: bool,
+  ) -> error::Result<()> {
+    match *self {
+      Mode::Edit(ref mut mode) => mode.on_cursor(event, cursor),
+      Mode::Play(ref mut mode) => mode.on_cursor(event, cursor),
+    }
+  }
+
+  fn on_focus<Event: piston_window::GenericEvent>(
+    &mut self,
+    event: &Event,
+    focus: bool,
+  ) -> error::Result<()> {
+    match *self {
+      Mode::Edit(ref mut mode) => mode.on_focus(event, focus),
+      Mode::Play(ref mut mode) => mode.on_focus(event, focus),
+    }
+  }
+
+  fn on_render<Event: piston_window::GenericEvent>(
+    &mut self,
+    event: &Event,
+    render_args: &piston_window::RenderArgs,
+  ) -> error::Result<()> {
+    match *self {
+      Mode::Edit(ref mut mode) => mode.on_render(event, render_args),
+      Mode::Play(ref mut mode) => mode.on_render(event, render_args),
+    }
+  }
+
+  fn on_resize<Event: piston_window::GenericEvent>(
+    &mut self,
+    event: &Event,
+    size: &[u32; 2],
+  ) -> error::Result<()> {
+    match *self {
+      Mode::Edit(ref mut mode) => mode.on_resize(event, size),
+      Mode::Play(ref mut mode) => mode.on_resize(event, size),
+    }
+  }
+}
+
+/// Inherit default implementation of EventHandler::on_event, which dispatches
+/// to the forwarding handlers above.
+impl<Window> handler::EventHandler for Mode<Window>
+where Window: piston_window::OpenGLWindow,
+{
+  fn on_enter(&mut self) -> error::Result<()> {
+    match *self {
+      Mode::Edit(ref mut mode) => mode.on_enter(),
+      Mode::Play(ref mut mode) => mode.on_enter(),
+    }
+  }
+
+  fn on_exit(&mut self) -> error::Result<()> {
+    match *self {
+      Mode::Edit(ref mut mode) => mode.on_exit(),
+      Mode::Play(ref mut mode) => mode.on_exit(),
+    }
+  }
+
+  fn requested_application_mode(&mut self) -> Option<String> {
+    match *self {
+      Mode::Edit(ref mut mode) => mode.requested_application_mode(),
+      Mode::Play(ref mut mode) => mode.requested_application_mode(),
+    }
+  }
+
+  fn simulation_tick(&self) -> Option<u64> {
+    match *self {
+      Mode::Edit(ref mode) => mode.simulation_tick(),
+      Mode::Play(ref mode) => mode.simulation_tick(),
+    }
+  }
+}
@@ -0,0 +1,103 @@
+extern crate graphics;
+extern crate piston_window;
+
+use render;
+use render::{Color, Transform};
+use track::Point;
+
+/// The most vertices passed to the graphics backend at once. A whole number
+/// of triangles, as many as the backend can take.
+const TRIANGLE_CHUNK_SIZE: usize = graphics::BACK_END_MAX_VERTEX_COUNT / 3 * 3;
+
+/// A Renderer that draws to a piston window, for use within draw_2d.
+pub struct PistonRenderer<'a, G>
+where
+  G: 'a + graphics::Graphics,
+{
+  /// The window's transform from pixels to its own coordinates.
+  base: graphics::math::Matrix2d,
+  draw_state: graphics::DrawState,
+  transform: Transform,
+  graphics: &'a mut G,
+}
+
+impl<'a, G> PistonRenderer<'a, G>
+where
+  G: 'a + graphics::Graphics,
+{
+  pub fn new(
+    context: &piston_window::Context,
+    graphics: &'a mut G,
+  ) -> PistonRenderer<'a, G> {
+    PistonRenderer {
+      base: context.transform,
+      draw_state: context.draw_state,
+      transform: Transform::identity(),
+      graphics: graphics,
+    }
+  }
+
+  fn matrix(&self) -> graphics::math::Matrix2d {
+    graphics::math::multiply(self.base, self.transform.matrix())
+  }
+}
+
+impl<'a, G> render::Renderer for PistonRenderer<'a, G>
+where
+  G: 'a + graphics::Graphics,
+{
+  fn transform(&self) -> Transform {
+    self.transform
+  }
+
+  fn set_transform(&mut self, transform: Transform) {
+    self.transform = transform;
+  }
+
+  fn clear(&mut self, color: Color) {
+    piston_window::clear(color, self.graphics);
+  }
+
+  fn rectangle(
+    &mut self,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    color: Color,
+  ) {
+    let matrix = self.matrix();
+    piston_window::rectangle(
+      color,
+      [x, y, width, height],
+      matrix,
+      self.graphics,
+    );
+  }
+
+  fn circle(&mut self, center: &Point, radius: f64, color: Color) {
+    let matrix = self.matrix();
+    piston_window::ellipse(
+      color,
+      piston_window::ellipse::circle(center.x, center.y, radius),
+      matrix,
+      self.graphics,
+    );
+  }
+
+  fn triangles(&mut self, vertices: &[Point], color: Color) {
+    use self::graphics::triangulation::{tx, ty};
+
+    let matrix = self.matrix();
+    let mut chunk = Vec::with_capacity(TRIANGLE_CHUNK_SIZE);
+    self.graphics.tri_list(&self.draw_state, &color, |f| {
+      for vertices in vertices.chunks(TRIANGLE_CHUNK_SIZE) {
+        chunk.clear();
+        chunk.extend(vertices.iter().map(|vertex| {
+          [tx(matrix, vertex.x, vertex.y), ty(matrix, vertex.x, vertex.y)]
+        }));
+        f(&chunk);
+      }
+    });
+  }
+}
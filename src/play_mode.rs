@@ -0,0 +1,413 @@
+extern crate piston_window;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use camera;
+use error;
+use handler;
+use keybindings;
+use keybindings::Action;
+use piston_renderer;
+use render;
+use geometry;
+use rust_rider;
+use scene;
+use timeline;
+use track;
+
+/// Where the timeline slider starts in the play bar, in screen pixels. The
+/// ride time is shown to its left.
+const TIMELINE_X_OFFSET: f64 = 80.0;
+/// The space, in screen pixels, left around the timeline slider.
+const TIMELINE_MARGIN: f64 = 6.0;
+/// The width, in screen pixels, of the mark showing the current tick.
+const TIMELINE_KNOB_WIDTH: f64 = 4.0;
+const DARK_RED: render::Color = [0.5, 0.0, 0.0, 1.0];
+
+/// The timeline slider, in screen coordinates, for a screen of the given size.
+fn timeline_slider(screen_size: &track::Vector) -> geometry::Aabb {
+  geometry::Aabb::from_corners(
+    &track::Point::new(
+      TIMELINE_X_OFFSET,
+      screen_size.y - scene::STATUS_BAR_HEIGHT + TIMELINE_MARGIN,
+    ),
+    &track::Point::new(
+      screen_size.x - TIMELINE_MARGIN,
+      screen_size.y - TIMELINE_MARGIN,
+    ),
+  )
+}
+
+/// Draw the timeline slider over the play bar, with a mark at the tick being
+/// shown. The slider spans the ride as far as it has been simulated.
+pub fn draw_timeline<R>(
+  renderer: &mut R,
+  timeline: &timeline::Timeline,
+  screen_size: &track::Vector,
+) where
+  R: render::Renderer,
+{
+  let slider = timeline_slider(screen_size);
+  renderer.set_transform(render::Transform::identity());
+  renderer.rectangle(
+    slider.min.x,
+    slider.min.y,
+    slider.width(),
+    slider.height(),
+    DARK_RED,
+  );
+
+  let progress = match timeline.furthest_tick() {
+    0 => 0.0,
+    furthest_tick => {
+      timeline.simulation().tick() as f64 / furthest_tick as f64
+    },
+  };
+  renderer.rectangle(
+    slider.min.x + (slider.width() - TIMELINE_KNOB_WIDTH) * progress,
+    slider.min.y,
+    TIMELINE_KNOB_WIDTH,
+    slider.height(),
+    scene::WHITE,
+  );
+}
+
+/// The application mode in which the rider rides the track built in GameMode.
+/// The track is shared with GameMode and is never modified here.
+pub struct PlayMode<Window>
+where
+  Window: piston_window::Window,
+{
+  state: Rc<RefCell<rust_rider::State>>,
+  timeline: Option<timeline::Timeline>,
+  camera: camera::Camera,
+  follow_settings: camera::FollowSettings,
+  window: Rc<RefCell<piston_window::PistonWindow<Window>>>,
+  keybindings: keybindings::Keybindings,
+  modifiers: keybindings::Modifiers,
+  cursor_position: track::Point,
+  /// Whether the timeline slider is being dragged.
+  scrubbing: bool,
+  requested_application_mode: Option<String>,
+}
+
+/// How PlayMode responds to input-events.
+impl<Window> handler::InputHandler for PlayMode<Window>
+where Window: piston_window::Window,
+{
+  fn on_button<Event: piston_window::GenericEvent>(
+    &mut self,
+    _event: &Event,
+    _button_args: &piston_window::ButtonArgs,
+  ) -> error::Result<()> {
+    Ok(())
+  }
+
+  fn on_controller_axis<Event: piston_window::GenericEvent>(
+    &mut self,
+    _event: &Event,
+    _controller_axis_args: &piston_window::ControllerAxisArgs,
+  ) -> error::Result<()> {
+    Ok(())
+  }
+
+  fn on_mouse_cursor<Event: piston_window::GenericEvent>(
+    &mut self,
+    _event: &Event,
+    position: &[f64; 2],
+  ) -> error::Result<()> {
+    self.cursor_position = track::Point::new(position[0], position[1]);
+    if self.scrubbing {
+      self.scrub();
+    }
+
+    Ok(())
+  }
+
+  fn on_mouse_relative<Event: piston_window::GenericEvent>(
+    &mut self,
+    _event: &Event,
+    _relative: &[f64; 2],
+  ) -> error::Result<()> {
+    Ok(())
+  }
+
+  fn on_mouse_scroll<Event: piston_window::GenericEvent>(
+    &mut self,
+    _event: &Event,
+    _scroll: &[f64; 2],
+  ) -> error::Result<()> {
+    Ok(())
+  }
+
+  fn on_press<Event: piston_window::GenericEvent>(
+    &mut self,
+    _event: &Event,
+    button: &piston_window::Button,
+  ) -> error::Result<()> {
+    let action = self.keybindings.action(
+      keybindings::Context::Play,
+      button,
+      &self.modifiers,
+    );
+    self.modifiers.press(button);
+
+    match action {
+      Some(Action::Stop) => {
+        self.requested_application_mode =
+          Some(String::from(rust_rider::EDIT_APPLICATION_MODE));
+      },
+      Some(Action::Pause) => {
+        if let Some(ref mut timeline) = self.timeline {
+          let paused = timeline.paused();
+          timeline.set_paused(!paused);
+        }
+      },
+      Some(Action::StepForward) => {
+        if let Some(ref mut timeline) = self.timeline {
+          timeline.step_forward();
+        }
+      },
+      Some(Action::StepBack) => {
+        if let Some(ref mut timeline) = self.timeline {
+          timeline.step_back();
+        }
+      },
+      Some(Action::Scrub) => {
+        let slider = timeline_slider(&self.screen_size());
+        if slider.expanded(TIMELINE_MARGIN).contains(&self.cursor_position) {
+          self.scrubbing = true;
+          self.scrub();
+        }
+      },
+      _ => {},
+    }
+
+    Ok(())
+  }
+
+  fn on_release<Event: piston_window::GenericEvent>(
+    &mut self,
+    _event: &Event,
+    button: &piston_window::Button,
+  ) -> error::Result<()> {
+    self.modifiers.release(button);
+    if self.keybindings.releases(Action::Scrub, button) {
+      self.scrubbing = false;
+    }
+
+    Ok(())
+  }
+
+  fn on_text<Event: piston_window::GenericEvent>(
+    &mut self,
+    _event: &Event,
+    _text: &String,
+  ) -> error::Result<()> {
+    Ok(())
+  }
+
+  fn on_touch<Event: piston_window::GenericEvent>(
+    &mut self,
+    _event: &Event,
+    _touch_args: &piston_window::TouchArgs,
+  ) -> error::Result<()> {
+    Ok(())
+  }
+}
+
+/// How PlayMode responds to update-events.
+impl<Window> handler::UpdateHandler for PlayMode<Window>
+where Window: piston_window::Window,
+{
+  fn on_idle<Event: piston_window::GenericEvent>(
+    &mut self,
+    _event: &Event,
+    _idle_args: &piston_window::IdleArgs,
+  ) -> error::Result<()> {
+    Ok(())
+  }
+
+  fn on_update<Event: piston_window::GenericEvent>(
+    &mut self,
+    _event: &Event,
+    update_args: &piston_window::UpdateArgs,
+  ) -> error::Result<()> {
+    let screen_size = self.screen_size();
+    if let Some(ref mut timeline) = self.timeline {
+      timeline.update(update_args.dt);
+      scene::follow_rider(
+        &mut self.camera,
+        &self.follow_settings,
+        timeline.simulation(),
+        &screen_size,
+        update_args.dt,
+      );
+    }
+
+    Ok(())
+  }
+}
+
+/// How PlayMode responds to window-events.
+impl<Window> handler::WindowHandler for PlayMode<Window>
+where Window: piston_window::OpenGLWindow,
+{
+  fn on_after_render<Event: piston_window::GenericEvent>(
+    &mut self,
+    _event: &Event,
+    _after_render_args: &piston_window::AfterRenderArgs,
+  ) -> error::Result<()> {
+    Ok(())
+  }
+
+  fn on_close<Event: piston_window::GenericEvent>(
+    &mut self,
+    _event: &Event,
+    _close_args: &piston_window::CloseArgs,
+  ) -> error::Result<()> {
+    Ok(())
+  }
+
+  fn on_cursor<Event: piston_window::GenericEvent>(
+    &mut self,
+    _event: &Event,
+    _cursor: bool,
+  ) -> error::Result<()> {
+    Ok(())
+  }
+
+  fn on_focus<Event: piston_window::GenericEvent>(
+    &mut self,
+    _event: &Event,
+    _focus: bool,
+  ) -> error::Result<()> {
+    Ok(())
+  }
+
+  fn on_render<Event: piston_window::GenericEvent>(
+    &mut self,
+    event: &Event,
+    _render_args: &piston_window::RenderArgs,
+  ) -> error::Result<()> {
+    // Borrow member references immutably before allowing self to be borrowed
+    // mutably by self.window.draw_2d().
+    let state = self.state.borrow();
+    let timeline = &self.timeline;
+    let camera = &self.camera;
+    let screen_size = self.screen_size();
+
+    self.window.borrow_mut().draw_2d(event, |context, graphics| {
+      let mut renderer =
+        piston_renderer::PistonRenderer::new(&context, graphics);
+      scene::draw_ride(
+        &mut renderer,
+        state.track(),
+        state.spatial_index(),
+        timeline.as_ref().map(|timeline| timeline.simulation()),
+        camera,
+        &screen_size,
+      );
+      if let Some(ref timeline) = *timeline {
+        draw_timeline(&mut renderer, timeline, &screen_size);
+      }
+    });
+
+    Ok(())
+  }
+
+  fn on_resize<Event: piston_window::GenericEvent>(
+    &mut self,
+    _event: &Event,
+    _size: &[u32; 2],
+  ) -> error::Result<()> {
+    Ok(())
+  }
+}
+
+impl<Window> handler::EventHandler for PlayMode<Window>
+where Window: piston_window::OpenGLWindow,
+{
+  /// Every ride starts from the rider's start point on the current track, as
+  /// seen from wherever the editor was looking.
+  fn on_enter(&mut self) -> error::Result<()> {
+    let state = self.state.borrow();
+    self.timeline = Some(timeline::Timeline::new(state.track()));
+    self.camera = *state.camera();
+
+    Ok(())
+  }
+
+  fn on_exit(&mut self) -> error::Result<()> {
+    self.timeline = None;
+    self.modifiers = keybindings::Modifiers::default();
+    self.scrubbing = false;
+
+    Ok(())
+  }
+
+  fn requested_application_mode(&mut self) -> Option<String> {
+    self.requested_application_mode.take()
+  }
+
+  fn simulation_tick(&self) -> Option<u64> {
+    self
+      .timeline
+      .as_ref()
+      .map(|timeline| timeline.simulation().tick())
+  }
+}
+
+impl<Window> PlayMode<Window>
+where
+  Window: piston_window::Window + piston_window::OpenGLWindow,
+{
+  /// Create a PlayMode that rides the track held by a shared State, following
+  /// the rider as described by `follow_settings`, and controlled with the
+  /// play bindings of `keybindings`.
+  pub fn new(
+    window: Rc<RefCell<piston_window::PistonWindow<Window>>>,
+    state: Rc<RefCell<rust_rider::State>>,
+    follow_settings: camera::FollowSettings,
+    keybindings: keybindings::Keybindings,
+  ) -> PlayMode<Window> {
+    PlayMode {
+      state: state,
+      timeline: None,
+      camera: camera::Camera::new(),
+      follow_settings: follow_settings,
+      window: window,
+      keybindings: keybindings,
+      modifiers: keybindings::Modifiers::default(),
+      cursor_position: track::Point::new(0.0, 0.0),
+      scrubbing: false,
+      requested_application_mode: None,
+    }
+  }
+}
+
+impl<Window> PlayMode<Window>
+where
+  Window: piston_window::Window,
+{
+  fn screen_size(&self) -> track::Vector {
+    use piston_window::Window; // size
+
+    let window_size = self.window.borrow().size();
+    track::Vector::new(window_size.width as f64, window_size.height as f64)
+  }
+
+  /// Move the ride to the tick under the cursor on the timeline slider.
+  fn scrub(&mut self) {
+    let slider = timeline_slider(&self.screen_size());
+    if let Some(ref mut timeline) = self.timeline {
+      let fraction = ((self.cursor_position.x - slider.min.x) /
+                        slider.width())
+        .max(0.0)
+        .min(1.0);
+      let tick = (fraction * timeline.furthest_tick() as f64).round() as u64;
+      timeline.seek(tick);
+    }
+  }
+}
@@ -0,0 +1,382 @@
+use track::{Point, Vector};
+
+/// A color, as red, green, blue and alpha components from 0 to 1.
+pub type Color = [f32; 4];
+
+/// The number of columns in a glyph of the built-in font.
+const GLYPH_WIDTH: usize = 5;
+/// The number of rows in a glyph of the built-in font.
+const GLYPH_HEIGHT: usize = 7;
+/// The first character the built-in font has a glyph for.
+const FIRST_GLYPH: char = ' ';
+
+/// A 5x7 bitmap font covering printable ASCII. Each glyph is five columns,
+/// left to right, and bit n of a column is set if row n, from the top, is
+/// filled.
+const GLYPHS: [[u8; GLYPH_WIDTH]; 95] = [
+  [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+  [0x00, 0x00, 0x5f, 0x00, 0x00], // '!'
+  [0x00, 0x07, 0x00, 0x07, 0x00], // '"'
+  [0x14, 0x7f, 0x14, 0x7f, 0x14], // '#'
+  [0x24, 0x2a, 0x7f, 0x2a, 0x12], // '$'
+  [0x23, 0x13, 0x08, 0x64, 0x62], // '%'
+  [0x36, 0x49, 0x55, 0x22, 0x50], // '&'
+  [0x00, 0x05, 0x03, 0x00, 0x00], // '''
+  [0x00, 0x1c, 0x22, 0x41, 0x00], // '('
+  [0x00, 0x41, 0x22, 0x1c, 0x00], // ')'
+  [0x14, 0x08, 0x3e, 0x08, 0x14], // '*'
+  [0x08, 0x08, 0x3e, 0x08, 0x08], // '+'
+  [0x00, 0x50, 0x30, 0x00, 0x00], // ','
+  [0x08, 0x08, 0x08, 0x08, 0x08], // '-'
+  [0x00, 0x60, 0x60, 0x00, 0x00], // '.'
+  [0x20, 0x10, 0x08, 0x04, 0x02], // '/'
+  [0x3e, 0x51, 0x49, 0x45, 0x3e], // '0'
+  [0x00, 0x42, 0x7f, 0x40, 0x00], // '1'
+  [0x42, 0x61, 0x51, 0x49, 0x46], // '2'
+  [0x21, 0x41, 0x45, 0x4b, 0x31], // '3'
+  [0x18, 0x14, 0x12, 0x7f, 0x10], // '4'
+  [0x27, 0x45, 0x45, 0x45, 0x39], // '5'
+  [0x3c, 0x4a, 0x49, 0x49, 0x30], // '6'
+  [0x01, 0x71, 0x09, 0x05, 0x03], // '7'
+  [0x36, 0x49, 0x49, 0x49, 0x36], // '8'
+  [0x06, 0x49, 0x49, 0x29, 0x1e], // '9'
+  [0x00, 0x36, 0x36, 0x00, 0x00], // ':'
+  [0x00, 0x56, 0x36, 0x00, 0x00], // ';'
+  [0x08, 0x14, 0x22, 0x41, 0x00], // '<'
+  [0x14, 0x14, 0x14, 0x14, 0x14], // '='
+  [0x00, 0x41, 0x22, 0x14, 0x08], // '>'
+  [0x02, 0x01, 0x51, 0x09, 0x06], // '?'
+  [0x32, 0x49, 0x79, 0x41, 0x3e], // '@'
+  [0x7e, 0x11, 0x11, 0x11, 0x7e], // 'A'
+  [0x7f, 0x49, 0x49, 0x49, 0x36], // 'B'
+  [0x3e, 0x41, 0x41, 0x41, 0x22], // 'C'
+  [0x7f, 0x41, 0x41, 0x22, 0x1c], // 'D'
+  [0x7f, 0x49, 0x49, 0x49, 0x41], // 'E'
+  [0x7f, 0x09, 0x09, 0x09, 0x01], // 'F'
+  [0x3e, 0x41, 0x49, 0x49, 0x7a], // 'G'
+  [0x7f, 0x08, 0x08, 0x08, 0x7f], // 'H'
+  [0x00, 0x41, 0x7f, 0x41, 0x00], // 'I'
+  [0x20, 0x40, 0x41, 0x3f, 0x01], // 'J'
+  [0x7f, 0x08, 0x14, 0x22, 0x41], // 'K'
+  [0x7f, 0x40, 0x40, 0x40, 0x40], // 'L'
+  [0x7f, 0x02, 0x0c, 0x02, 0x7f], // 'M'
+  [0x7f, 0x04, 0x08, 0x10, 0x7f], // 'N'
+  [0x3e, 0x41, 0x41, 0x41, 0x3e], // 'O'
+  [0x7f, 0x09, 0x09, 0x09, 0x06], // 'P'
+  [0x3e, 0x41, 0x51, 0x21, 0x5e], // 'Q'
+  [0x7f, 0x09, 0x19, 0x29, 0x46], // 'R'
+  [0x46, 0x49, 0x49, 0x49, 0x31], // 'S'
+  [0x01, 0x01, 0x7f, 0x01, 0x01], // 'T'
+  [0x3f, 0x40, 0x40, 0x40, 0x3f], // 'U'
+  [0x1f, 0x20, 0x40, 0x20, 0x1f], // 'V'
+  [0x3f, 0x40, 0x38, 0x40, 0x3f], // 'W'
+  [0x63, 0x14, 0x08, 0x14, 0x63], // 'X'
+  [0x07, 0x08, 0x70, 0x08, 0x07], // 'Y'
+  [0x61, 0x51, 0x49, 0x45, 0x43], // 'Z'
+  [0x00, 0x7f, 0x41, 0x41, 0x00], // '['
+  [0x02, 0x04, 0x08, 0x10, 0x20], // '\'
+  [0x00, 0x41, 0x41, 0x7f, 0x00], // ']'
+  [0x04, 0x02, 0x01, 0x02, 0x04], // '^'
+  [0x40, 0x40, 0x40, 0x40, 0x40], // '_'
+  [0x00, 0x01, 0x02, 0x04, 0x00], // '`'
+  [0x20, 0x54, 0x54, 0x54, 0x78], // 'a'
+  [0x7f, 0x48, 0x44, 0x44, 0x38], // 'b'
+  [0x38, 0x44, 0x44, 0x44, 0x20], // 'c'
+  [0x38, 0x44, 0x44, 0x48, 0x7f], // 'd'
+  [0x38, 0x54, 0x54, 0x54, 0x18], // 'e'
+  [0x08, 0x7e, 0x09, 0x01, 0x02], // 'f'
+  [0x0c, 0x52, 0x52, 0x52, 0x3e], // 'g'
+  [0x7f, 0x08, 0x04, 0x04, 0x78], // 'h'
+  [0x00, 0x44, 0x7d, 0x40, 0x00], // 'i'
+  [0x20, 0x40, 0x44, 0x3d, 0x00], // 'j'
+  [0x7f, 0x10, 0x28, 0x44, 0x00], // 'k'
+  [0x00, 0x41, 0x7f, 0x40, 0x00], // 'l'
+  [0x7c, 0x04, 0x18, 0x04, 0x78], // 'm'
+  [0x7c, 0x08, 0x04, 0x04, 0x78], // 'n'
+  [0x38, 0x44, 0x44, 0x44, 0x38], // 'o'
+  [0x7c, 0x14, 0x14, 0x14, 0x08], // 'p'
+  [0x08, 0x14, 0x14, 0x18, 0x7c], // 'q'
+  [0x7c, 0x08, 0x04, 0x04, 0x08], // 'r'
+  [0x48, 0x54, 0x54, 0x54, 0x20], // 's'
+  [0x04, 0x3f, 0x44, 0x40, 0x20], // 't'
+  [0x3c, 0x40, 0x40, 0x20, 0x7c], // 'u'
+  [0x1c, 0x20, 0x40, 0x20, 0x1c], // 'v'
+  [0x3c, 0x40, 0x30, 0x40, 0x3c], // 'w'
+  [0x44, 0x28, 0x10, 0x28, 0x44], // 'x'
+  [0x0c, 0x50, 0x50, 0x50, 0x3c], // 'y'
+  [0x44, 0x64, 0x54, 0x4c, 0x44], // 'z'
+  [0x00, 0x08, 0x36, 0x41, 0x00], // '{'
+  [0x00, 0x00, 0x7f, 0x00, 0x00], // '|'
+  [0x00, 0x41, 0x36, 0x08, 0x00], // '}'
+  [0x10, 0x08, 0x08, 0x10, 0x08], // '~'
+];
+
+/// The glyph drawn for a character. Characters the font does not cover are
+/// drawn as '?'.
+fn glyph(character: char) -> &'static [u8; GLYPH_WIDTH] {
+  let index = |character: char| {
+    (character as usize).wrapping_sub(FIRST_GLYPH as usize)
+  };
+  GLYPHS.get(index(character)).unwrap_or(&GLYPHS[index('?')])
+}
+
+/// A 2D affine transform, as a 2x3 matrix in the layout piston's graphics
+/// crate uses. Like graphics::Transformed, each method applies its change
+/// before the transform it is called on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+  matrix: [[f64; 3]; 2],
+}
+
+impl Default for Transform {
+  fn default() -> Transform {
+    Transform::identity()
+  }
+}
+
+impl Transform {
+  /// The Transform that leaves every point where it is.
+  pub fn identity() -> Transform {
+    Transform { matrix: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0]] }
+  }
+
+  pub fn matrix(&self) -> [[f64; 3]; 2] {
+    self.matrix
+  }
+
+  /// The Transform that applies `other` and then this one.
+  pub fn multiply(&self, other: &Transform) -> Transform {
+    let a = &self.matrix;
+    let b = &other.matrix;
+    Transform {
+      matrix: [
+        [
+          a[0][0] * b[0][0] + a[0][1] * b[1][0],
+          a[0][0] * b[0][1] + a[0][1] * b[1][1],
+          a[0][0] * b[0][2] + a[0][1] * b[1][2] + a[0][2],
+        ],
+        [
+          a[1][0] * b[0][0] + a[1][1] * b[1][0],
+          a[1][0] * b[0][1] + a[1][1] * b[1][1],
+          a[1][0] * b[0][2] + a[1][1] * b[1][2] + a[1][2],
+        ],
+      ],
+    }
+  }
+
+  /// Move by `x` and `y`.
+  pub fn trans(&self, x: f64, y: f64) -> Transform {
+    self.multiply(&Transform { matrix: [[1.0, 0.0, x], [0.0, 1.0, y]] })
+  }
+
+  /// Scale by `factor` about the origin.
+  pub fn zoom(&self, factor: f64) -> Transform {
+    self.multiply(
+      &Transform { matrix: [[factor, 0.0, 0.0], [0.0, factor, 0.0]] },
+    )
+  }
+
+  /// Rotate the x axis to point along the vector (`x`, `y`).
+  pub fn orient(&self, x: f64, y: f64) -> Transform {
+    let length = (x * x + y * y).sqrt();
+    if length == 0.0 {
+      return *self;
+    }
+
+    let (cos, sin) = (x / length, y / length);
+    self.multiply(&Transform { matrix: [[cos, -sin, 0.0], [sin, cos, 0.0]] })
+  }
+
+  /// Where this Transform takes `point`.
+  pub fn apply(&self, point: &Point) -> Point {
+    let m = &self.matrix;
+    Point::new(
+      m[0][0] * point.x + m[0][1] * point.y + m[0][2],
+      m[1][0] * point.x + m[1][1] * point.y + m[1][2],
+    )
+  }
+
+  /// How much this Transform scales areas by, as a factor on lengths.
+  pub fn scale(&self) -> f64 {
+    let m = &self.matrix;
+    (m[0][0] * m[1][1] - m[0][1] * m[1][0]).abs().sqrt()
+  }
+}
+
+/// Something the game can be drawn on. Every shape is positioned through the
+/// renderer's current transform, which starts out as the identity: one unit
+/// per pixel, with the origin in the top-left corner.
+///
+/// Lines are built from rectangles, and text from a batch of triangles, so a
+/// backend need only provide clear, rectangle, circle and triangles.
+pub trait Renderer {
+  fn transform(&self) -> Transform;
+
+  fn set_transform(&mut self, transform: Transform);
+
+  /// Fill the whole surface, ignoring the transform.
+  fn clear(&mut self, color: Color);
+
+  /// Fill the rectangle with its top-left corner at `x`, `y`.
+  fn rectangle(
+    &mut self,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    color: Color,
+  );
+
+  fn circle(&mut self, center: &Point, radius: f64, color: Color);
+
+  /// Fill the triangles made by each three vertices in turn, in one call to
+  /// the backend where it allows.
+  fn triangles(&mut self, vertices: &[Point], color: Color);
+
+  /// Draw a line `width` wide, centered on the segment from `point1` to
+  /// `point2`.
+  fn line(
+    &mut self,
+    point1: &Point,
+    point2: &Point,
+    width: f64,
+    color: Color,
+  ) {
+    let transform = self.transform();
+    let tangent = point2 - point1;
+    self.set_transform(
+      transform
+        .trans(point1.x, point1.y)
+        .orient(tangent.x, tangent.y)
+        .trans(0.0, -width / 2.0),
+    );
+    self.rectangle(0.0, 0.0, tangent.norm(), width, color);
+    self.set_transform(transform);
+  }
+
+  /// Draw a line of text `size` tall, with its top-left corner at `position`,
+  /// in the built-in font. The pixels of every glyph are drawn together, as
+  /// one batch.
+  fn text(&mut self, text: &str, position: &Point, size: f64, color: Color) {
+    let pixel = size / GLYPH_HEIGHT as f64;
+    let mut batch = Batch::new();
+    let mut x = position.x;
+    for character in text.chars() {
+      for (column, bits) in glyph(character).iter().enumerate() {
+        for row in 0..GLYPH_HEIGHT {
+          if bits & (1 << row) != 0 {
+            batch.rectangle(
+              x + column as f64 * pixel,
+              position.y + row as f64 * pixel,
+              pixel,
+              pixel,
+              color,
+            );
+          }
+        }
+      }
+      x += (GLYPH_WIDTH + 1) as f64 * pixel;
+    }
+    batch.draw(self);
+  }
+}
+
+/// Shapes to draw, kept as one buffer of triangles per color so that a whole
+/// track can be drawn with a call to Renderer::triangles for each color.
+/// Shapes of one color are drawn in the order they were added, but all of one
+/// color is drawn before any of the next.
+#[derive(Clone, Debug, Default)]
+pub struct Batch {
+  buffers: Vec<(Color, Vec<Point>)>,
+}
+
+impl Batch {
+  pub fn new() -> Batch {
+    Batch { buffers: Vec::new() }
+  }
+
+  /// The triangles to fill with `color`.
+  fn buffer(&mut self, color: Color) -> &mut Vec<Point> {
+    let index = match self
+      .buffers
+      .iter()
+      .position(|&(buffer_color, _)| buffer_color == color)
+    {
+      Some(index) => index,
+      None => {
+        self.buffers.push((color, Vec::new()));
+        self.buffers.len() - 1
+      },
+    };
+
+    &mut self.buffers[index].1
+  }
+
+  /// Add a `width` by `height` rectangle with its top-left corner at (`x`,
+  /// `y`), as two triangles.
+  pub fn rectangle(
+    &mut self,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    color: Color,
+  ) {
+    let corners = [
+      Point::new(x, y),
+      Point::new(x + width, y),
+      Point::new(x + width, y + height),
+      Point::new(x, y + height),
+    ];
+    self.buffer(color).extend_from_slice(&[
+      corners[0],
+      corners[1],
+      corners[2],
+      corners[0],
+      corners[2],
+      corners[3],
+    ]);
+  }
+
+  /// Add a line `width` wide, centered on the segment from `point1` to
+  /// `point2`, as two triangles.
+  pub fn line(
+    &mut self,
+    point1: &Point,
+    point2: &Point,
+    width: f64,
+    color: Color,
+  ) {
+    let tangent = point2 - point1;
+    let length = tangent.norm();
+    if length == 0.0 {
+      return;
+    }
+
+    let normal = Vector::new(-tangent.y, tangent.x) * (width / 2.0 / length);
+    let corners = [
+      point1 + normal,
+      point2 + normal,
+      point2 - normal,
+      point1 - normal,
+    ];
+    self.buffer(color).extend_from_slice(&[
+      corners[0],
+      corners[1],
+      corners[2],
+      corners[0],
+      corners[2],
+      corners[3],
+    ]);
+  }
+
+  /// Draw everything in the batch, through the renderer's current transform.
+  pub fn draw<R>(&self, renderer: &mut R)
+  where
+    R: Renderer + ?Sized,
+  {
+    for &(color, ref vertices) in self.buffers.iter() {
+      renderer.triangles(vertices, color);
+    }
+  }
+}
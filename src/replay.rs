@@ -0,0 +1,599 @@
+extern crate piston_window;
+extern crate serde_json;
+extern crate std;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use error;
+use event_source;
+use track;
+
+/// The version of the replay format written by Recorder.
+const CURRENT_VERSION: u32 = 3;
+
+/// Serialize numbers as the bits of the f64 they are, so that a replay feeds
+/// the game exactly the times and positions it was recorded with. Written as
+/// decimals, they may read back a little off, and the ride with them.
+mod f64_bits {
+  extern crate serde;
+
+  pub fn serialize<S>(value: &f64, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: serde::Serializer,
+  {
+    use self::serde::Serialize; // serialize
+
+    value.to_bits().serialize(serializer)
+  }
+
+  pub fn deserialize<'de, D>(deserializer: D) -> Result<f64, D::Error>
+  where
+    D: serde::Deserializer<'de>,
+  {
+    use self::serde::Deserialize; // deserialize
+
+    Ok(f64::from_bits(u64::deserialize(deserializer)?))
+  }
+}
+
+/// The first line of a replay: everything needed to start the session over.
+#[derive(Serialize)]
+struct HeaderRef<'track> {
+  version: u32,
+  track_path: &'track std::path::Path,
+  track: &'track track::Track,
+  /// The width and height of the window the session was recorded in.
+  window_size: [u32; 2],
+}
+
+#[derive(Deserialize)]
+struct Header {
+  version: u32,
+  track_path: std::path::PathBuf,
+  track: track::Track,
+  window_size: [u32; 2],
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(tag = "device", rename_all = "snake_case")]
+enum RecordedButton {
+  Keyboard { code: i32 },
+  Mouse { code: u32 },
+  Controller { id: i32, button: u8 },
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum RecordedButtonState {
+  Press,
+  Release,
+}
+
+/// An event, in a form that does not depend on piston's own types. Events the
+/// game never handles, such as controller axes, touches and custom events,
+/// are not recorded.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum RecordedEvent {
+  Button {
+    state: RecordedButtonState,
+    button: RecordedButton,
+    scancode: Option<i32>,
+  },
+  MouseCursor {
+    #[serde(with = "f64_bits")]
+    x: f64,
+    #[serde(with = "f64_bits")]
+    y: f64,
+  },
+  MouseRelative {
+    #[serde(with = "f64_bits")]
+    x: f64,
+    #[serde(with = "f64_bits")]
+    y: f64,
+  },
+  MouseScroll {
+    #[serde(with = "f64_bits")]
+    x: f64,
+    #[serde(with = "f64_bits")]
+    y: f64,
+  },
+  Press { button: RecordedButton },
+  Release { button: RecordedButton },
+  Text { text: String },
+  Resize { width: u32, height: u32 },
+  Focus { focused: bool },
+  Cursor { inside: bool },
+  Close,
+  Render {
+    #[serde(with = "f64_bits")]
+    ext_dt: f64,
+    width: u32,
+    height: u32,
+    draw_width: u32,
+    draw_height: u32,
+  },
+  AfterRender,
+  Update {
+    #[serde(with = "f64_bits")]
+    dt: f64,
+  },
+  Idle {
+    #[serde(with = "f64_bits")]
+    dt: f64,
+  },
+}
+
+/// One line of a replay after the header.
+#[derive(Serialize, Deserialize)]
+struct Record {
+  /// Seconds since recording began.
+  #[serde(with = "f64_bits")]
+  time: f64,
+  /// The active application mode's simulation tick when the event arrived.
+  tick: Option<u64>,
+  event: RecordedEvent,
+}
+
+fn record_button(button: &piston_window::Button) -> RecordedButton {
+  match *button {
+    piston_window::Button::Keyboard(key) => {
+      RecordedButton::Keyboard { code: key.code() }
+    },
+    piston_window::Button::Mouse(mouse_button) => {
+      RecordedButton::Mouse { code: u32::from(mouse_button) }
+    },
+    piston_window::Button::Controller(controller_button) => {
+      RecordedButton::Controller {
+        id: controller_button.id,
+        button: controller_button.button,
+      }
+    },
+  }
+}
+
+fn replay_button(button: &RecordedButton) -> piston_window::Button {
+  match *button {
+    RecordedButton::Keyboard { code } => {
+      piston_window::Button::Keyboard(piston_window::Key::from(code as u32))
+    },
+    RecordedButton::Mouse { code } => {
+      piston_window::Button::Mouse(piston_window::MouseButton::from(code))
+    },
+    RecordedButton::Controller { id, button } => {
+      piston_window::Button::Controller(
+        piston_window::ControllerButton::new(id, button),
+      )
+    },
+  }
+}
+
+/// The RecordedEvent for an event, checked in the same order that
+/// EventHandler::on_event dispatches them.
+fn record_event<Event>(event: &Event) -> Option<RecordedEvent>
+where
+  Event: piston_window::GenericEvent,
+{
+  if let Some(button_args) = event.button_args() {
+    return Some(RecordedEvent::Button {
+      state: match button_args.state {
+        piston_window::ButtonState::Press => RecordedButtonState::Press,
+        piston_window::ButtonState::Release => RecordedButtonState::Release,
+      },
+      button: record_button(&button_args.button),
+      scancode: button_args.scancode,
+    });
+  }
+  if event.controller_axis_args().is_some() {
+    return None;
+  }
+  if let Some(position) = event.mouse_cursor_args() {
+    return Some(RecordedEvent::MouseCursor {
+      x: position[0],
+      y: position[1],
+    });
+  }
+  if let Some(delta) = event.mouse_relative_args() {
+    return Some(RecordedEvent::MouseRelative {
+      x: delta[0],
+      y: delta[1],
+    });
+  }
+  if let Some(scroll) = event.mouse_scroll_args() {
+    return Some(RecordedEvent::MouseScroll {
+      x: scroll[0],
+      y: scroll[1],
+    });
+  }
+  if let Some(button) = event.press_args() {
+    return Some(RecordedEvent::Press { button: record_button(&button) });
+  }
+  if let Some(button) = event.release_args() {
+    return Some(RecordedEvent::Release { button: record_button(&button) });
+  }
+  if let Some(text) = event.text_args() {
+    return Some(RecordedEvent::Text { text: text });
+  }
+  if event.touch_args().is_some() {
+    return None;
+  }
+  if let Some(idle_args) = event.idle_args() {
+    return Some(RecordedEvent::Idle { dt: idle_args.dt });
+  }
+  if let Some(update_args) = event.update_args() {
+    return Some(RecordedEvent::Update { dt: update_args.dt });
+  }
+  if event.after_render_args().is_some() {
+    return Some(RecordedEvent::AfterRender);
+  }
+  if event.close_args().is_some() {
+    return Some(RecordedEvent::Close);
+  }
+  if let Some(inside) = event.cursor_args() {
+    return Some(RecordedEvent::Cursor { inside: inside });
+  }
+  if let Some(focused) = event.focus_args() {
+    return Some(RecordedEvent::Focus { focused: focused });
+  }
+  if let Some(render_args) = event.render_args() {
+    return Some(RecordedEvent::Render {
+      ext_dt: render_args.ext_dt,
+      width: render_args.width,
+      height: render_args.height,
+      draw_width: render_args.draw_width,
+      draw_height: render_args.draw_height,
+    });
+  }
+  if let Some(size) = event.resize_args() {
+    return Some(RecordedEvent::Resize {
+      width: size[0],
+      height: size[1],
+    });
+  }
+
+  None
+}
+
+/// The piston event a RecordedEvent was recorded from.
+fn replay_event(event: &RecordedEvent) -> piston_window::Event {
+  use piston_window::{Event, Input, Loop, Motion};
+
+  match *event {
+    RecordedEvent::Button {
+      state,
+      ref button,
+      scancode,
+    } => Event::Input(Input::Button(piston_window::ButtonArgs {
+      state: match state {
+        RecordedButtonState::Press => piston_window::ButtonState::Press,
+        RecordedButtonState::Release => piston_window::ButtonState::Release,
+      },
+      button: replay_button(button),
+      scancode: scancode,
+    })),
+    RecordedEvent::MouseCursor { x, y } => {
+      Event::Input(Input::Move(Motion::MouseCursor(x, y)))
+    },
+    RecordedEvent::MouseRelative { x, y } => {
+      Event::Input(Input::Move(Motion::MouseRelative(x, y)))
+    },
+    RecordedEvent::MouseScroll { x, y } => {
+      Event::Input(Input::Move(Motion::MouseScroll(x, y)))
+    },
+    RecordedEvent::Press { ref button } => {
+      Event::Input(Input::Press(replay_button(button)))
+    },
+    RecordedEvent::Release { ref button } => {
+      Event::Input(Input::Release(replay_button(button)))
+    },
+    RecordedEvent::Text { ref text } => Event::Input(Input::Text(text.clone())),
+    RecordedEvent::Resize { width, height } => {
+      Event::Input(Input::Resize(width, height))
+    },
+    RecordedEvent::Focus { focused } => Event::Input(Input::Focus(focused)),
+    RecordedEvent::Cursor { inside } => Event::Input(Input::Cursor(inside)),
+    RecordedEvent::Close => {
+      Event::Input(Input::Close(piston_window::CloseArgs))
+    },
+    RecordedEvent::Render {
+      ext_dt,
+      width,
+      height,
+      draw_width,
+      draw_height,
+    } => Event::Loop(Loop::Render(piston_window::RenderArgs {
+      ext_dt: ext_dt,
+      width: width,
+      height: height,
+      draw_width: draw_width,
+      draw_height: draw_height,
+    })),
+    RecordedEvent::AfterRender => {
+      Event::Loop(Loop::AfterRender(piston_window::AfterRenderArgs))
+    },
+    RecordedEvent::Update { dt } => {
+      Event::Loop(Loop::Update(piston_window::UpdateArgs { dt: dt }))
+    },
+    RecordedEvent::Idle { dt } => {
+      Event::Loop(Loop::Idle(piston_window::IdleArgs { dt: dt }))
+    },
+  }
+}
+
+fn seconds(duration: std::time::Duration) -> f64 {
+  duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1.0e-9
+}
+
+/// A simulation tick as described in errors.
+fn describe_tick(tick: Option<u64>) -> String {
+  match tick {
+    Some(tick) => format!("tick {}", tick),
+    None => String::from("no simulation"),
+  }
+}
+
+/// Writes the events of a session to a replay file, one JSON object per line,
+/// after a header holding the track the session began with. Each line is
+/// written as soon as it is recorded, so a replay survives a crash.
+pub struct Recorder {
+  writer: std::io::LineWriter<std::fs::File>,
+  start: std::time::Instant,
+}
+
+impl Recorder {
+  /// Start a replay file at `path` for a session that begins editing `track`,
+  /// which is saved to `track_path`, in a window of `window_size`.
+  pub fn create(
+    path: &std::path::Path,
+    track_path: &std::path::Path,
+    track: &track::Track,
+    window_size: [u32; 2],
+  ) -> error::Result<Recorder> {
+    use std::io::Write; // write_all
+
+    let mut writer = std::io::LineWriter::new(std::fs::File::create(path)?);
+    serde_json::to_writer(
+      &mut writer,
+      &HeaderRef {
+        version: CURRENT_VERSION,
+        track_path: track_path,
+        track: track,
+        window_size: window_size,
+      },
+    )?;
+    writer.write_all(b"\n")?;
+
+    Ok(Recorder {
+      writer: writer,
+      start: std::time::Instant::now(),
+    })
+  }
+
+  /// Record an event, along with the simulation tick it arrived on.
+  pub fn record<Event>(
+    &mut self,
+    event: &Event,
+    tick: Option<u64>,
+  ) -> error::Result<()>
+  where
+    Event: piston_window::GenericEvent,
+  {
+    use std::io::Write; // write_all
+
+    let event = match record_event(event) {
+      Some(event) => event,
+      None => return Ok(()),
+    };
+    let record = Record {
+      time: seconds(self.start.elapsed()),
+      tick: tick,
+      event: event,
+    };
+    serde_json::to_writer(&mut self.writer, &record)?;
+    self.writer.write_all(b"\n")?;
+
+    Ok(())
+  }
+}
+
+/// A replay file opened for playback.
+pub struct Replay {
+  lines: std::io::Lines<std::io::BufReader<std::fs::File>>,
+  line_number: usize,
+  header: Header,
+}
+
+impl Replay {
+  /// Open the replay file at `path` and read its header.
+  pub fn open(path: &std::path::Path) -> error::Result<Replay> {
+    use std::io::BufRead; // lines
+
+    let file = std::fs::File::open(path)?;
+    let mut lines = std::io::BufReader::new(file).lines();
+    let header: Header = match lines.next() {
+      Some(line) => serde_json::from_str(&line?)?,
+      None => return Err(error::Error::from("Replay is empty")),
+    };
+    if header.version != CURRENT_VERSION {
+      return Err(error::Error::from(
+        format!("Unsupported replay version {}", header.version),
+      ));
+    }
+
+    Ok(Replay {
+      lines: lines,
+      line_number: 1,
+      header: header,
+    })
+  }
+
+  /// The path the recorded session saved its track to.
+  pub fn track_path(&self) -> &std::path::Path {
+    &self.header.track_path
+  }
+
+  /// The track the recorded session began with.
+  pub fn track(&self) -> &track::Track {
+    &self.header.track
+  }
+
+  /// The width and height of the window the session was recorded in, which
+  /// the replay should be shown in too.
+  pub fn window_size(&self) -> [u32; 2] {
+    self.header.window_size
+  }
+
+  /// The next record in the file, if any.
+  fn next_record(&mut self) -> error::Result<Option<Record>> {
+    use error::ResultExt; // chain_err
+
+    while let Some(line) = self.lines.next() {
+      let line = line?;
+      self.line_number += 1;
+      if line.trim().is_empty() {
+        continue;
+      }
+
+      let line_number = self.line_number;
+      let record = serde_json::from_str(&line).chain_err(|| {
+        format!("Invalid event on line {} of replay", line_number)
+      })?;
+      return Ok(Some(record));
+    }
+
+    Ok(None)
+  }
+}
+
+/// An EventSource that plays back a Replay at the pace it was recorded.
+/// The window's own events are discarded, so only recorded input reaches the
+/// game, but the window is still drawn to and can be closed to stop early.
+///
+/// Each event is checked to arrive on the simulation tick it was recorded on,
+/// so a replay that no longer rides the same way stops with an error rather
+/// than carrying on with input meant for a different ride.
+pub struct ReplaySource<Window>
+where
+  Window: piston_window::Window,
+{
+  replay: Replay,
+  window: Rc<RefCell<piston_window::PistonWindow<Window>>>,
+  start: std::time::Instant,
+  rendered: bool,
+  /// The tick recorded with the last event returned.
+  tick: Option<u64>,
+}
+
+impl<Window> ReplaySource<Window>
+where
+  Window: piston_window::Window,
+{
+  /// Play back `replay` in `window`.
+  pub fn new(
+    replay: Replay,
+    window: Rc<RefCell<piston_window::PistonWindow<Window>>>,
+  ) -> ReplaySource<Window> {
+    ReplaySource {
+      replay: replay,
+      window: window,
+      start: std::time::Instant::now(),
+      rendered: false,
+      tick: None,
+    }
+  }
+}
+
+impl<Window> event_source::EventSource for ReplaySource<Window>
+where
+  Window: piston_window::Window,
+{
+  fn next_event(&mut self) -> error::Result<Option<piston_window::Event>> {
+    use piston_window::Window; // poll_event, should_close, swap_buffers
+
+    {
+      let mut window = self.window.borrow_mut();
+      // PistonWindow::next would show the last frame drawn, so do the same.
+      if self.rendered {
+        window.swap_buffers();
+        self.rendered = false;
+      }
+      while let Some(_) = window.poll_event() {}
+      if window.should_close() {
+        return Ok(None);
+      }
+    }
+
+    let record = match self.replay.next_record()? {
+      Some(record) => record,
+      None => return Ok(None),
+    };
+
+    let elapsed = seconds(self.start.elapsed());
+    if record.time > elapsed {
+      let delay = record.time - elapsed;
+      std::thread::sleep(std::time::Duration::new(
+        delay.trunc() as u64,
+        (delay.fract() * 1.0e9) as u32,
+      ));
+    }
+
+    if let RecordedEvent::Render { .. } = record.event {
+      self.rendered = true;
+    }
+    self.tick = record.tick;
+    Ok(Some(replay_event(&record.event)))
+  }
+
+  fn check_tick(&mut self, tick: Option<u64>) -> error::Result<()> {
+    if tick != self.tick {
+      return Err(error::Error::from(format!(
+        "Replay diverged on line {}: the event was recorded at {}, but is \
+         replayed at {}",
+        self.replay.line_number,
+        describe_tick(self.tick),
+        describe_tick(tick)
+      )));
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use simulation;
+  use track::Point;
+
+  #[test]
+  fn replayed_updates_ride_exactly_as_recorded() {
+    let track = track::Track::with_line(
+      Point::new(0.0, 150.0),
+      Point::new(1000.0, 400.0),
+    );
+    let mut recorded = simulation::Simulation::new(&track);
+    let mut replayed = simulation::Simulation::new(&track);
+
+    let mut time = 0.0;
+    for index in 0..300 {
+      // Frame times that jitter, as a real window's do, and that have no
+      // short decimal form.
+      let dt = 1.0 / 60.0 + (index % 7) as f64 / 3.0e4;
+      time += dt;
+      let line = serde_json::to_string(&Record {
+        time: time,
+        tick: Some(recorded.tick()),
+        event: RecordedEvent::Update { dt: dt },
+      }).unwrap();
+      recorded.update(dt);
+
+      let record: Record = serde_json::from_str(&line).unwrap();
+      assert_eq!(record.time.to_bits(), time.to_bits());
+      match record.event {
+        RecordedEvent::Update { dt } => replayed.update(dt),
+        _ => panic!("Update replayed as {:?}", record.event),
+      }
+      assert_eq!(replayed.checksum(), recorded.checksum());
+    }
+  }
+}
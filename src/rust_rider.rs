@@ -1,98 +1,991 @@
-extern crate graphics;
 extern crate nalgebra;
 extern crate piston;
 extern crate piston_window;
 
 use std::cell::RefCell;
+use std::collections::BTreeSet;
 use std::rc::Rc;
 
+use camera;
 use error;
+use geometry;
 use handler;
+use history;
+use keybindings;
+use keybindings::Action;
+use piston_renderer;
+use render;
+use scene::{draw_visible_lines, line_kind_color, BLUE, GREEN, LINE_WIDTH, RED,
+            STATUS_BAR_HEIGHT, WHITE};
+use snap;
+use spatial_index;
+use track;
+use track::{Endpoint, LineKind, LineSegment, Point, Vector};
+use track_file;
 
 enum EditMode {
   Insert,
   Select,
 }
 
-type Point = nalgebra::Point2<f64>;
-type Vector = nalgebra::Vector2<f64>;
+impl Default for EditMode {
+  fn default() -> EditMode {
+    EditMode::Insert
+  }
+}
 
-fn draw_line_segment<G>(
-  point1: &Point,
-  point2: &Point,
-  context: &piston_window::Context,
-  graphics: &mut G,
-) where
-  G: graphics::Graphics,
-{
-  use self::graphics::Transformed; // piston_window::Context.{trans,orient}
-
-  let tangent = point2 - point1;
-  let width = nalgebra::distance(point1, point2);
-  let height = 4.0;
-  piston_window::rectangle(
-    BLACK,
-    [0.0, 0.0, width, height],
-    context
-      .trans(point1.x, point1.y - height / 2.0)
-      .orient(tangent.x, tangent.y)
-      .transform,
-    graphics,
-  );
+/// How lines are drawn in EditMode::Insert.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Tool {
+  /// A straight line from where the mouse is pressed to where it is released.
+  Line,
+  /// Connected lines following the cursor for as long as the mouse is
+  /// pressed.
+  Pencil,
+  /// A curve of the given shape, from where the mouse is pressed to where it
+  /// is released, which can then be reshaped by its handles.
+  Curve(CurveTool),
 }
 
-struct LineSegment {
-  point1: Point,
-  point2: Point,
+/// The shapes of curve that can be drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CurveTool {
+  Quadratic,
+  Cubic,
+  Arc,
 }
 
-impl LineSegment {
-  pub fn new(point1: Point, point2: Point) -> LineSegment {
-    LineSegment {
-      point1: point1,
-      point2: point2,
+impl Default for Tool {
+  fn default() -> Tool {
+    Tool::Line
+  }
+}
+
+impl Tool {
+  /// The name shown in the edit bar.
+  fn name(&self) -> &'static str {
+    match *self {
+      Tool::Line => "line",
+      Tool::Pencil => "pencil",
+      Tool::Curve(CurveTool::Quadratic) => "quadratic",
+      Tool::Curve(CurveTool::Cubic) => "cubic",
+      Tool::Curve(CurveTool::Arc) => "arc",
     }
   }
+}
 
-  pub fn draw<G>(&self, context: &piston_window::Context, graphics: &mut G)
-  where
-    G: graphics::Graphics,
-  {
-    draw_line_segment(&self.point1, &self.point2, context, graphics);
+impl CurveTool {
+  /// The tool that draws curves of the same type as `shape`.
+  fn of_shape(shape: &track::CurveShape) -> CurveTool {
+    match *shape {
+      track::CurveShape::Quadratic { .. } => CurveTool::Quadratic,
+      track::CurveShape::Cubic { .. } => CurveTool::Cubic,
+      track::CurveShape::Arc { .. } => CurveTool::Arc,
+    }
+  }
+
+  /// A curve of this shape from `start` to `end`, with its other handles
+  /// placed between them.
+  fn shape(&self, start: Point, end: Point) -> track::CurveShape {
+    let chord = end - start;
+    match *self {
+      CurveTool::Quadratic => track::CurveShape::Quadratic {
+        start: start,
+        control: start + chord / 2.0,
+        end: end,
+      },
+      CurveTool::Cubic => track::CurveShape::Cubic {
+        start: start,
+        control1: start + chord / 3.0,
+        control2: start + chord * (2.0 / 3.0),
+        end: end,
+      },
+      // Bulge to one side, so that the arc starts out curved.
+      CurveTool::Arc => track::CurveShape::Arc {
+        start: start,
+        through: start + chord / 2.0 +
+          Vector::new(-chord.y, chord.x) * ARC_BULGE,
+        end: end,
+      },
+    }
   }
 }
 
-const BLACK: piston_window::types::Color = [0.0, 0.0, 0.0, 1.0];
-const GREEN: piston_window::types::Color = [0.0, 1.0, 0.0, 1.0];
-const BLUE: piston_window::types::Color = [0.0, 0.0, 1.0, 1.0];
+/// A curve being drawn, or reshaped, in EditMode::Insert. The track is only
+/// changed once the curve is committed.
+struct ActiveCurve {
+  curve: track::Curve,
+  /// The ID of the curve being reshaped, if it is already on the track.
+  id: Option<u64>,
+  /// Whether the end of a new curve is still following the cursor.
+  drawing: bool,
+  /// The index of the handle following the cursor, if any.
+  dragged_handle: Option<usize>,
+}
+
+const TRANSLUCENT_BLUE: render::Color = [0.0, 0.0, 1.0, 0.2];
+const TRANSLUCENT_ORANGE: render::Color = [1.0, 0.5, 0.0, 0.6];
+const GRID_COLOR: render::Color = [0.0, 0.0, 0.0, 0.1];
+
+/// The height, in screen pixels, of the text in the edit bar.
+const EDIT_BAR_TEXT_SIZE: f64 = 14.0;
+
+/// How far from a line segment, in screen pixels, a click still selects it.
+const SELECT_RADIUS: f64 = 6.0;
+/// The radius, in screen pixels, of the handles drawn on a selected segment's
+/// endpoints. A click within a handle drags that endpoint.
+const HANDLE_RADIUS: f64 = 6.0;
+/// How far, in screen pixels, the lines of a pencil stroke may stray from the
+/// path the cursor took.
+const PENCIL_TOLERANCE: f64 = 2.0;
+/// How many line segments a curve is tessellated into unless changed.
+const DEFAULT_CURVE_SEGMENT_COUNT: usize = 16;
+/// How far a new arc bulges out from the line between its ends, as a
+/// fraction of that line's length.
+const ARC_BULGE: f64 = 0.25;
+/// How wide, in screen pixels, the lines from a curve's ends to its control
+/// points are drawn.
+const GUIDE_WIDTH: f64 = 1.0;
+/// How wide, in screen pixels, grid lines are drawn.
+const GRID_LINE_WIDTH: f64 = 1.0;
+/// The closest together, in screen pixels, grid lines are drawn. When zoomed
+/// out further, only every second, fourth, and so on, line is drawn.
+const MIN_GRID_SPACING: f64 = 8.0;
+/// The radius, in screen pixels, of the marker on the point the cursor has
+/// snapped to.
+const SNAP_INDICATOR_RADIUS: f64 = 5.0;
 
-/// The game-state of the Rust Rider game. The state should act as the save data
-/// for a resumable session of the game.
+/// The name of the application mode in which the track is edited.
+pub const EDIT_APPLICATION_MODE: &'static str = "rust_rider";
+/// The name of the application mode in which the rider rides the track.
+pub const PLAY_APPLICATION_MODE: &'static str = "play";
+
+/// A drag in progress in EditMode::Select, started by pressing the left mouse
+/// button. Each variant remembers what it looked like when the drag started,
+/// so every cursor movement is applied relative to that and not accumulated.
+enum Drag {
+  /// Move every selected segment along with the cursor.
+  Move {
+    origin: Point,
+    originals: Vec<(usize, LineSegment)>,
+  },
+  /// Move one endpoint of one segment along with the cursor.
+  Endpoint {
+    index: usize,
+    endpoint: Endpoint,
+    original: LineSegment,
+  },
+  /// Select every segment that ends up inside the box.
+  Box { origin: Point },
+}
+
+/// The game-state of the Rust Rider game. The track is the save data for a
+/// resumable session of the game; everything else describes the editor's
+/// in-progress interaction with it.
 pub struct State {
+  track: track::Track,
   edit_mode: EditMode,
-  line_segments: Vec<LineSegment>,
+  tool: Tool,
+  line_kind: LineKind,
   active_line_segment: Option<Point>,
+  /// Every position the cursor has been at since the pencil was pressed.
+  pencil_stroke: Option<Vec<Point>>,
+  active_curve: Option<ActiveCurve>,
+  /// How many line segments new curves are tessellated into.
+  curve_segment_count: usize,
+  snap_settings: snap::SnapSettings,
+  snap_modes: snap::SnapModes,
+  /// Where lines being drawn start or end: the mouse position, snapped.
+  snap: snap::Snap,
+  selection: BTreeSet<usize>,
+  drag: Option<Drag>,
+  history: history::History,
+  /// Finds the track's line segments near a point or in a box. Kept in step
+  /// with every change to them.
+  spatial_index: spatial_index::SpatialIndex,
+  camera: camera::Camera,
+  /// The cursor's position in screen coordinates.
+  cursor_position: Point,
+  /// The cursor's position in world coordinates.
   mouse_position: Point,
 }
 
 impl State {
   /// Create a State with default values for a new game.
   pub fn new() -> State {
+    State::new_with_track(track::Track::new())
+  }
+
+  /// Create a State that edits an existing track.
+  pub fn new_with_track(track: track::Track) -> State {
+    let spatial_index =
+      spatial_index::SpatialIndex::from_line_segments(&track.line_segments);
     State {
+      track: track,
       edit_mode: EditMode::Insert,
-      line_segments: Vec::new(),
+      tool: Tool::Line,
+      line_kind: LineKind::Normal,
       active_line_segment: None,
+      pencil_stroke: None,
+      active_curve: None,
+      curve_segment_count: DEFAULT_CURVE_SEGMENT_COUNT,
+      snap_settings: snap::SnapSettings::default(),
+      snap_modes: snap::SnapModes::default(),
+      snap: snap::Snap::none(Point::new(0.0, 0.0)),
+      selection: BTreeSet::new(),
+      drag: None,
+      history: history::History::default(),
+      spatial_index: spatial_index,
+      camera: camera::Camera::new(),
+      cursor_position: Point::new(0.0, 0.0),
       mouse_position: Point::new(0.0, 0.0),
     }
   }
+
+  pub fn track(&self) -> &track::Track {
+    &self.track
+  }
+
+  /// Start editing another track. Selections and history refer to the old
+  /// track's segments, so they are discarded; the view is kept.
+  pub fn replace_track(&mut self, track: track::Track) {
+    self.spatial_index =
+      spatial_index::SpatialIndex::from_line_segments(&track.line_segments);
+    self.track = track;
+    self.active_line_segment = None;
+    self.pencil_stroke = None;
+    self.active_curve = None;
+    self.selection.clear();
+    self.drag = None;
+    self.history = history::History::default();
+  }
+
+  /// Finds the track's line segments by where they are.
+  pub fn spatial_index(&self) -> &spatial_index::SpatialIndex {
+    &self.spatial_index
+  }
+
+  /// The editor's view onto the track.
+  pub fn camera(&self) -> &camera::Camera {
+    &self.camera
+  }
+
+  /// Change how the cursor snaps while drawing. Grid snapping is turned on or
+  /// off as the settings say.
+  pub fn set_snap_settings(&mut self, snap_settings: snap::SnapSettings) {
+    self.snap_modes.grid = snap_settings.grid;
+    self.snap_settings = snap_settings;
+    self.snap = self.snap_mouse_position();
+  }
+
+  /// Snap the mouse position for drawing. Only drawing in EditMode::Insert
+  /// snaps, and only the line being drawn snaps to fixed angles.
+  fn snap_mouse_position(&self) -> snap::Snap {
+    if let EditMode::Select = self.edit_mode {
+      return snap::Snap::none(self.mouse_position);
+    }
+
+    let curve_start = match self.active_curve {
+      Some(ref active) if active.drawing => {
+        Some(active.curve.shape.handles()[0])
+      },
+      _ => None,
+    };
+    let anchor = self.active_line_segment.or(curve_start);
+    snap::snap(
+      &self.mouse_position,
+      anchor.as_ref(),
+      self.camera.zoom(),
+      &self.snap_settings,
+      &self.snap_modes,
+      &self.track.line_segments,
+      &self.spatial_index,
+    )
+  }
+
+  fn toggle_grid_snap(&mut self) {
+    self.snap_modes.grid = !self.snap_modes.grid;
+    self.snap = self.snap_mouse_position();
+  }
+
+  /// Snap lines to the next configured angle, or stop snapping them after
+  /// the last.
+  fn cycle_angle_snap(&mut self) {
+    let next = {
+      let angle_steps = &self.snap_settings.angle_steps;
+      let index = match self.snap_modes.angle_step {
+        Some(angle_step) => angle_steps
+          .iter()
+          .position(|&step| step == angle_step)
+          .map_or(0, |index| index + 1),
+        None => 0,
+      };
+      angle_steps.get(index).cloned()
+    };
+    self.snap_modes.angle_step = next;
+    self.snap = self.snap_mouse_position();
+  }
+
+  /// Move the cursor to a screen position, and continue any drag or pencil
+  /// stroke in progress.
+  fn move_cursor(&mut self, cursor_position: Point) {
+    self.cursor_position = cursor_position;
+    self.mouse_position = self.camera.to_world(&cursor_position);
+    self.snap = self.snap_mouse_position();
+    self.update_drag();
+
+    let mouse_position = self.mouse_position;
+    if let Some(ref mut stroke) = self.pencil_stroke {
+      if stroke.last() != Some(&mouse_position) {
+        stroke.push(mouse_position);
+      }
+    }
+
+    let snapped = self.snap.point;
+    if let Some(ref mut active) = self.active_curve {
+      if active.drawing {
+        let start = active.curve.shape.handles()[0];
+        active.curve.shape =
+          CurveTool::of_shape(&active.curve.shape).shape(start, snapped);
+      } else if let Some(index) = active.dragged_handle {
+        active.curve.shape = active.curve.shape.with_handle(index, snapped);
+      }
+    }
+  }
+
+  /// Make a change to the line segments that can later be undone.
+  fn execute(&mut self, command: history::Command) {
+    self.history.execute(
+      command,
+      &mut self.track.line_segments,
+      &mut self.spatial_index,
+    );
+  }
+
+  /// Undo the most recent change to the line segments. Indices change, so the
+  /// selection is discarded.
+  fn undo(&mut self) {
+    if self.drag.is_none() &&
+      self
+        .history
+        .undo(&mut self.track.line_segments, &mut self.spatial_index)
+    {
+      self.selection.clear();
+    }
+  }
+
+  /// Redo the most recently undone change to the line segments.
+  fn redo(&mut self) {
+    if self.drag.is_none() &&
+      self
+        .history
+        .redo(&mut self.track.line_segments, &mut self.spatial_index)
+    {
+      self.selection.clear();
+    }
+  }
+
+  /// Add a new segment to the end of the track.
+  fn push_line_segment(&mut self, line_segment: LineSegment) {
+    let index = self.track.line_segments.len();
+    self.execute(history::Command::Insert(vec![(index, line_segment)]));
+  }
+
+  /// Start drawing with the current tool at the mouse position. While a curve
+  /// is active, pressing on one of its handles drags the handle, and pressing
+  /// anywhere else commits the curve.
+  fn begin_line(&mut self) {
+    self.selection.clear();
+    // Select may have been released since the cursor last moved.
+    self.snap = self.snap_mouse_position();
+    let snapped = self.snap.point;
+
+    if self.active_curve.is_some() {
+      match self.curve_handle_at(&self.mouse_position) {
+        Some(index) => {
+          if let Some(ref mut active) = self.active_curve {
+            active.dragged_handle = Some(index);
+          }
+        },
+        None => self.commit_curve(),
+      }
+      return;
+    }
+
+    match self.tool {
+      Tool::Line => {
+        self.active_line_segment = Some(snapped);
+      },
+      Tool::Pencil => {
+        self.pencil_stroke = Some(vec![snapped]);
+      },
+      Tool::Curve(tool) => {
+        self.active_curve = Some(ActiveCurve {
+          curve: track::Curve {
+            shape: tool.shape(snapped, snapped),
+            segment_count: self.curve_segment_count,
+            kind: self.line_kind,
+          },
+          id: None,
+          drawing: true,
+          dragged_handle: None,
+        });
+      },
+    }
+  }
+
+  /// The handle of the active curve that contains `point`, if any.
+  fn curve_handle_at(&self, point: &Point) -> Option<usize> {
+    let handle_radius = HANDLE_RADIUS / self.camera.zoom();
+    match self.active_curve {
+      Some(ref active) => active
+        .curve
+        .shape
+        .handles()
+        .iter()
+        .position(|handle| nalgebra::distance(handle, point) <= handle_radius),
+      None => None,
+    }
+  }
+
+  /// Start reshaping the curve that the segment under `point` is part of, if
+  /// any, committing the active curve first.
+  fn edit_curve_at(&mut self, point: &Point) {
+    if self.drag.is_some() {
+      return;
+    }
+    self.commit_curve();
+
+    let radius = SELECT_RADIUS / self.camera.zoom();
+    let id = match self.line_segment_at(point, radius) {
+      Some(index) => self.track.line_segments[index].curve(),
+      None => None,
+    };
+    let curve = id.and_then(|id| self.track.curves.get(&id)).cloned();
+    if let Some(curve) = curve {
+      self.selection.clear();
+      self.curve_segment_count = curve.segment_count;
+      self.active_curve = Some(ActiveCurve {
+        curve: curve,
+        id: id,
+        drawing: false,
+        dragged_handle: None,
+      });
+    }
+  }
+
+  /// Change how many line segments the active curve, and the curves drawn
+  /// after it, are tessellated into.
+  fn change_curve_segment_count(&mut self, more: bool) {
+    self.curve_segment_count = if more {
+      (self.curve_segment_count + 1).min(track::MAX_CURVE_SEGMENT_COUNT)
+    } else {
+      self.curve_segment_count.saturating_sub(1).max(1)
+    };
+    if let Some(ref mut active) = self.active_curve {
+      active.curve.segment_count = self.curve_segment_count;
+    }
+  }
+
+  /// Add the active curve to the track as one change, tessellated into line
+  /// segments. A reshaped curve's old segments are replaced.
+  fn commit_curve(&mut self) {
+    let active = match self.active_curve.take() {
+      Some(active) => active,
+      None => return,
+    };
+
+    let mut commands = Vec::new();
+    let mut first_index = self.track.line_segments.len();
+    if let Some(old_id) = active.id {
+      let removals: Vec<_> = self
+        .track
+        .line_segments
+        .iter()
+        .enumerate()
+        .filter(|&(_, line_segment)| line_segment.curve() == Some(old_id))
+        .map(|(index, line_segment)| (index, line_segment.clone()))
+        .collect();
+      first_index -= removals.len();
+      commands.push(history::Command::Remove(removals));
+    }
+
+    let id = self.track.next_curve_id();
+    let insertions = active
+      .curve
+      .line_segments(id)
+      .into_iter()
+      .enumerate()
+      .map(|(offset, line_segment)| (first_index + offset, line_segment))
+      .collect();
+    commands.push(history::Command::Insert(insertions));
+
+    self.track.curves.insert(id, active.curve);
+    self.execute(history::Command::Batch(commands));
+  }
+
+  /// Finish drawing at the mouse position. Everything drawn is added to the
+  /// track as one change, so a pencil stroke is undone all at once. A curve
+  /// stays active so that its handles can be dragged.
+  fn end_line(&mut self) {
+    let mut cancel_curve = false;
+    if let Some(ref mut active) = self.active_curve {
+      if active.drawing {
+        active.drawing = false;
+        // A click without a drag draws nothing.
+        let handles = active.curve.shape.handles();
+        cancel_curve = handles.first() == handles.last();
+      }
+      active.dragged_handle = None;
+    }
+    if cancel_curve {
+      self.active_curve = None;
+    }
+
+    let snapped = self.snap.point;
+    let line_kind = self.line_kind;
+
+    if let Some(point1) = self.active_line_segment.take() {
+      self.push_line_segment(LineSegment::new(point1, snapped, line_kind));
+    }
+
+    if let Some(mut stroke) = self.pencil_stroke.take() {
+      // A stroke ends where the cursor snapped to, so that it can join up
+      // with other lines.
+      if stroke.last() != Some(&snapped) {
+        stroke.push(snapped);
+      }
+      let tolerance = PENCIL_TOLERANCE / self.camera.zoom();
+      let points = geometry::simplify_polyline(&stroke, tolerance);
+      let first_index = self.track.line_segments.len();
+      let insertions: Vec<_> = points
+        .windows(2)
+        .enumerate()
+        .map(|(offset, pair)| {
+          (first_index + offset, LineSegment::new(pair[0], pair[1], line_kind))
+        })
+        .collect();
+      if !insertions.is_empty() {
+        self.execute(history::Command::Insert(insertions));
+      }
+    }
+  }
+
+  /// The index of the segment nearest `point`, if any is within `radius`.
+  fn line_segment_at(&self, point: &Point, radius: f64) -> Option<usize> {
+    let mut nearest = None;
+    let mut nearest_distance = radius;
+    for index in self.spatial_index.query_point(point, radius) {
+      let distance = self.track.line_segments[index].distance_to(point);
+      if distance <= nearest_distance {
+        nearest = Some(index);
+        nearest_distance = distance;
+      }
+    }
+
+    nearest
+  }
+
+  /// The selected segment endpoint whose handle contains `point`, if any.
+  fn selected_endpoint_at(&self, point: &Point) -> Option<(usize, Endpoint)> {
+    let handle_radius = HANDLE_RADIUS / self.camera.zoom();
+    for &index in self.selection.iter() {
+      for &endpoint in [Endpoint::Point1, Endpoint::Point2].iter() {
+        let handle = self.track.line_segments[index].endpoint(endpoint);
+        if nalgebra::distance(handle, point) <= handle_radius {
+          return Some((index, endpoint));
+        }
+      }
+    }
+
+    None
+  }
+
+  /// Begin a Drag at the mouse position. Pressing on a selected endpoint drags
+  /// that endpoint, pressing on a segment selects and moves it, and pressing
+  /// on empty space starts a box selection.
+  fn begin_drag(&mut self) {
+    let origin = self.mouse_position;
+
+    if let Some((index, endpoint)) = self.selected_endpoint_at(&origin) {
+      self.drag = Some(Drag::Endpoint {
+        index: index,
+        endpoint: endpoint,
+        original: self.track.line_segments[index].clone(),
+      });
+      return;
+    }
+
+    match self.line_segment_at(&origin, SELECT_RADIUS / self.camera.zoom()) {
+      Some(index) => {
+        if !self.selection.contains(&index) {
+          self.selection.clear();
+          self.selection.insert(index);
+        }
+        let originals = self
+          .selection
+          .iter()
+          .map(|&index| (index, self.track.line_segments[index].clone()))
+          .collect();
+        self.drag = Some(Drag::Move {
+          origin: origin,
+          originals: originals,
+        });
+      },
+      None => {
+        self.selection.clear();
+        self.drag = Some(Drag::Box { origin: origin });
+      },
+    }
+  }
+
+  /// Move the segment at `index` without recording the change.
+  fn set_line_segment(&mut self, index: usize, line_segment: LineSegment) {
+    self.spatial_index.replace(
+      index,
+      *line_segment.point1(),
+      *line_segment.point2(),
+    );
+    self.track.line_segments[index] = line_segment;
+  }
+
+  /// Apply the Drag in progress for the current mouse position.
+  fn update_drag(&mut self) {
+    let mouse_position = self.mouse_position;
+
+    let moved: Vec<(usize, LineSegment)> = match self.drag {
+      Some(Drag::Move {
+        ref origin,
+        ref originals,
+      }) => {
+        let offset = mouse_position - origin;
+        originals
+          .iter()
+          .map(|&(index, ref original)| (index, original.translated(&offset)))
+          .collect()
+      },
+      Some(Drag::Endpoint {
+        index,
+        endpoint,
+        ref original,
+      }) => vec![(index, original.with_endpoint(endpoint, mouse_position))],
+      Some(Drag::Box { .. }) | None => Vec::new(),
+    };
+    for (index, line_segment) in moved.into_iter() {
+      self.set_line_segment(index, line_segment);
+    }
+  }
+
+  /// Finish the Drag in progress, if any. A drag that changed the track is
+  /// recorded so that it can be undone in one step; a click that only selected
+  /// a segment is not.
+  fn end_drag(&mut self) {
+    self.update_drag();
+
+    match self.drag.take() {
+      Some(Drag::Move { originals, .. }) => {
+        let replacements: Vec<_> = originals
+          .into_iter()
+          .map(|(index, original)| {
+            (index, original, self.track.line_segments[index].clone())
+          })
+          .filter(|&(_, ref before, ref after)| before != after)
+          .collect();
+        if !replacements.is_empty() {
+          self.history.record(history::Command::Replace(replacements));
+        }
+      },
+      Some(Drag::Endpoint {
+        index, original, ..
+      }) => {
+        let replacement = self.track.line_segments[index].clone();
+        if replacement != original {
+          self.history.record(history::Command::Replace(
+            vec![(index, original, replacement)],
+          ));
+        }
+      },
+      Some(Drag::Box { origin }) => {
+        let selection_box =
+          geometry::Aabb::from_corners(&origin, &self.mouse_position);
+        let line_segments = &self.track.line_segments;
+        self.selection = self
+          .spatial_index
+          .query_aabb(&selection_box)
+          .into_iter()
+          .filter(|&index| {
+            selection_box.contains(line_segments[index].point1()) &&
+              selection_box.contains(line_segments[index].point2())
+          })
+          .collect();
+      },
+      None => {},
+    }
+  }
+
+  /// Remove every selected segment from the track.
+  fn delete_selection(&mut self) {
+    if self.drag.is_some() {
+      return;
+    }
+
+    if self.selection.is_empty() {
+      return;
+    }
+
+    let removals = self
+      .selection
+      .iter()
+      .map(|&index| (index, self.track.line_segments[index].clone()))
+      .collect();
+    self.execute(history::Command::Remove(removals));
+    self.selection.clear();
+  }
+
+  /// Draw the track being edited, as seen through the camera, with the edit
+  /// bar along the bottom of a screen of the given size.
+  pub fn draw<R>(&self, renderer: &mut R, screen_size: &Vector)
+  where
+    R: render::Renderer,
+  {
+    renderer.clear(WHITE);
+
+    // The track is drawn in world coordinates, and the edit bar over it in
+    // screen coordinates.
+    renderer.set_transform(self.camera.transform());
+
+    if self.snap_modes.grid {
+      self.draw_grid(renderer, screen_size);
+    }
+
+    draw_visible_lines(
+      renderer,
+      &self.track,
+      &self.spatial_index,
+      &self.camera,
+      screen_size,
+    );
+
+    // What is being drawn goes over the track it is drawn onto.
+    if let Some(point1) = self.active_line_segment {
+      renderer.line(
+        &point1,
+        &self.snap.point,
+        LINE_WIDTH,
+        line_kind_color(self.line_kind),
+      );
+    }
+
+    if let Some(ref stroke) = self.pencil_stroke {
+      let mut batch = render::Batch::new();
+      for pair in stroke.windows(2) {
+        batch.line(
+          &pair[0],
+          &pair[1],
+          LINE_WIDTH,
+          line_kind_color(self.line_kind),
+        );
+      }
+      batch.draw(renderer);
+    }
+
+    if let Some(ref active) = self.active_curve {
+      self.draw_active_curve(renderer, active);
+    }
+
+    let handle_radius = HANDLE_RADIUS / self.camera.zoom();
+    for &index in self.selection.iter() {
+      let line = &self.track.line_segments[index];
+      renderer.line(line.point1(), line.point2(), LINE_WIDTH, BLUE);
+      for point in [line.point1(), line.point2()].iter() {
+        renderer.circle(point, handle_radius, TRANSLUCENT_BLUE);
+      }
+    }
+
+    if let Some(Drag::Box { ref origin }) = self.drag {
+      let selection_box =
+        geometry::Aabb::from_corners(origin, &self.mouse_position);
+      renderer.rectangle(
+        selection_box.min.x,
+        selection_box.min.y,
+        selection_box.width(),
+        selection_box.height(),
+        TRANSLUCENT_BLUE,
+      );
+    }
+
+    renderer.circle(&self.track.rider_start, 4.0, RED);
+
+    if let EditMode::Insert = self.edit_mode {
+      self.draw_snap(renderer);
+    }
+
+    renderer.set_transform(render::Transform::identity());
+
+    let edit_bar_color = match self.edit_mode {
+      EditMode::Insert => GREEN,
+      EditMode::Select => BLUE,
+    };
+    let edit_bar_y_offset = screen_size.y - STATUS_BAR_HEIGHT;
+    renderer.rectangle(
+      0.0,
+      edit_bar_y_offset,
+      screen_size.x,
+      STATUS_BAR_HEIGHT,
+      edit_bar_color,
+    );
+
+    // A swatch at the start of the edit bar shows the kind of line drawn.
+    let swatch_margin = 4.0;
+    let swatch_size = STATUS_BAR_HEIGHT - 2.0 * swatch_margin;
+    renderer.rectangle(
+      swatch_margin,
+      edit_bar_y_offset + swatch_margin,
+      swatch_size,
+      swatch_size,
+      line_kind_color(self.line_kind),
+    );
+
+    // Followed by the name of the tool lines are drawn with, and for curves,
+    // how many segments they are made of.
+    let tool_text = match self.tool {
+      Tool::Curve(_) => {
+        format!("{} ({})", self.tool.name(), self.curve_segment_count)
+      },
+      _ => String::from(self.tool.name()),
+    };
+    let mut status_text = tool_text;
+    if self.snap_modes.grid {
+      status_text.push_str(" | grid");
+    }
+    if let Some(angle_step) = self.snap_modes.angle_step {
+      status_text.push_str(&format!(" | {} deg", angle_step));
+    }
+    let text_margin = (STATUS_BAR_HEIGHT - EDIT_BAR_TEXT_SIZE) / 2.0;
+    renderer.text(
+      &status_text,
+      &Point::new(
+        swatch_size + 2.0 * swatch_margin,
+        edit_bar_y_offset + text_margin,
+      ),
+      EDIT_BAR_TEXT_SIZE,
+      WHITE,
+    );
+  }
+
+  /// Draw the lines of the snapping grid that can be seen on a screen of the
+  /// given size.
+  fn draw_grid<R>(&self, renderer: &mut R, screen_size: &Vector)
+  where
+    R: render::Renderer,
+  {
+    let zoom = self.camera.zoom();
+    let mut spacing = self.snap_settings.grid_size;
+    if spacing <= 0.0 {
+      return;
+    }
+    while spacing * zoom < MIN_GRID_SPACING {
+      spacing *= 2.0;
+    }
+
+    let area = self.camera.visible_area(screen_size);
+    let width = GRID_LINE_WIDTH / zoom;
+    let mut batch = render::Batch::new();
+    let mut x = (area.min.x / spacing).ceil() * spacing;
+    while x <= area.max.x {
+      batch.line(
+        &Point::new(x, area.min.y),
+        &Point::new(x, area.max.y),
+        width,
+        GRID_COLOR,
+      );
+      x += spacing;
+    }
+    let mut y = (area.min.y / spacing).ceil() * spacing;
+    while y <= area.max.y {
+      batch.line(
+        &Point::new(area.min.x, y),
+        &Point::new(area.max.x, y),
+        width,
+        GRID_COLOR,
+      );
+      y += spacing;
+    }
+    batch.draw(renderer);
+  }
+
+  /// Mark the point the cursor has snapped to: a circle on an endpoint, and a
+  /// square on the grid or at a fixed angle.
+  fn draw_snap<R>(&self, renderer: &mut R)
+  where
+    R: render::Renderer,
+  {
+    let radius = SNAP_INDICATOR_RADIUS / self.camera.zoom();
+    let point = &self.snap.point;
+    match self.snap.kind {
+      snap::SnapKind::Endpoint => {
+        renderer.circle(point, radius, TRANSLUCENT_ORANGE);
+      },
+      snap::SnapKind::Grid | snap::SnapKind::Angle => {
+        renderer.rectangle(
+          point.x - radius,
+          point.y - radius,
+          2.0 * radius,
+          2.0 * radius,
+          TRANSLUCENT_ORANGE,
+        );
+      },
+      snap::SnapKind::None => {},
+    }
+  }
+
+  /// Draw the segments the active curve will be tessellated into, and the
+  /// handles it is reshaped by.
+  fn draw_active_curve<R>(&self, renderer: &mut R, active: &ActiveCurve)
+  where
+    R: render::Renderer,
+  {
+    let handles = active.curve.shape.handles();
+    let guide_width = GUIDE_WIDTH / self.camera.zoom();
+    // Bézier curves leave their ends heading towards their control points.
+    let guides: &[(usize, usize)] = match active.curve.shape {
+      track::CurveShape::Quadratic { .. } => &[(0, 1), (1, 2)],
+      track::CurveShape::Cubic { .. } => &[(0, 1), (2, 3)],
+      track::CurveShape::Arc { .. } => &[],
+    };
+    for &(from, to) in guides.iter() {
+      renderer.line(&handles[from], &handles[to], guide_width, BLUE);
+    }
+
+    let mut batch = render::Batch::new();
+    for line_segment in active.curve.line_segments(0).iter() {
+      batch.line(
+        line_segment.point1(),
+        line_segment.point2(),
+        LINE_WIDTH,
+        line_kind_color(active.curve.kind),
+      );
+    }
+    batch.draw(renderer);
+
+    let handle_radius = HANDLE_RADIUS / self.camera.zoom();
+    for handle in handles.iter() {
+      renderer.circle(handle, handle_radius, TRANSLUCENT_BLUE);
+    }
+  }
 }
 
 pub struct GameMode<Window>
 where
   Window: piston_window::Window,
 {
-  state: State,
+  state: Rc<RefCell<State>>,
   window: Rc<RefCell<piston_window::PistonWindow<Window>>>,
+  track_path: std::path::PathBuf,
+  keybindings: keybindings::Keybindings,
+  modifiers: keybindings::Modifiers,
+  panning: bool,
+  requested_application_mode: Option<String>,
 }
 
 /// How GameMode responds to input-events.
@@ -120,7 +1013,10 @@ where Window: piston_window::Window,
     _event: &Event,
     position: &[f64; 2],
   ) -> error::Result<()> {
-    self.state.mouse_position = Point::new(position[0], position[1]);
+    self
+      .state
+      .borrow_mut()
+      .move_cursor(Point::new(position[0], position[1]));
 
     Ok(())
   }
@@ -128,16 +1024,33 @@ where Window: piston_window::Window,
   fn on_mouse_relative<Event: piston_window::GenericEvent>(
     &mut self,
     _event: &Event,
-    _relative: &[f64; 2],
+    relative: &[f64; 2],
   ) -> error::Result<()> {
+    if self.panning {
+      let mut state = self.state.borrow_mut();
+      state.camera.pan(&Vector::new(relative[0], relative[1]));
+
+      // The cursor has not moved on screen, but it is now over another part of
+      // the world.
+      let cursor_position = state.cursor_position;
+      state.move_cursor(cursor_position);
+    }
+
     Ok(())
   }
 
   fn on_mouse_scroll<Event: piston_window::GenericEvent>(
     &mut self,
     _event: &Event,
-    _scroll: &[f64; 2],
+    scroll: &[f64; 2],
   ) -> error::Result<()> {
+    let mut state = self.state.borrow_mut();
+    let cursor_position = state.cursor_position;
+    state
+      .camera
+      .zoom_at(&cursor_position, camera::ZOOM_STEP.powf(scroll[1]));
+    state.move_cursor(cursor_position);
+
     Ok(())
   }
 
@@ -146,18 +1059,90 @@ where Window: piston_window::Window,
     _event: &Event,
     button: &piston_window::Button,
   ) -> error::Result<()> {
-    match button {
-      &piston_window::Button::Keyboard(key) => match key {
-        piston_window::Key::LShift | piston_window::Key::RShift => {
-          self.state.edit_mode = EditMode::Select;
-        },
-        _ => {},
+    let action = self.keybindings.action(
+      keybindings::Context::Edit,
+      button,
+      &self.modifiers,
+    );
+    self.modifiers.press(button);
+
+    match action {
+      Some(Action::Draw) => {
+        let mut state = self.state.borrow_mut();
+        match state.edit_mode {
+          EditMode::Insert => {
+            state.begin_line();
+          },
+          EditMode::Select => {
+            state.begin_drag();
+          },
+        }
       },
-      &piston_window::Button::Mouse(mouse_button) => match mouse_button {
-        piston_window::MouseButton::Left => {
-          self.state.active_line_segment = Some(self.state.mouse_position);
-        },
-        _ => {},
+      Some(Action::Select) => {
+        self.state.borrow_mut().edit_mode = EditMode::Select;
+      },
+      Some(Action::Pan) => {
+        self.panning = true;
+      },
+      Some(Action::Undo) => {
+        self.state.borrow_mut().undo();
+      },
+      Some(Action::Redo) => {
+        self.state.borrow_mut().redo();
+      },
+      Some(Action::Play) => {
+        self.requested_application_mode =
+          Some(String::from(PLAY_APPLICATION_MODE));
+      },
+      Some(Action::Save) => {
+        self.save_track();
+      },
+      Some(Action::Open) => {
+        self.open_track();
+      },
+      Some(Action::Delete) => {
+        self.state.borrow_mut().delete_selection();
+      },
+      Some(Action::NormalLine) => {
+        self.state.borrow_mut().line_kind = LineKind::Normal;
+      },
+      Some(Action::AccelerationLine) => {
+        self.state.borrow_mut().line_kind = LineKind::Acceleration;
+      },
+      Some(Action::SceneryLine) => {
+        self.state.borrow_mut().line_kind = LineKind::Scenery;
+      },
+      Some(Action::LineTool) => {
+        self.state.borrow_mut().tool = Tool::Line;
+      },
+      Some(Action::PencilTool) => {
+        self.state.borrow_mut().tool = Tool::Pencil;
+      },
+      Some(Action::QuadraticTool) => {
+        self.state.borrow_mut().tool = Tool::Curve(CurveTool::Quadratic);
+      },
+      Some(Action::CubicTool) => {
+        self.state.borrow_mut().tool = Tool::Curve(CurveTool::Cubic);
+      },
+      Some(Action::ArcTool) => {
+        self.state.borrow_mut().tool = Tool::Curve(CurveTool::Arc);
+      },
+      Some(Action::EditCurve) => {
+        let mut state = self.state.borrow_mut();
+        let mouse_position = state.mouse_position;
+        state.edit_curve_at(&mouse_position);
+      },
+      Some(Action::MoreSegments) => {
+        self.state.borrow_mut().change_curve_segment_count(true);
+      },
+      Some(Action::FewerSegments) => {
+        self.state.borrow_mut().change_curve_segment_count(false);
+      },
+      Some(Action::ToggleGridSnap) => {
+        self.state.borrow_mut().toggle_grid_snap();
+      },
+      Some(Action::CycleAngleSnap) => {
+        self.state.borrow_mut().cycle_angle_snap();
       },
       _ => {},
     }
@@ -170,27 +1155,18 @@ where Window: piston_window::Window,
     _event: &Event,
     button: &piston_window::Button,
   ) -> error::Result<()> {
-    match button {
-      &piston_window::Button::Keyboard(key) => match key {
-        piston_window::Key::LShift | piston_window::Key::RShift => {
-          self.state.edit_mode = EditMode::Insert;
-        },
-        _ => {},
-      },
-      &piston_window::Button::Mouse(mouse_button) => match mouse_button {
-        piston_window::MouseButton::Left => {
-          match self.state.active_line_segment {
-            Some(point1) => {
-              self.state.line_segments.push(
-                LineSegment::new(point1, self.state.mouse_position));
-              self.state.active_line_segment = None;
-            }
-            None => {}
-          }
-        },
-        _ => {},
-      },
-      _ => {},
+    self.modifiers.release(button);
+    let mut state = self.state.borrow_mut();
+
+    if self.keybindings.releases(Action::Select, button) {
+      state.edit_mode = EditMode::Insert;
+    }
+    if self.keybindings.releases(Action::Pan, button) {
+      self.panning = false;
+    }
+    if self.keybindings.releases(Action::Draw, button) {
+      state.end_line();
+      state.end_drag();
     }
 
     Ok(())
@@ -279,42 +1255,16 @@ where Window: piston_window::OpenGLWindow,
 
     // Borrow member references immutably before allowing self to be borrowed
     // mutably by self.window.draw_2d().
-    let state = &self.state;
+    let state = self.state.borrow();
     let window_size = self.window.borrow().size();
+    let screen_size =
+      Vector::new(window_size.width as f64, window_size.height as f64);
 
     self.window.borrow_mut().draw_2d(event, |context, graphics| {
-      let edit_bar_color = match state.edit_mode {
-        EditMode::Insert => GREEN,
-        EditMode::Select => BLUE,
-      };
-      let edit_bar_width = window_size.width;
-      let edit_bar_height = 20;
-      let edit_bar_x_offset = 0;
-      let edit_bar_y_offset = window_size.height - edit_bar_height;
-
-      piston_window::clear([1.0; 4], graphics);
-      piston_window::rectangle(
-        edit_bar_color,
-        [
-          edit_bar_x_offset as f64,
-          edit_bar_y_offset as f64,
-          edit_bar_width as f64,
-          edit_bar_height as f64,
-        ],
-        context.transform,
-        graphics,
+      state.draw(
+        &mut piston_renderer::PistonRenderer::new(&context, graphics),
+        &screen_size,
       );
-
-      match state.active_line_segment {
-        Some(point1) => {
-          draw_line_segment(&point1, &state.mouse_position, &context, graphics);
-        }
-        None => {}
-      }
-
-      for line in state.line_segments.iter() {
-        line.draw(&context, graphics);
-      }
     });
 
     Ok(())
@@ -332,27 +1282,102 @@ where Window: piston_window::OpenGLWindow,
 /// Inherit default implementation of EventHandler::on_event.
 impl<Window> handler::EventHandler for GameMode<Window>
 where Window: piston_window::OpenGLWindow,
-{}
+{
+  /// Abandon any line still being drawn so it does not outlive the mode.
+  /// Switching modes commits a curve whose handles are being adjusted, so
+  /// that it is on the track when it is ridden.
+  fn on_exit(&mut self) -> error::Result<()> {
+    let mut state = self.state.borrow_mut();
+    state.active_line_segment = None;
+    state.pencil_stroke = None;
+    if state.active_curve.as_ref().map_or(false, |active| active.drawing) {
+      state.active_curve = None;
+    }
+    state.commit_curve();
+    state.edit_mode = EditMode::Insert;
+    state.drag = None;
+    self.modifiers = keybindings::Modifiers::default();
+    self.panning = false;
+
+    Ok(())
+  }
+
+  fn requested_application_mode(&mut self) -> Option<String> {
+    self.requested_application_mode.take()
+  }
+}
 
 impl<Window> GameMode<Window>
 where
   Window: piston_window::Window + piston_window::OpenGLWindow,
 {
-  /// Create a GameMode for a new game.
+  /// Create a GameMode for a new game, saved to and opened from `track_path`.
   pub fn new(
     window: Rc<RefCell<piston_window::PistonWindow<Window>>>,
+    track_path: &std::path::Path,
+    keybindings: keybindings::Keybindings,
+    snap_settings: snap::SnapSettings,
   ) -> GameMode<Window> {
-    GameMode::new_with_state(window, State::new())
+    GameMode::new_with_state(
+      window,
+      Rc::new(RefCell::new(State::new())),
+      track_path,
+      keybindings,
+      snap_settings,
+    )
   }
 
-  /// Create a GameMode with an existing State.
+  /// Create a GameMode that edits an existing State. The State may be shared
+  /// with other application modes, such as a PlayMode. The cursor snaps as
+  /// `snap_settings` say while drawing.
   pub fn new_with_state(
     window: Rc<RefCell<piston_window::PistonWindow<Window>>>,
-    state: State,
+    state: Rc<RefCell<State>>,
+    track_path: &std::path::Path,
+    keybindings: keybindings::Keybindings,
+    snap_settings: snap::SnapSettings,
   ) -> GameMode<Window> {
+    state.borrow_mut().set_snap_settings(snap_settings);
     GameMode {
       window: window,
       state: state,
+      track_path: track_path.to_path_buf(),
+      keybindings: keybindings,
+      modifiers: keybindings::Modifiers::default(),
+      panning: false,
+      requested_application_mode: None,
+    }
+  }
+
+  /// Save the track to the track path. A failure is reported but does not end
+  /// the game, so no edits are lost and saving can be tried again.
+  fn save_track(&self) {
+    use error::ResultExt; // chain_err
+
+    let result = track_file::save(&self.track_path, self.state.borrow().track())
+      .chain_err(|| {
+        format!("Failed to save track to {}", self.track_path.display())
+      });
+    if let Err(ref e) = result {
+      error::report(e);
+    }
+  }
+
+  /// Replace the track with the one saved at the track path. A failure is
+  /// reported and leaves the current track untouched.
+  fn open_track(&mut self) {
+    use error::ResultExt; // chain_err
+
+    let result = track_file::load(&self.track_path).chain_err(|| {
+      format!("Failed to open track from {}", self.track_path.display())
+    });
+    match result {
+      Ok(track) => {
+        self.state.borrow_mut().replace_track(track);
+      },
+      Err(ref e) => {
+        error::report(e);
+      },
     }
   }
 }
@@ -0,0 +1,175 @@
+//! Drawing the track and the ride, as the editor, play mode and export all
+//! show them.
+
+use camera;
+use render;
+use simulation;
+use spatial_index;
+use track;
+
+pub const WHITE: render::Color = [1.0, 1.0, 1.0, 1.0];
+pub const BLACK: render::Color = [0.0, 0.0, 0.0, 1.0];
+pub const GREEN: render::Color = [0.0, 1.0, 0.0, 1.0];
+pub const BLUE: render::Color = [0.0, 0.0, 1.0, 1.0];
+pub const RED: render::Color = [1.0, 0.0, 0.0, 1.0];
+const BROWN: render::Color = [0.6, 0.4, 0.2, 1.0];
+
+/// How wide lines are drawn, in world units.
+pub const LINE_WIDTH: f64 = 4.0;
+/// The height, in screen pixels, of the bar along the bottom of the screen
+/// that shows the current mode.
+pub const STATUS_BAR_HEIGHT: f64 = 20.0;
+/// The height, in screen pixels, of the ride time shown in the play bar.
+const TIME_TEXT_SIZE: f64 = 14.0;
+/// How wide the rider's arms and legs are drawn, in world units.
+const LIMB_WIDTH: f64 = 1.5;
+/// How wide the rope from the rider's hands to the sled is drawn, in world
+/// units.
+const ROPE_WIDTH: f64 = 0.5;
+
+/// The color that lines of a kind are drawn with.
+pub fn line_kind_color(kind: track::LineKind) -> render::Color {
+  match kind {
+    track::LineKind::Normal => BLACK,
+    track::LineKind::Acceleration => RED,
+    track::LineKind::Scenery => GREEN,
+  }
+}
+
+/// Draw the line segments of `track` that can be seen through `camera` on a
+/// screen of the given size, finding them with `spatial_index`. The segments
+/// are drawn in one batch of triangles per kind of line.
+pub fn draw_visible_lines<R>(
+  renderer: &mut R,
+  track: &track::Track,
+  spatial_index: &spatial_index::SpatialIndex,
+  camera: &camera::Camera,
+  screen_size: &track::Vector,
+) where
+  R: render::Renderer,
+{
+  let visible_area =
+    camera.visible_area(screen_size).expanded(LINE_WIDTH / 2.0);
+  let mut batch = render::Batch::new();
+  for index in spatial_index.query_aabb(&visible_area) {
+    let line_segment = &track.line_segments[index];
+    batch.line(
+      line_segment.point1(),
+      line_segment.point2(),
+      LINE_WIDTH,
+      line_kind_color(line_segment.kind()),
+    );
+  }
+  batch.draw(renderer);
+}
+
+/// Draw the rider as `simulation` has it, in world coordinates: the sled, the
+/// rider's arms and legs, and the rope it holds while it is on the sled.
+pub fn draw_rider<R>(renderer: &mut R, simulation: &simulation::Simulation)
+where
+  R: render::Renderer,
+{
+  let pose = simulation.rider_pose();
+  let world_transform = renderer.transform();
+
+  let sled_transform = world_transform
+    .trans(pose.sled.position.x, pose.sled.position.y)
+    .orient(pose.sled.angle.cos(), pose.sled.angle.sin());
+  renderer.set_transform(sled_transform);
+  renderer.rectangle(
+    -simulation::SLED_HALF_LENGTH,
+    -simulation::SLED_HALF_HEIGHT,
+    2.0 * simulation::SLED_HALF_LENGTH,
+    2.0 * simulation::SLED_HALF_HEIGHT,
+    BROWN,
+  );
+  renderer.set_transform(world_transform);
+
+  if pose.attached {
+    let offset = track::Vector::new(
+      simulation::SLED_HALF_LENGTH * pose.sled.angle.cos(),
+      simulation::SLED_HALF_LENGTH * pose.sled.angle.sin(),
+    );
+    let sled_front = pose.sled.position + offset;
+    for hand in pose.hands.iter() {
+      renderer.line(hand, &sled_front, ROPE_WIDTH, BLACK);
+    }
+  }
+
+  for limb in pose.hands.iter().chain(pose.feet.iter()) {
+    renderer.line(&pose.torso, limb, LIMB_WIDTH, BLACK);
+  }
+  renderer.circle(&pose.torso, simulation::TORSO_RADIUS, RED);
+  for hand in pose.hands.iter() {
+    renderer.circle(hand, simulation::HAND_RADIUS, BLACK);
+  }
+  for foot in pose.feet.iter() {
+    renderer.circle(foot, simulation::FOOT_RADIUS, BLACK);
+  }
+}
+
+/// Move `camera` on by `dt` seconds of a ride, following the rider of
+/// `simulation` where it is drawn. Play mode and export both follow the rider
+/// this way, so that an export shows the ride as playback does.
+pub fn follow_rider(
+  camera: &mut camera::Camera,
+  follow_settings: &camera::FollowSettings,
+  simulation: &simulation::Simulation,
+  screen_size: &track::Vector,
+  dt: f64,
+) {
+  camera.follow(
+    follow_settings,
+    &simulation.interpolated_rider_position(),
+    &simulation.rider_velocity(),
+    screen_size,
+    dt,
+  );
+}
+
+/// Draw a ride of `track`, as seen through `camera`, with the play bar along
+/// the bottom of a screen of the given size. The rider is only drawn while a
+/// simulation is running.
+pub fn draw_ride<R>(
+  renderer: &mut R,
+  track: &track::Track,
+  spatial_index: &spatial_index::SpatialIndex,
+  simulation: Option<&simulation::Simulation>,
+  camera: &camera::Camera,
+  screen_size: &track::Vector,
+) where
+  R: render::Renderer,
+{
+  renderer.clear(WHITE);
+
+  // The track is drawn in world coordinates, and the play bar over it in
+  // screen coordinates.
+  renderer.set_transform(camera.transform());
+
+  draw_visible_lines(renderer, track, spatial_index, camera, screen_size);
+
+  if let Some(simulation) = simulation {
+    draw_rider(renderer, simulation);
+  }
+
+  renderer.set_transform(render::Transform::identity());
+
+  let play_bar_y_offset = screen_size.y - STATUS_BAR_HEIGHT;
+  renderer.rectangle(
+    0.0,
+    play_bar_y_offset,
+    screen_size.x,
+    STATUS_BAR_HEIGHT,
+    RED,
+  );
+
+  if let Some(simulation) = simulation {
+    let margin = (STATUS_BAR_HEIGHT - TIME_TEXT_SIZE) / 2.0;
+    renderer.text(
+      &format!("{:.2}", simulation.time()),
+      &track::Point::new(margin, play_bar_y_offset + margin),
+      TIME_TEXT_SIZE,
+      WHITE,
+    );
+  }
+}
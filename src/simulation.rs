@@ -0,0 +1,856 @@
+extern crate nalgebra;
+extern crate ncollide;
+extern crate nphysics2d;
+extern crate std;
+
+use std::collections::BTreeMap;
+
+use geometry;
+use spatial_index;
+use track;
+use track::{LineKind, LineSegment, Point, Vector};
+
+/// Downward acceleration applied to every dynamic body, in pixels/second^2.
+const GRAVITY: f64 = 400.0;
+/// Duration of a single physics step, in seconds.
+pub const TIME_STEP: f64 = 1.0 / 120.0;
+/// How many steps apart the physics world is rebuilt from the state of the
+/// rider's bodies and the lines near them alone, dropping the contacts it
+/// carries from one step to the next. A Snapshot taken on one of these ticks
+/// restores the world exactly as it was.
+pub const KEYFRAME_INTERVAL: u64 = 60;
+/// How far, in world units, the lines in the physics world reach beyond where
+/// the rider could be by the end of the next step. The lines near the rider are
+/// refreshed before it can reach one that was left out.
+const COLLIDER_MARGIN: f64 = 64.0;
+/// The most steps a single update may take before dropping accumulated time.
+/// This keeps a long stall (e.g. dragging the window) from freezing the game
+/// while the simulation catches up.
+const MAX_STEPS_PER_UPDATE: u32 = 8;
+
+/// Half the length of the sled, along its runners.
+pub const SLED_HALF_LENGTH: f64 = 12.0;
+/// Half the thickness of the sled.
+pub const SLED_HALF_HEIGHT: f64 = 2.0;
+pub const TORSO_RADIUS: f64 = 5.0;
+pub const HAND_RADIUS: f64 = 2.0;
+pub const FOOT_RADIUS: f64 = 2.5;
+const RIDER_DENSITY: f64 = 1.0;
+const RIDER_RESTITUTION: f64 = 0.1;
+const RIDER_FRICTION: f64 = 0.05;
+
+const LINE_RESTITUTION: f64 = 0.0;
+const LINE_FRICTION: f64 = 0.05;
+
+/// The collision group every body of the rider belongs to. The rider's bodies
+/// collide with lines, but never with each other.
+const RIDER_COLLISION_GROUP: usize = 1;
+
+/// Line segments shorter than this have no usable collision normal.
+const MIN_LINE_LENGTH: f64 = 1.0e-6;
+
+/// How quickly an acceleration line speeds up a sled touching it, in
+/// pixels/second^2.
+const LINE_ACCELERATION: f64 = 600.0;
+/// The largest change in the velocity of any of the rider's bodies, in
+/// pixels/second, that a single step may cause without the rider crashing.
+/// Both collisions and the pins holding the rider to the sled change their
+/// velocities, so this bounds the impact the pins can take before breaking.
+const CRASH_VELOCITY_CHANGE: f64 = 350.0;
+/// How far beyond its runners the sled still counts as touching a line. The
+/// contact solver keeps a resting sled slightly apart from the line it rests
+/// on, so an exact test would miss it.
+const CONTACT_MARGIN: f64 = 1.0;
+
+/// The FNV-1a offset basis and prime, used by Simulation::checksum.
+const CHECKSUM_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const CHECKSUM_PRIME: u64 = 0x100000001b3;
+
+type World = nphysics2d::world::World<f64>;
+type RigidBody = nphysics2d::object::RigidBody<f64>;
+type RigidBodyHandle = nphysics2d::object::RigidBodyHandle<f64>;
+type BallInSocket = nphysics2d::detection::joint::BallInSocket<f64>;
+type BallInSocketHandle = std::rc::Rc<std::cell::RefCell<BallInSocket>>;
+type Isometry = nalgebra::Isometry2<f64>;
+
+/// The kinds of body the rider is built from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Part {
+  Sled,
+  Torso,
+  Hand,
+  Foot,
+}
+
+/// The rider's bodies, in the order they are added to the world, each with
+/// where it starts relative to the center of the sled. The sled starts
+/// centered on the track's start point.
+const PARTS: [(Part, [f64; 2]); 6] = [
+  (Part::Sled, [0.0, 0.0]),
+  (Part::Torso, [-4.0, -8.0]),
+  (Part::Hand, [6.0, -9.0]),
+  (Part::Hand, [5.0, -10.0]),
+  (Part::Foot, [5.0, -4.5]),
+  (Part::Foot, [3.0, -4.5]),
+];
+/// The index of the sled in PARTS.
+const SLED: usize = 0;
+/// The index of the torso in PARTS.
+const TORSO: usize = 1;
+
+/// Where a body of the rider starts, relative to the center of the sled.
+fn part_offset(index: usize) -> Vector {
+  let offset = PARTS[index].1;
+  Vector::new(offset[0], offset[1])
+}
+
+/// Build the static body a rider collides against for one line segment.
+/// Scenery lines have no body.
+fn line_segment_body(line_segment: &LineSegment) -> Option<RigidBody> {
+  let point1 = line_segment.point1();
+  let point2 = line_segment.point2();
+  if line_segment.kind() == LineKind::Scenery ||
+    nalgebra::distance(point1, point2) < MIN_LINE_LENGTH
+  {
+    return None;
+  }
+
+  Some(RigidBody::new_static(
+    ncollide::shape::Segment::new(*point1, *point2),
+    LINE_RESTITUTION,
+    LINE_FRICTION,
+  ))
+}
+
+/// Build the dynamic body for one of the rider's bodies, at rest in its
+/// place on a sled centered on the given start point.
+fn part_body(index: usize, rider_start: &Point) -> RigidBody {
+  let (part, _) = PARTS[index];
+  let mut body = match part {
+    Part::Sled => RigidBody::new_dynamic(
+      ncollide::shape::Cuboid::new(
+        Vector::new(SLED_HALF_LENGTH, SLED_HALF_HEIGHT),
+      ),
+      RIDER_DENSITY,
+      RIDER_RESTITUTION,
+      RIDER_FRICTION,
+    ),
+    Part::Torso | Part::Hand | Part::Foot => {
+      let radius = match part {
+        Part::Torso => TORSO_RADIUS,
+        Part::Hand => HAND_RADIUS,
+        _ => FOOT_RADIUS,
+      };
+      RigidBody::new_dynamic(
+        ncollide::shape::Ball::new(radius),
+        RIDER_DENSITY,
+        RIDER_RESTITUTION,
+        RIDER_FRICTION,
+      )
+    },
+  };
+
+  let position = rider_start + part_offset(index);
+  body.append_translation(
+    &nalgebra::Translation2::new(position.x, position.y),
+  );
+  // A resting rider must still respond when the track changes beneath it.
+  body.set_deactivation_threshold(None);
+
+  let mut collision_groups =
+    nphysics2d::object::RigidBodyCollisionGroups::new_dynamic();
+  collision_groups.set_membership(&[RIDER_COLLISION_GROUP]);
+  collision_groups.set_blacklist(&[RIDER_COLLISION_GROUP]);
+  body.set_collision_groups(collision_groups);
+
+  body
+}
+
+/// A joint pinning a point fixed to one body to a point fixed to another. Each
+/// point is relative to its body's center, in the rider's starting pose.
+fn pin(
+  body1: &RigidBodyHandle,
+  point1: Point,
+  body2: &RigidBodyHandle,
+  point2: Point,
+) -> BallInSocket {
+  use self::nphysics2d::detection::joint::Anchor;
+
+  BallInSocket::new(
+    Anchor::new(Some(body1.clone()), point1),
+    Anchor::new(Some(body2.clone()), point2),
+  )
+}
+
+/// Interpolate from one rotation to another the shorter way around.
+fn interpolate_angle(angle1: f64, angle2: f64, t: f64) -> f64 {
+  use std::f64::consts::PI;
+
+  let mut difference = (angle2 - angle1) % (2.0 * PI);
+  if difference > PI {
+    difference -= 2.0 * PI;
+  } else if difference < -PI {
+    difference += 2.0 * PI;
+  }
+  angle1 + difference * t
+}
+
+/// Where one of the rider's bodies is, and how far it has turned.
+#[derive(Clone, Copy, Debug)]
+pub struct Pose {
+  pub position: Point,
+  /// The rotation from the starting pose, in radians. Positive angles turn
+  /// clockwise on screen.
+  pub angle: f64,
+}
+
+/// Where to draw each of the rider's bodies.
+#[derive(Clone, Debug)]
+pub struct RiderPose {
+  pub sled: Pose,
+  pub torso: Point,
+  pub hands: Vec<Point>,
+  pub feet: Vec<Point>,
+  /// Whether the rider is still held to the sled.
+  pub attached: bool,
+}
+
+/// The state of one body, as kept in a Snapshot.
+#[derive(Clone, Debug)]
+struct BodyState {
+  transformation: Isometry,
+  linear_velocity: Vector,
+  angular_velocity: nalgebra::Vector1<f64>,
+}
+
+/// A physics world containing the rider and a static body for every line
+/// segment of a track near it. Time is consumed in fixed-size steps, so the
+/// outcome of a run does not depend on how often it is updated.
+///
+/// The rider is a sled carrying a torso, two hands and two feet. The hands and
+/// feet hang from the torso on pins, as arms and legs, and pins hold all of
+/// them to the sled until the rider crashes. Then those pins break, and the
+/// rider tumbles off the sled as a ragdoll.
+///
+/// Bodies and joints are always added to the world in the same order, and no
+/// step depends on the wall clock, so two Simulations of the same track built
+/// by the same program agree on `checksum` after the same number of steps, as
+/// the tests below check.
+pub struct Simulation {
+  world: World,
+  /// The track's lines and start point, to rebuild the world from.
+  line_segments: Vec<LineSegment>,
+  rider_start: Point,
+  /// Finds the lines near the rider, which are the only ones in the world.
+  line_index: spatial_index::SpatialIndex,
+  /// The area whose lines are in the world.
+  collider_area: geometry::Aabb,
+  /// The static bodies in the world, by the index of the line each is for.
+  colliders: BTreeMap<usize, RigidBodyHandle>,
+  /// The rider's bodies, in the order of PARTS.
+  parts: Vec<RigidBodyHandle>,
+  /// The pins holding the rider to the sled. Empty once the rider crashes.
+  attachments: Vec<BallInSocketHandle>,
+  acceleration_lines: Vec<AccelerationLine>,
+  /// Finds the acceleration lines the sled is touching.
+  acceleration_index: spatial_index::SpatialIndex,
+  /// Where the rider's bodies were before the latest step, for interpolation.
+  previous_transformations: Vec<Isometry>,
+  accumulator: f64,
+  tick: u64,
+  crash_tick: Option<u64>,
+}
+
+/// Everything about a Simulation that changes as it steps, from which it can
+/// be put back to the tick the Snapshot was taken on. Only Snapshots taken on
+/// a multiple of KEYFRAME_INTERVAL put back the contacts between the rider and
+/// the track too.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+  tick: u64,
+  crash_tick: Option<u64>,
+  parts: Vec<BodyState>,
+  previous_transformations: Vec<Isometry>,
+}
+
+impl Snapshot {
+  /// The tick the Snapshot was taken on.
+  pub fn tick(&self) -> u64 {
+    self.tick
+  }
+}
+
+/// An acceleration line, as the simulation needs to know it. Where it is, is
+/// kept in the Simulation's acceleration_index.
+struct AccelerationLine {
+  direction: Vector,
+}
+
+impl Simulation {
+  /// Create a Simulation with the rider at rest at the track's start point.
+  pub fn new(track: &track::Track) -> Simulation {
+    let mut acceleration_lines = Vec::new();
+    let mut acceleration_index = spatial_index::SpatialIndex::default();
+    for line_segment in track.line_segments.iter() {
+      let point1 = *line_segment.point1();
+      let point2 = *line_segment.point2();
+      if line_segment.kind() == LineKind::Acceleration &&
+        nalgebra::distance(&point1, &point2) >= MIN_LINE_LENGTH
+      {
+        acceleration_lines.push(AccelerationLine {
+          direction: (point2 - point1).normalize(),
+        });
+        acceleration_index.push(point1, point2);
+      }
+    }
+
+    let mut simulation = Simulation {
+      world: World::new(),
+      line_segments: track.line_segments.clone(),
+      rider_start: track.rider_start,
+      line_index: spatial_index::SpatialIndex::from_line_segments(
+        &track.line_segments,
+      ),
+      collider_area: geometry::Aabb::from_corners(
+        &track.rider_start,
+        &track.rider_start,
+      ),
+      colliders: BTreeMap::new(),
+      parts: Vec::new(),
+      attachments: Vec::new(),
+      acceleration_lines: acceleration_lines,
+      acceleration_index: acceleration_index,
+      previous_transformations: Vec::new(),
+      accumulator: 0.0,
+      tick: 0,
+      crash_tick: None,
+    };
+    simulation.build_world(None);
+    simulation.previous_transformations = simulation.transformations();
+
+    simulation
+  }
+
+  /// Replace the physics world with a new one holding the rider, each of
+  /// whose bodies is in the given state, or at rest in its starting pose if
+  /// none is given, and the track's lines near it. The rider is pinned to the
+  /// sled unless it has crashed. Everything is added in the same order every
+  /// time, so the same states always build the same world. The cost depends
+  /// only on how many lines are near the rider, not on the length of the
+  /// track.
+  fn build_world(&mut self, states: Option<&[BodyState]>) {
+    let mut world = World::new();
+    world.set_gravity(Vector::new(0.0, GRAVITY));
+
+    let mut parts = Vec::with_capacity(PARTS.len());
+    for index in 0..PARTS.len() {
+      let mut body = part_body(index, &self.rider_start);
+      if let Some(states) = states {
+        let state = &states[index];
+        body.set_transformation(state.transformation);
+        body.set_lin_vel(state.linear_velocity);
+        body.set_ang_vel(state.angular_velocity);
+      }
+      parts.push(world.add_rigid_body(body));
+    }
+    // The arms and legs: each hand and foot hangs from the torso's center.
+    for index in (TORSO + 1)..PARTS.len() {
+      world.add_ball_in_socket(pin(
+        &parts[TORSO],
+        Point::origin(),
+        &parts[index],
+        Point::from_coordinates(part_offset(TORSO) - part_offset(index)),
+      ));
+    }
+
+    self.world = world;
+    self.parts = parts;
+    self.attachments.clear();
+    // The pins to the sled are added after the other joints, as they are the
+    // only joints that come and go.
+    if self.crash_tick.is_none() {
+      self.attach();
+    }
+
+    self.colliders.clear();
+    self.refresh_colliders();
+  }
+
+  /// Make the lines in the world those near the rider, removing the ones it
+  /// has left behind and adding the ones it is coming up to. Lines that stay
+  /// keep their bodies, and with them their contacts with the rider. New
+  /// lines are added in the order of their indices, so the same steps always
+  /// build the same world.
+  fn refresh_colliders(&mut self) {
+    self.collider_area = self.rider_reach().expanded(COLLIDER_MARGIN);
+    let nearby = self.line_index.query_aabb(&self.collider_area);
+
+    let behind: Vec<usize> = self
+      .colliders
+      .keys()
+      .filter(|index| nearby.binary_search(index).is_err())
+      .cloned()
+      .collect();
+    for index in behind {
+      if let Some(handle) = self.colliders.remove(&index) {
+        self.world.remove_rigid_body(&handle);
+      }
+    }
+
+    for index in nearby {
+      if self.colliders.contains_key(&index) {
+        continue;
+      }
+      if let Some(body) = line_segment_body(&self.line_segments[index]) {
+        let handle = self.world.add_rigid_body(body);
+        self.colliders.insert(index, handle);
+      }
+    }
+  }
+
+  /// The box that the rider's bodies could reach by the end of the next step.
+  fn rider_reach(&self) -> geometry::Aabb {
+    let positions: Vec<Point> = self
+      .transformations()
+      .iter()
+      .map(|transformation| {
+        Point::from_coordinates(transformation.translation.vector)
+      })
+      .collect();
+    let mut reach = geometry::Aabb::from_corners(&positions[0], &positions[0]);
+    for position in positions.iter() {
+      reach.include(position);
+    }
+    let speed = self
+      .velocities()
+      .iter()
+      .fold(0.0, |speed: f64, velocity| speed.max(velocity.norm()));
+
+    // No body reaches further from its center than the corners of the sled,
+    // and none moves further in a step than gravity and an acceleration line
+    // could speed it up to.
+    let step_speed = speed + (GRAVITY + LINE_ACCELERATION) * TIME_STEP;
+    reach.expanded(
+      SLED_HALF_LENGTH + SLED_HALF_HEIGHT + step_speed * TIME_STEP +
+        CONTACT_MARGIN,
+    )
+  }
+
+  /// Pin the rider to the sled as it sits in its starting pose. Two pins keep
+  /// the torso upright, and the hands and feet have one each.
+  fn attach(&mut self) {
+    let sled = &self.parts[SLED];
+    let torso = &self.parts[TORSO];
+    let mut pins = vec![
+      pin(
+        sled,
+        Point::from_coordinates(part_offset(TORSO)),
+        torso,
+        Point::origin(),
+      ),
+      pin(
+        sled,
+        Point::from_coordinates(
+          part_offset(TORSO) + Vector::new(0.0, TORSO_RADIUS),
+        ),
+        torso,
+        Point::new(0.0, TORSO_RADIUS),
+      ),
+    ];
+    for index in (TORSO + 1)..PARTS.len() {
+      pins.push(pin(
+        sled,
+        Point::from_coordinates(part_offset(index)),
+        &self.parts[index],
+        Point::origin(),
+      ));
+    }
+
+    for joint in pins.into_iter() {
+      let attachment = self.world.add_ball_in_socket(joint);
+      self.attachments.push(attachment);
+    }
+  }
+
+  /// Break the pins holding the rider to the sled.
+  fn detach(&mut self) {
+    for attachment in self.attachments.drain(..) {
+      self.world.remove_ball_in_socket(&attachment);
+    }
+  }
+
+  fn transformations(&self) -> Vec<Isometry> {
+    self.parts.iter().map(|part| *part.borrow().position()).collect()
+  }
+
+  fn velocities(&self) -> Vec<Vector> {
+    self.parts.iter().map(|part| part.borrow().lin_vel()).collect()
+  }
+
+  fn body_states(&self) -> Vec<BodyState> {
+    self
+      .parts
+      .iter()
+      .map(|part| {
+        let part = part.borrow();
+        BodyState {
+          transformation: *part.position(),
+          linear_velocity: part.lin_vel(),
+          angular_velocity: part.ang_vel(),
+        }
+      })
+      .collect()
+  }
+
+  /// Advance the simulation by exactly one TIME_STEP.
+  pub fn step(&mut self) {
+    self.previous_transformations = self.transformations();
+    self.accelerate_sled();
+
+    let velocities_before = self.velocities();
+    self.world.step(TIME_STEP);
+    self.tick += 1;
+
+    // Whatever change in velocity gravity does not explain came from a
+    // collision, or from the pins holding the rider together. A hard enough
+    // jolt to any of the rider's bodies is a crash.
+    if self.crash_tick.is_none() {
+      let gravity = Vector::new(0.0, GRAVITY * TIME_STEP);
+      let crashed = self
+        .velocities()
+        .iter()
+        .zip(velocities_before.iter())
+        .any(|(&velocity, &velocity_before)| {
+          (velocity - velocity_before - gravity).norm() > CRASH_VELOCITY_CHANGE
+        });
+      if crashed {
+        self.crash_tick = Some(self.tick);
+        self.detach();
+      }
+    }
+
+    // Rebuilding the world only from the rider's state is what lets a
+    // Snapshot on a keyframe put back the world exactly: the physics engine
+    // keeps contacts, and numbers the bodies they are between, in ways only a
+    // new world resets.
+    if self.tick % KEYFRAME_INTERVAL == 0 {
+      let states = self.body_states();
+      self.build_world(Some(&states));
+    } else {
+      let reach = self.rider_reach();
+      if !self.collider_area.contains(&reach.min) ||
+        !self.collider_area.contains(&reach.max)
+      {
+        self.refresh_colliders();
+      }
+    }
+  }
+
+  /// Speed up the sled along every acceleration line its runners are touching.
+  fn accelerate_sled(&mut self) {
+    let mut sled = self.parts[SLED].borrow_mut();
+    let transformation = *sled.position();
+    let touching = self.acceleration_index.query_segment(
+      &(transformation * Point::new(-SLED_HALF_LENGTH, SLED_HALF_HEIGHT)),
+      &(transformation * Point::new(SLED_HALF_LENGTH, SLED_HALF_HEIGHT)),
+      CONTACT_MARGIN,
+    );
+
+    for index in touching {
+      let velocity = sled.lin_vel();
+      sled.set_lin_vel(
+        velocity +
+          self.acceleration_lines[index].direction *
+            (LINE_ACCELERATION * TIME_STEP),
+      );
+    }
+  }
+
+  /// Advance the simulation by `dt` seconds of wall-clock time. Any time left
+  /// over that does not fill a whole step is carried into the next update.
+  pub fn update(&mut self, dt: f64) {
+    self.update_with(dt, |_| {});
+  }
+
+  /// Like update, but calls `on_step` after every step taken.
+  pub fn update_with<F>(&mut self, dt: f64, mut on_step: F)
+  where
+    F: FnMut(&Simulation),
+  {
+    self.accumulator += dt;
+
+    let mut steps = 0;
+    while self.accumulator >= TIME_STEP {
+      if steps == MAX_STEPS_PER_UPDATE {
+        self.accumulator = 0.0;
+        break;
+      }
+      self.step();
+      on_step(self);
+      self.accumulator -= TIME_STEP;
+      steps += 1;
+    }
+  }
+
+  /// Drop any time carried over between updates, so the rider is drawn
+  /// exactly where the latest step left it. Used while time is not passing.
+  pub fn hold(&mut self) {
+    self.accumulator = 0.0;
+    self.previous_transformations = self.transformations();
+  }
+
+  pub fn snapshot(&self) -> Snapshot {
+    Snapshot {
+      tick: self.tick,
+      crash_tick: self.crash_tick,
+      parts: self.body_states(),
+      previous_transformations: self.previous_transformations.clone(),
+    }
+  }
+
+  /// Put the simulation back to the tick `snapshot` was taken on, rebuilding
+  /// the world around the rider so that nothing carries over from the steps
+  /// being undone. The world was rebuilt the same way on every multiple of
+  /// KEYFRAME_INTERVAL, so after restoring a Snapshot from one of those ticks
+  /// the steps that follow are the same as those that followed the snapshot.
+  /// The snapshot must have come from a Simulation of the same track.
+  pub fn restore(&mut self, snapshot: &Snapshot) {
+    self.tick = snapshot.tick;
+    self.crash_tick = snapshot.crash_tick;
+    self.build_world(Some(&snapshot.parts));
+    self.previous_transformations = snapshot.previous_transformations.clone();
+    self.accumulator = 0.0;
+  }
+
+  /// The number of steps taken since the rider left its start point.
+  pub fn tick(&self) -> u64 {
+    self.tick
+  }
+
+  /// The simulated time since the rider left its start point, in seconds.
+  pub fn time(&self) -> f64 {
+    self.tick as f64 * TIME_STEP
+  }
+
+  /// The step during which the rider crashed, if it has crashed.
+  pub fn crash_tick(&self) -> Option<u64> {
+    self.crash_tick
+  }
+
+  /// The current position of the center of the rider's torso.
+  pub fn rider_position(&self) -> Point {
+    let torso = self.parts[TORSO].borrow();
+    Point::from_coordinates(torso.position().translation.vector)
+  }
+
+  /// How far the time carried over from the last update reaches into the
+  /// next step, from 0 to 1.
+  pub fn interpolation(&self) -> f64 {
+    self.accumulator / TIME_STEP
+  }
+
+  /// Where to draw one of the rider's bodies: between its poses before and
+  /// after the latest step, by how far wall-clock time has moved past that
+  /// step. This keeps the rider moving smoothly when frames and steps do not
+  /// line up.
+  fn interpolated_pose(&self, index: usize) -> Pose {
+    let t = self.interpolation();
+    let previous = &self.previous_transformations[index];
+    let current = *self.parts[index].borrow().position();
+
+    let previous_position =
+      Point::from_coordinates(previous.translation.vector);
+    let position = Point::from_coordinates(current.translation.vector);
+    Pose {
+      position: previous_position + (position - previous_position) * t,
+      angle: interpolate_angle(
+        previous.rotation.angle(),
+        current.rotation.angle(),
+        t,
+      ),
+    }
+  }
+
+  /// Where to draw each of the rider's bodies of the given kind.
+  fn interpolated_positions(&self, part: Part) -> Vec<Point> {
+    (0..PARTS.len())
+      .filter(|&index| PARTS[index].0 == part)
+      .map(|index| self.interpolated_pose(index).position)
+      .collect()
+  }
+
+  /// Where to draw the center of the rider's torso.
+  pub fn interpolated_rider_position(&self) -> Point {
+    self.interpolated_pose(TORSO).position
+  }
+
+  /// Where to draw each of the rider's bodies.
+  pub fn rider_pose(&self) -> RiderPose {
+    RiderPose {
+      sled: self.interpolated_pose(SLED),
+      torso: self.interpolated_rider_position(),
+      hands: self.interpolated_positions(Part::Hand),
+      feet: self.interpolated_positions(Part::Foot),
+      attached: !self.attachments.is_empty(),
+    }
+  }
+
+  /// The current velocity of the rider's torso.
+  pub fn rider_velocity(&self) -> Vector {
+    self.parts[TORSO].borrow().lin_vel()
+  }
+
+  /// A 64-bit FNV-1a hash of the step count, the crash and the exact bits of
+  /// the position and velocity of each of the rider's bodies, for telling
+  /// whether two runs of a track went the same way.
+  pub fn checksum(&self) -> u64 {
+    let mut values = vec![
+      self.tick,
+      self.crash_tick.map_or(u64::max_value(), |tick| tick),
+    ];
+    for part in self.parts.iter() {
+      let part = part.borrow();
+      let position = part.position();
+      let velocity = part.lin_vel();
+      values.extend_from_slice(&[
+        position.translation.vector.x.to_bits(),
+        position.translation.vector.y.to_bits(),
+        position.rotation.angle().to_bits(),
+        velocity.x.to_bits(),
+        velocity.y.to_bits(),
+        part.ang_vel()[0].to_bits(),
+      ]);
+    }
+
+    let mut hash = CHECKSUM_OFFSET_BASIS;
+    for &value in values.iter() {
+      for byte in 0..8 {
+        hash ^= (value >> (8 * byte)) & 0xff;
+        hash = hash.wrapping_mul(CHECKSUM_PRIME);
+      }
+    }
+
+    hash
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// A slope the rider lands on and slides down.
+  fn slope() -> track::Track {
+    track::Track::with_line(Point::new(0.0, 150.0), Point::new(1000.0, 400.0))
+  }
+
+  #[test]
+  fn runs_of_the_same_track_agree_on_checksums() {
+    let track = slope();
+    let mut simulation1 = Simulation::new(&track);
+    let mut simulation2 = Simulation::new(&track);
+    assert_eq!(simulation1.checksum(), simulation2.checksum());
+
+    for _ in 0..300 {
+      simulation1.step();
+      simulation2.step();
+      assert_eq!(simulation1.checksum(), simulation2.checksum());
+    }
+    assert_eq!(simulation1.tick(), 300);
+  }
+
+  #[test]
+  fn restoring_a_keyframe_repeats_the_run() {
+    let track = slope();
+    let mut simulation = Simulation::new(&track);
+    let mut checksums = Vec::new();
+    for _ in 0..240 {
+      simulation.step();
+      checksums.push(simulation.checksum());
+    }
+
+    let mut restored = Simulation::new(&track);
+    let mut snapshot = None;
+    for _ in 0..200 {
+      restored.step();
+      if restored.tick() == KEYFRAME_INTERVAL {
+        snapshot = Some(restored.snapshot());
+      }
+    }
+    restored.restore(&snapshot.unwrap());
+    assert_eq!(restored.tick(), KEYFRAME_INTERVAL);
+    while restored.tick() < 240 {
+      restored.step();
+      assert_eq!(
+        restored.checksum(),
+        checksums[restored.tick() as usize - 1]
+      );
+    }
+  }
+
+  #[test]
+  fn checksum_changes_as_the_rider_moves() {
+    let mut simulation = Simulation::new(&slope());
+    let start = simulation.checksum();
+    simulation.step();
+
+    assert!(simulation.checksum() != start);
+  }
+
+  #[test]
+  fn different_tracks_give_different_checksums() {
+    let mut other = slope();
+    other.rider_start.x += 1.0;
+    let mut simulation1 = Simulation::new(&slope());
+    let mut simulation2 = Simulation::new(&other);
+    for _ in 0..10 {
+      simulation1.step();
+      simulation2.step();
+    }
+
+    assert!(simulation1.checksum() != simulation2.checksum());
+  }
+
+  #[test]
+  fn lines_far_from_the_rider_change_nothing() {
+    let mut far = slope();
+    for index in 0..5000 {
+      let x = index as f64 * 20.0;
+      far.line_segments.push(LineSegment::new(
+        Point::new(x, 100_000.0),
+        Point::new(x + 20.0, 100_000.0),
+        LineKind::Normal,
+      ));
+    }
+    let mut simulation1 = Simulation::new(&slope());
+    let mut simulation2 = Simulation::new(&far);
+
+    for _ in 0..300 {
+      simulation1.step();
+      simulation2.step();
+      assert_eq!(simulation1.checksum(), simulation2.checksum());
+      assert_eq!(simulation2.colliders.len(), 1);
+    }
+  }
+
+  #[test]
+  fn only_lines_near_the_rider_are_in_the_world() {
+    // The slope, in a hundred pieces.
+    let mut track = track::Track::new();
+    for index in 0..100 {
+      let x = index as f64 * 10.0;
+      track.line_segments.push(LineSegment::new(
+        Point::new(x, 150.0 + x / 4.0),
+        Point::new(x + 10.0, 150.0 + (x + 10.0) / 4.0),
+        LineKind::Normal,
+      ));
+    }
+    let mut simulation = Simulation::new(&track);
+
+    for _ in 0..600 {
+      simulation.step();
+      assert!(simulation.colliders.len() < 50);
+    }
+    // The rider has slid on, leaving the top of the slope out of the world.
+    assert!(!simulation.colliders.contains_key(&0));
+  }
+}
@@ -0,0 +1,273 @@
+extern crate nalgebra;
+
+use spatial_index::SpatialIndex;
+use track::{LineSegment, Point};
+
+/// How the cursor snaps while drawing.
+#[derive(Clone, Debug)]
+pub struct SnapSettings {
+  /// How close, in screen pixels, the cursor must come to the end of a
+  /// segment to snap to it. Zero turns endpoint snapping off.
+  pub endpoint_radius: f64,
+  /// The spacing of the grid, in world units.
+  pub grid_size: f64,
+  /// Whether grid snapping is on to begin with.
+  pub grid: bool,
+  /// The angles, in degrees, whose multiples lines may be snapped to. Angle
+  /// snapping cycles through them.
+  pub angle_steps: Vec<f64>,
+}
+
+impl Default for SnapSettings {
+  fn default() -> SnapSettings {
+    SnapSettings {
+      endpoint_radius: 8.0,
+      grid_size: 16.0,
+      grid: false,
+      angle_steps: vec![15.0, 45.0],
+    }
+  }
+}
+
+/// Which kinds of snapping are turned on.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SnapModes {
+  pub grid: bool,
+  /// The angle, in degrees, whose multiples lines from an anchor snap to, if
+  /// any.
+  pub angle_step: Option<f64>,
+}
+
+/// What a point snapped to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SnapKind {
+  /// Nothing: the point is where the cursor is.
+  None,
+  /// The end of an existing segment.
+  Endpoint,
+  /// A point on the grid.
+  Grid,
+  /// A fixed angle from the anchor.
+  Angle,
+}
+
+/// Where the cursor snapped to.
+#[derive(Clone, Copy, Debug)]
+pub struct Snap {
+  pub point: Point,
+  pub kind: SnapKind,
+}
+
+impl Snap {
+  /// Snap nowhere: stay at `point`.
+  pub fn none(point: Point) -> Snap {
+    Snap {
+      point: point,
+      kind: SnapKind::None,
+    }
+  }
+}
+
+/// The end of a segment nearest `point`, if any is within `radius`.
+pub fn nearest_endpoint(
+  point: &Point,
+  radius: f64,
+  line_segments: &[LineSegment],
+  spatial_index: &SpatialIndex,
+) -> Option<Point> {
+  let mut nearest = None;
+  let mut nearest_distance = radius;
+  for index in spatial_index.query_point(point, radius) {
+    let line_segment = &line_segments[index];
+    for endpoint in [line_segment.point1(), line_segment.point2()].iter() {
+      let distance = nalgebra::distance(*endpoint, point);
+      if distance <= nearest_distance {
+        nearest = Some(**endpoint);
+        nearest_distance = distance;
+      }
+    }
+  }
+
+  nearest
+}
+
+/// The point on a grid with the given spacing nearest `point`.
+pub fn to_grid(point: &Point, grid_size: f64) -> Point {
+  Point::new(
+    (point.x / grid_size).round() * grid_size,
+    (point.y / grid_size).round() * grid_size,
+  )
+}
+
+/// `point` turned about `anchor` to the nearest multiple of `step` degrees,
+/// keeping its distance from `anchor`.
+pub fn to_angle(point: &Point, anchor: &Point, step: f64) -> Point {
+  let offset = point - anchor;
+  let step = step.to_radians();
+  let angle = (offset.y.atan2(offset.x) / step).round() * step;
+  let distance = offset.norm();
+  Point::new(
+    anchor.x + distance * angle.cos(),
+    anchor.y + distance * angle.sin(),
+  )
+}
+
+/// Snap `point`, a cursor position in world coordinates, seen at `zoom`.
+/// Existing endpoints come first, so that lines join up. Otherwise the line
+/// from `anchor`, the other end of the line being drawn, snaps to a fixed
+/// angle, with its length snapped to the grid spacing; without an anchor, the
+/// point snaps to the grid.
+pub fn snap(
+  point: &Point,
+  anchor: Option<&Point>,
+  zoom: f64,
+  settings: &SnapSettings,
+  modes: &SnapModes,
+  line_segments: &[LineSegment],
+  spatial_index: &SpatialIndex,
+) -> Snap {
+  if settings.endpoint_radius > 0.0 {
+    let radius = settings.endpoint_radius / zoom;
+    if let Some(endpoint) =
+      nearest_endpoint(point, radius, line_segments, spatial_index)
+    {
+      return Snap {
+        point: endpoint,
+        kind: SnapKind::Endpoint,
+      };
+    }
+  }
+
+  if let (Some(anchor), Some(step)) = (anchor, modes.angle_step) {
+    let mut snapped = to_angle(point, anchor, step);
+    if modes.grid && settings.grid_size > 0.0 {
+      let offset = snapped - anchor;
+      let distance = offset.norm();
+      if distance > 0.0 {
+        let length = (distance / settings.grid_size).round() *
+          settings.grid_size;
+        snapped = anchor + offset * (length / distance);
+      }
+    }
+    return Snap {
+      point: snapped,
+      kind: SnapKind::Angle,
+    };
+  }
+
+  if modes.grid && settings.grid_size > 0.0 {
+    return Snap {
+      point: to_grid(point, settings.grid_size),
+      kind: SnapKind::Grid,
+    };
+  }
+
+  Snap::none(*point)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use track::LineKind;
+
+  /// Whether `point` is within rounding error of (`x`, `y`).
+  fn near(point: &Point, x: f64, y: f64) -> bool {
+    nalgebra::distance(point, &Point::new(x, y)) < 1e-9
+  }
+
+  /// Snap `point` at zoom 1 among `line_segments`.
+  fn snap_among(
+    point: Point,
+    anchor: Option<Point>,
+    modes: &SnapModes,
+    line_segments: &[LineSegment],
+  ) -> Snap {
+    snap(
+      &point,
+      anchor.as_ref(),
+      1.0,
+      &SnapSettings::default(),
+      modes,
+      line_segments,
+      &SpatialIndex::from_line_segments(line_segments),
+    )
+  }
+
+  #[test]
+  fn points_snap_to_the_grid() {
+    assert_eq!(to_grid(&Point::new(7.0, 9.0), 16.0), Point::new(0.0, 16.0));
+    assert_eq!(
+      to_grid(&Point::new(-25.0, 40.0), 16.0),
+      Point::new(-32.0, 48.0)
+    );
+  }
+
+  #[test]
+  fn lines_snap_to_angles_keeping_their_length() {
+    let anchor = Point::new(10.0, 10.0);
+    let snapped = to_angle(&Point::new(20.0, 12.0), &anchor, 45.0);
+    assert!(near(&snapped, 10.0 + 104.0f64.sqrt(), 10.0));
+
+    let snapped = to_angle(&Point::new(19.0, 20.0), &anchor, 45.0);
+    let side = 181.0f64.sqrt() / 2.0f64.sqrt();
+    assert!(near(&snapped, 10.0 + side, 10.0 + side));
+  }
+
+  #[test]
+  fn endpoints_come_before_other_snapping() {
+    let line_segments = vec![
+      LineSegment::new(
+        Point::new(0.0, 0.0),
+        Point::new(100.0, 3.0),
+        LineKind::Normal,
+      ),
+    ];
+    let modes = SnapModes {
+      grid: true,
+      angle_step: Some(45.0),
+    };
+
+    let snapped = snap_among(
+      Point::new(96.0, 0.0),
+      Some(Point::new(0.0, 50.0)),
+      &modes,
+      &line_segments,
+    );
+    assert_eq!(snapped.kind, SnapKind::Endpoint);
+    assert_eq!(snapped.point, Point::new(100.0, 3.0));
+
+    // Out of the endpoint radius, the grid takes over.
+    let snapped =
+      snap_among(Point::new(90.0, 3.0), None, &modes, &line_segments);
+    assert_eq!(snapped.kind, SnapKind::Grid);
+    assert_eq!(snapped.point, Point::new(96.0, 0.0));
+  }
+
+  #[test]
+  fn angle_snapping_rounds_the_length_to_the_grid() {
+    let modes = SnapModes {
+      grid: true,
+      angle_step: Some(90.0),
+    };
+    let snapped = snap_among(
+      Point::new(2.0, 37.0),
+      Some(Point::new(0.0, 0.0)),
+      &modes,
+      &[],
+    );
+    assert_eq!(snapped.kind, SnapKind::Angle);
+    assert!(near(&snapped.point, 0.0, 32.0));
+  }
+
+  #[test]
+  fn nothing_snaps_with_every_mode_off() {
+    let snapped = snap_among(
+      Point::new(3.5, 7.25),
+      Some(Point::new(0.0, 0.0)),
+      &SnapModes::default(),
+      &[],
+    );
+    assert_eq!(snapped.kind, SnapKind::None);
+    assert_eq!(snapped.point, Point::new(3.5, 7.25));
+  }
+}
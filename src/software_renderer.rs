@@ -0,0 +1,342 @@
+extern crate std;
+
+use render;
+use render::{Color, Transform};
+use track::Point;
+
+/// The most segments a circle is drawn with.
+const MAX_CIRCLE_SEGMENTS: usize = 256;
+/// The fewest segments a circle is drawn with.
+const MIN_CIRCLE_SEGMENTS: usize = 8;
+/// The longest a segment of a circle may be, in pixels.
+const CIRCLE_SEGMENT_LENGTH: f64 = 2.0;
+
+/// A Renderer that draws into an in-memory RGBA image, without a window or a
+/// GPU. Pixels are rows of 8-bit red, green, blue and alpha, top row first,
+/// and a pixel is filled if its center lies inside a shape.
+pub struct SoftwareRenderer {
+  width: u32,
+  height: u32,
+  pixels: Vec<u8>,
+  transform: Transform,
+}
+
+impl SoftwareRenderer {
+  /// Create a SoftwareRenderer with a transparent image of the given size.
+  pub fn new(width: u32, height: u32) -> SoftwareRenderer {
+    SoftwareRenderer {
+      width: width,
+      height: height,
+      pixels: vec![0; width as usize * height as usize * 4],
+      transform: Transform::identity(),
+    }
+  }
+
+  pub fn width(&self) -> u32 {
+    self.width
+  }
+
+  pub fn height(&self) -> u32 {
+    self.height
+  }
+
+  /// The image, as rows of RGBA pixels.
+  pub fn pixels(&self) -> &[u8] {
+    &self.pixels
+  }
+
+  pub fn into_pixels(self) -> Vec<u8> {
+    self.pixels
+  }
+
+  /// The RGBA value of the pixel in column `x` and row `y`.
+  pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+    let index = self.index(x, y);
+    [
+      self.pixels[index],
+      self.pixels[index + 1],
+      self.pixels[index + 2],
+      self.pixels[index + 3],
+    ]
+  }
+
+  fn index(&self, x: u32, y: u32) -> usize {
+    (y as usize * self.width as usize + x as usize) * 4
+  }
+
+  /// Draw `color` over the pixel in column `x` and row `y`.
+  fn blend(&mut self, x: u32, y: u32, color: Color) {
+    let index = self.index(x, y);
+    let alpha = color[3].max(0.0).min(1.0);
+    let destination_alpha = self.pixels[index + 3] as f32 / 255.0;
+    let out_alpha = alpha + destination_alpha * (1.0 - alpha);
+    if out_alpha <= 0.0 {
+      return;
+    }
+
+    for channel in 0..3 {
+      let source = color[channel].max(0.0).min(1.0);
+      let destination = self.pixels[index + channel] as f32 / 255.0;
+      let out = (source * alpha +
+                   destination * destination_alpha * (1.0 - alpha)) /
+        out_alpha;
+      self.pixels[index + channel] = (out * 255.0).round() as u8;
+    }
+    self.pixels[index + 3] = (out_alpha * 255.0).round() as u8;
+  }
+
+  /// Fill a polygon, given in world coordinates, using the even-odd rule.
+  fn fill_polygon(&mut self, points: &[Point], color: Color) {
+    let points: Vec<Point> =
+      points.iter().map(|point| self.transform.apply(point)).collect();
+    if points.len() < 3 {
+      return;
+    }
+
+    let min_y = points.iter().map(|point| point.y).fold(
+      std::f64::INFINITY,
+      f64::min,
+    );
+    let max_y = points.iter().map(|point| point.y).fold(
+      std::f64::NEG_INFINITY,
+      f64::max,
+    );
+    let first_row = (min_y - 0.5).ceil().max(0.0) as u32;
+    let last_row = (max_y - 0.5).floor().min(self.height as f64 - 1.0);
+    if last_row < 0.0 {
+      return;
+    }
+
+    let mut crossings = Vec::with_capacity(points.len());
+    for row in first_row..(last_row as u32 + 1) {
+      let y = row as f64 + 0.5;
+
+      crossings.clear();
+      for (index, point1) in points.iter().enumerate() {
+        let point2 = &points[(index + 1) % points.len()];
+        // Each edge covers the rows from its top inclusive to its bottom
+        // exclusive, so a vertex shared by two edges is only counted once.
+        if (point1.y <= y) != (point2.y <= y) {
+          let t = (y - point1.y) / (point2.y - point1.y);
+          crossings.push(point1.x + (point2.x - point1.x) * t);
+        }
+      }
+      crossings.sort_by(|a, b| {
+        a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal)
+      });
+
+      for span in crossings.chunks(2) {
+        if span.len() < 2 {
+          break;
+        }
+        let first_column = (span[0] - 0.5).ceil().max(0.0) as u32;
+        let last_column = (span[1] - 0.5).ceil().min(self.width as f64);
+        if last_column <= 0.0 {
+          continue;
+        }
+        for column in first_column..(last_column as u32) {
+          self.blend(column, row, color);
+        }
+      }
+    }
+  }
+}
+
+impl render::Renderer for SoftwareRenderer {
+  fn transform(&self) -> Transform {
+    self.transform
+  }
+
+  fn set_transform(&mut self, transform: Transform) {
+    self.transform = transform;
+  }
+
+  fn clear(&mut self, color: Color) {
+    let mut rgba = [0; 4];
+    for channel in 0..4 {
+      rgba[channel] = (color[channel].max(0.0).min(1.0) * 255.0).round() as u8;
+    }
+    for pixel in self.pixels.chunks_mut(4) {
+      pixel.copy_from_slice(&rgba);
+    }
+  }
+
+  fn rectangle(
+    &mut self,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    color: Color,
+  ) {
+    self.fill_polygon(
+      &[
+        Point::new(x, y),
+        Point::new(x + width, y),
+        Point::new(x + width, y + height),
+        Point::new(x, y + height),
+      ],
+      color,
+    );
+  }
+
+  fn circle(&mut self, center: &Point, radius: f64, color: Color) {
+    let circumference =
+      2.0 * std::f64::consts::PI * radius * self.transform.scale();
+    let segments = ((circumference / CIRCLE_SEGMENT_LENGTH).ceil() as usize)
+      .max(MIN_CIRCLE_SEGMENTS)
+      .min(MAX_CIRCLE_SEGMENTS);
+
+    let points: Vec<Point> = (0..segments)
+      .map(|index| {
+        let angle =
+          2.0 * std::f64::consts::PI * index as f64 / segments as f64;
+        Point::new(
+          center.x + radius * angle.cos(),
+          center.y + radius * angle.sin(),
+        )
+      })
+      .collect();
+    self.fill_polygon(&points, color);
+  }
+
+  fn triangles(&mut self, vertices: &[Point], color: Color) {
+    for triangle in vertices.chunks(3) {
+      self.fill_polygon(triangle, color);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use render::Renderer;
+
+  const BLACK: Color = [0.0, 0.0, 0.0, 1.0];
+  const WHITE: Color = [1.0, 1.0, 1.0, 1.0];
+
+  /// A black image of the given size.
+  fn renderer(width: u32, height: u32) -> SoftwareRenderer {
+    let mut renderer = SoftwareRenderer::new(width, height);
+    renderer.clear(BLACK);
+    renderer
+  }
+
+  /// The image as rows of text, with '#' for every pixel drawn on and '.' for
+  /// every pixel left black.
+  fn rows(renderer: &SoftwareRenderer) -> Vec<String> {
+    (0..renderer.height())
+      .map(|y| {
+        (0..renderer.width())
+          .map(|x| if renderer.pixel(x, y) == [0, 0, 0, 255] {
+            '.'
+          } else {
+            '#'
+          })
+          .collect()
+      })
+      .collect()
+  }
+
+  #[test]
+  fn clear() {
+    let mut renderer = SoftwareRenderer::new(2, 1);
+    renderer.clear([1.0, 0.5, 0.0, 1.0]);
+
+    assert_eq!(renderer.pixels(), &[255, 128, 0, 255, 255, 128, 0, 255]);
+  }
+
+  #[test]
+  fn rectangle() {
+    let mut renderer = renderer(6, 5);
+    renderer.rectangle(1.0, 1.0, 3.0, 2.0, WHITE);
+
+    assert_eq!(
+      rows(&renderer),
+      vec!["......", ".###..", ".###..", "......", "......"]
+    );
+    assert_eq!(renderer.pixel(1, 1), [255, 255, 255, 255]);
+  }
+
+  #[test]
+  fn rectangle_through_transform() {
+    let mut renderer = renderer(6, 5);
+    renderer.set_transform(Transform::identity().trans(2.0, 1.0).zoom(2.0));
+    renderer.rectangle(0.0, 0.0, 1.0, 1.0, WHITE);
+
+    assert_eq!(
+      rows(&renderer),
+      vec!["......", "..##..", "..##..", "......", "......"]
+    );
+  }
+
+  #[test]
+  fn line() {
+    let mut renderer = renderer(8, 6);
+    renderer.line(
+      &Point::new(1.0, 3.0),
+      &Point::new(7.0, 3.0),
+      2.0,
+      WHITE,
+    );
+
+    assert_eq!(
+      rows(&renderer),
+      vec![
+        "........",
+        "........",
+        ".######.",
+        ".######.",
+        "........",
+        "........",
+      ]
+    );
+  }
+
+  #[test]
+  fn circle() {
+    let mut renderer = renderer(9, 9);
+    let center = Point::new(4.5, 4.5);
+    renderer.circle(&center, 3.0, WHITE);
+
+    for y in 0..9 {
+      for x in 0..9 {
+        let pixel_center = Point::new(x as f64 + 0.5, y as f64 + 0.5);
+        let distance = (pixel_center - center).norm();
+        // Circles are drawn as polygons just inside them.
+        if distance < 2.5 {
+          assert_eq!(renderer.pixel(x, y), [255, 255, 255, 255]);
+        } else if distance > 3.0 {
+          assert_eq!(renderer.pixel(x, y), [0, 0, 0, 255]);
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn text() {
+    let mut renderer = renderer(5, 7);
+    renderer.text("I", &Point::new(0.0, 0.0), 7.0, WHITE);
+
+    assert_eq!(
+      rows(&renderer),
+      vec![
+        ".###.",
+        "..#..",
+        "..#..",
+        "..#..",
+        "..#..",
+        "..#..",
+        ".###.",
+      ]
+    );
+  }
+
+  #[test]
+  fn translucent_colors_blend() {
+    let mut renderer = renderer(1, 1);
+    renderer.rectangle(0.0, 0.0, 1.0, 1.0, [1.0, 0.0, 0.0, 0.5]);
+
+    assert_eq!(renderer.pixel(0, 0), [128, 0, 0, 255]);
+  }
+}
@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+
+use geometry;
+use track::{LineSegment, Point};
+
+/// The width and height of a cell of a SpatialIndex by default, in world
+/// units. A little larger than the rider, so a query about the rider looks at
+/// only a few cells.
+pub const DEFAULT_CELL_SIZE: f64 = 64.0;
+
+/// The furthest cell from the origin along either axis. Coordinates beyond it
+/// fall in the last cell, so that counting cells cannot overflow.
+const MAX_CELL: f64 = 1.0e15;
+
+/// The most cells a segment may span along either axis and still be kept in
+/// the cells it passes through. Longer segments are kept in a list of their
+/// own that every query looks at, rather than in thousands of cells, and
+/// queries covering more cells than this look at every segment.
+const MAX_CELL_SPAN: i64 = 1024;
+
+/// The column and row of a cell of the grid.
+type Cell = (i64, i64);
+
+/// A uniform grid over a list of line segments, for finding the segments near
+/// a point, in a box or along a path without looking at every segment.
+///
+/// Segments are known by their index in the list, and the index is changed in
+/// step with the list. Removing a segment moves the last segment into its
+/// place, as Vec::swap_remove does, so that only the moved segment's cells
+/// need updating and an edit costs the same however long the track is. Each
+/// segment is kept in every cell it passes through, so a long segment does not
+/// make the cells around it slow to query. Query results are exact, and sorted
+/// by index.
+#[derive(Clone, Debug)]
+pub struct SpatialIndex {
+  cell_size: f64,
+  cells: HashMap<Cell, Vec<usize>>,
+  /// The segments that span too many cells to be kept in them.
+  long_segments: Vec<usize>,
+  segments: Vec<(Point, Point)>,
+}
+
+impl Default for SpatialIndex {
+  fn default() -> SpatialIndex {
+    SpatialIndex::new(DEFAULT_CELL_SIZE)
+  }
+}
+
+/// The cell containing the coordinate `x` along one axis.
+fn cell_coordinate(cell_size: f64, x: f64) -> i64 {
+  let cell = (x / cell_size).floor();
+  if cell.is_nan() {
+    0
+  } else {
+    cell.max(-MAX_CELL).min(MAX_CELL) as i64
+  }
+}
+
+/// How many cells the box around the segment from `point1` to `point2`, grown
+/// by `margin`, spans along its longer axis.
+fn cell_span(
+  cell_size: f64,
+  point1: &Point,
+  point2: &Point,
+  margin: f64,
+) -> i64 {
+  let span = |a: f64, b: f64| {
+    cell_coordinate(cell_size, a.max(b) + margin) -
+      cell_coordinate(cell_size, a.min(b) - margin) + 1
+  };
+  span(point1.x, point2.x).max(span(point1.y, point2.y))
+}
+
+/// Every cell that comes within `margin` of the segment from `point1` to
+/// `point2`, column by column. Some cells just beyond the margin may be
+/// included too.
+fn covered_cells(
+  cell_size: f64,
+  point1: &Point,
+  point2: &Point,
+  margin: f64,
+) -> Vec<Cell> {
+  let (a, b) = if point1.x <= point2.x {
+    (point1, point2)
+  } else {
+    (point2, point1)
+  };
+  let y_at = |x: f64| a.y + (b.y - a.y) * ((x - a.x) / (b.x - a.x));
+
+  let mut cells = Vec::new();
+  let first_column = cell_coordinate(cell_size, a.x - margin);
+  let last_column = cell_coordinate(cell_size, b.x + margin);
+  for column in first_column..(last_column + 1) {
+    // The part of the segment within reach of this column.
+    let left = (column as f64 * cell_size - margin).max(a.x).min(b.x);
+    let right = ((column + 1) as f64 * cell_size + margin).min(b.x).max(left);
+    let (y1, y2) = if b.x > a.x {
+      (y_at(left), y_at(right))
+    } else {
+      (a.y, b.y)
+    };
+
+    let first_row = cell_coordinate(cell_size, y1.min(y2) - margin);
+    let last_row = cell_coordinate(cell_size, y1.max(y2) + margin);
+    for row in first_row..(last_row + 1) {
+      cells.push((column, row));
+    }
+  }
+
+  cells
+}
+
+impl SpatialIndex {
+  /// Create an empty SpatialIndex whose cells are `cell_size` wide and high.
+  pub fn new(cell_size: f64) -> SpatialIndex {
+    SpatialIndex {
+      cell_size: cell_size,
+      cells: HashMap::new(),
+      long_segments: Vec::new(),
+      segments: Vec::new(),
+    }
+  }
+
+  /// Create a SpatialIndex over `line_segments`, with the default cell size.
+  pub fn from_line_segments(line_segments: &[LineSegment]) -> SpatialIndex {
+    let mut index = SpatialIndex::default();
+    for line_segment in line_segments.iter() {
+      index.push(*line_segment.point1(), *line_segment.point2());
+    }
+
+    index
+  }
+
+  /// The number of segments in the index.
+  pub fn len(&self) -> usize {
+    self.segments.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.segments.is_empty()
+  }
+
+  /// Whether the segment from `point1` to `point2` is kept in
+  /// `long_segments` rather than in the cells it passes through.
+  fn is_long(&self, point1: &Point, point2: &Point) -> bool {
+    cell_span(self.cell_size, point1, point2, 0.0) > MAX_CELL_SPAN
+  }
+
+  fn add_to_cells(&mut self, index: usize) {
+    let (point1, point2) = self.segments[index];
+    if self.is_long(&point1, &point2) {
+      self.long_segments.push(index);
+      return;
+    }
+    for cell in covered_cells(self.cell_size, &point1, &point2, 0.0) {
+      self.cells.entry(cell).or_insert_with(Vec::new).push(index);
+    }
+  }
+
+  fn remove_from_cells(&mut self, index: usize) {
+    let (point1, point2) = self.segments[index];
+    if self.is_long(&point1, &point2) {
+      self.long_segments.retain(|&other| other != index);
+      return;
+    }
+    for cell in covered_cells(self.cell_size, &point1, &point2, 0.0) {
+      let now_empty = match self.cells.get_mut(&cell) {
+        Some(indices) => {
+          indices.retain(|&other| other != index);
+          indices.is_empty()
+        },
+        None => false,
+      };
+      if now_empty {
+        self.cells.remove(&cell);
+      }
+    }
+  }
+
+  /// Record that the segment at `from` is now at `to`.
+  fn renumber(&mut self, from: usize, to: usize) {
+    let (point1, point2) = self.segments[to];
+    if self.is_long(&point1, &point2) {
+      for other in self.long_segments.iter_mut() {
+        if *other == from {
+          *other = to;
+        }
+      }
+      return;
+    }
+    for cell in covered_cells(self.cell_size, &point1, &point2, 0.0) {
+      if let Some(indices) = self.cells.get_mut(&cell) {
+        for other in indices.iter_mut() {
+          if *other == from {
+            *other = to;
+          }
+        }
+      }
+    }
+  }
+
+  /// Add the segment from `point1` to `point2` after all the others.
+  pub fn push(&mut self, point1: Point, point2: Point) {
+    self.segments.push((point1, point2));
+    let index = self.segments.len() - 1;
+    self.add_to_cells(index);
+  }
+
+  /// Add the segment from `point1` to `point2` at `index`, moving the segment
+  /// that was there to the end. This undoes `swap_remove(index)`.
+  pub fn swap_insert(&mut self, index: usize, point1: Point, point2: Point) {
+    self.segments.push((point1, point2));
+    let last = self.segments.len() - 1;
+    if index < last {
+      self.segments.swap(index, last);
+      self.renumber(index, last);
+    }
+    self.add_to_cells(index);
+  }
+
+  /// Remove the segment at `index`, moving the last segment into its place.
+  pub fn swap_remove(&mut self, index: usize) {
+    self.remove_from_cells(index);
+    self.segments.swap_remove(index);
+    let last = self.segments.len();
+    if index < last {
+      self.renumber(last, index);
+    }
+  }
+
+  /// Move the segment at `index` to run from `point1` to `point2`.
+  pub fn replace(&mut self, index: usize, point1: Point, point2: Point) {
+    if self.segments[index] == (point1, point2) {
+      return;
+    }
+
+    self.remove_from_cells(index);
+    self.segments[index] = (point1, point2);
+    self.add_to_cells(index);
+  }
+
+  /// The segments kept in any of `cells`, and the long segments, each once
+  /// and in order of index.
+  fn candidates<I>(&self, cells: I) -> Vec<usize>
+  where
+    I: IntoIterator<Item = Cell>,
+  {
+    let mut candidates = self.long_segments.clone();
+    for cell in cells {
+      if let Some(indices) = self.cells.get(&cell) {
+        candidates.extend_from_slice(indices);
+      }
+    }
+    candidates.sort();
+    candidates.dedup();
+
+    candidates
+  }
+
+  /// The segments that pass through `aabb`.
+  pub fn query_aabb(&self, aabb: &geometry::Aabb) -> Vec<usize> {
+    let first_column = cell_coordinate(self.cell_size, aabb.min.x);
+    let last_column = cell_coordinate(self.cell_size, aabb.max.x);
+    let first_row = cell_coordinate(self.cell_size, aabb.min.y);
+    let last_row = cell_coordinate(self.cell_size, aabb.max.y);
+
+    // A box larger than the occupied part of the grid is quicker to answer
+    // by looking at every occupied cell than every cell in the box.
+    let columns = (last_column - first_column + 1) as f64;
+    let rows = (last_row - first_row + 1) as f64;
+    let candidates = if columns * rows > self.cells.len() as f64 {
+      self.candidates(
+        self
+          .cells
+          .keys()
+          .filter(|&&(column, row)| {
+            first_column <= column && column <= last_column &&
+              first_row <= row && row <= last_row
+          })
+          .cloned(),
+      )
+    } else {
+      self.candidates(
+        (first_column..(last_column + 1))
+          .flat_map(|column| {
+            (first_row..(last_row + 1)).map(move |row| (column, row))
+          }),
+      )
+    };
+
+    candidates
+      .into_iter()
+      .filter(|&index| {
+        let (ref point1, ref point2) = self.segments[index];
+        aabb.intersects_segment(point1, point2)
+      })
+      .collect()
+  }
+
+  /// The segments that may come within `margin` of the segment from `point1`
+  /// to `point2`: those in the cells it covers, or every segment if it covers
+  /// too many cells to look at each.
+  fn candidates_near(
+    &self,
+    point1: &Point,
+    point2: &Point,
+    margin: f64,
+  ) -> Vec<usize> {
+    if cell_span(self.cell_size, point1, point2, margin) > MAX_CELL_SPAN {
+      (0..self.segments.len()).collect()
+    } else {
+      self.candidates(covered_cells(self.cell_size, point1, point2, margin))
+    }
+  }
+
+  /// The segments within `radius` of `point`.
+  pub fn query_point(&self, point: &Point, radius: f64) -> Vec<usize> {
+    self
+      .candidates_near(point, point, radius)
+      .into_iter()
+      .filter(|&index| {
+        let (ref point1, ref point2) = self.segments[index];
+        geometry::distance_to_segment(point, point1, point2) <= radius
+      })
+      .collect()
+  }
+
+  /// The segments within `radius` of the segment from `point1` to `point2`:
+  /// those a circle of that radius touches as it sweeps from one point to the
+  /// other.
+  pub fn query_segment(
+    &self,
+    point1: &Point,
+    point2: &Point,
+    radius: f64,
+  ) -> Vec<usize> {
+    self
+      .candidates_near(point1, point2, radius)
+      .into_iter()
+      .filter(|&index| {
+        let (ref other1, ref other2) = self.segments[index];
+        geometry::distance_between_segments(point1, point2, other1, other2) <=
+          radius
+      })
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  extern crate std;
+
+  use super::*;
+
+  /// Short horizontal segments, each 100 units to the right of the last, so
+  /// that each is in cells of its own.
+  fn row_of_segments(count: usize) -> SpatialIndex {
+    let mut index = SpatialIndex::default();
+    for segment in 0..count {
+      let x = segment as f64 * 100.0;
+      index.push(Point::new(x, 0.0), Point::new(x + 10.0, 0.0));
+    }
+    index
+  }
+
+  /// The index of every segment found near the middle of each of the
+  /// original segments of `row_of_segments`.
+  fn found(index: &SpatialIndex, count: usize) -> Vec<Vec<usize>> {
+    (0..count)
+      .map(|segment| {
+        let x = segment as f64 * 100.0 + 5.0;
+        index.query_point(&Point::new(x, 0.0), 1.0)
+      })
+      .collect()
+  }
+
+  #[test]
+  fn swap_remove_moves_last_segment_into_place() {
+    let mut index = row_of_segments(4);
+    index.swap_remove(1);
+
+    assert_eq!(index.len(), 3);
+    assert_eq!(found(&index, 4), vec![vec![0], vec![], vec![2], vec![1]]);
+  }
+
+  #[test]
+  fn swap_insert_undoes_swap_remove() {
+    for removed in 0..4 {
+      let mut index = row_of_segments(4);
+      let x = removed as f64 * 100.0;
+      index.swap_remove(removed);
+      index.swap_insert(
+        removed,
+        Point::new(x, 0.0),
+        Point::new(x + 10.0, 0.0),
+      );
+
+      assert_eq!(found(&index, 4), vec![vec![0], vec![1], vec![2], vec![3]]);
+    }
+  }
+
+  #[test]
+  fn long_segments_are_found() {
+    let mut index = row_of_segments(2);
+    index.push(Point::new(-1.0e12, 50.0), Point::new(1.0e12, 50.0));
+    index.swap_remove(0);
+
+    assert_eq!(index.query_point(&Point::new(5.0, 45.0), 10.0), vec![0]);
+    let aabb = geometry::Aabb::from_corners(
+      &Point::new(0.0, 40.0),
+      &Point::new(10.0, 60.0),
+    );
+    assert_eq!(index.query_aabb(&aabb), vec![0]);
+    let point1 = Point::new(-1.0e12, 0.0);
+    let point2 = Point::new(1.0e12, 0.0);
+    assert_eq!(index.query_segment(&point1, &point2, 1.0), vec![1]);
+  }
+
+  #[test]
+  fn non_finite_points_do_not_panic() {
+    let mut index = row_of_segments(2);
+    index.push(Point::new(std::f64::NAN, 0.0), Point::new(0.0, 0.0));
+    index.push(Point::new(std::f64::INFINITY, 0.0), Point::new(0.0, 0.0));
+    index.query_point(&Point::new(std::f64::NEG_INFINITY, 0.0), 1.0);
+    index.swap_remove(2);
+    index.swap_remove(2);
+
+    assert_eq!(found(&index, 2), vec![vec![0], vec![1]]);
+  }
+}
@@ -0,0 +1,1054 @@
+//! Tracks as SVG documents.
+//!
+//! Exported tracks draw each line segment as a `<line>` whose class is the
+//! name of its kind, and mark the rider's start with a circle. Imported
+//! documents may be drawn in any vector editor: every `<line>`, `<polyline>`,
+//! `<polygon>` and `<path>` becomes line segments, with curves flattened to a
+//! tolerance. The kind of the segments is taken from the class of the element
+//! or of the group it is in, and is normal otherwise. Other shapes are
+//! ignored. One SVG user unit is one world unit.
+
+extern crate std;
+extern crate xml;
+
+use std::io::Write;
+
+use self::xml::attribute::OwnedAttribute;
+use self::xml::reader::{EventReader, XmlEvent as ReadEvent};
+use self::xml::writer::{EmitterConfig, XmlEvent as WriteEvent};
+
+use error;
+use geometry;
+use track;
+use track::{LineKind, LineSegment, Point};
+use track_file;
+
+/// How far, in world units, a curve may stray from the segments it is
+/// flattened to, unless another tolerance is given.
+pub const DEFAULT_TOLERANCE: f64 = 0.5;
+
+/// The most chords an arc may be flattened into, as many as a Bézier curve
+/// may be. An arc that needs more is too large to import.
+const MAX_ARC_STEPS: f64 = 65536.0;
+
+const SVG_NAMESPACE: &'static str = "http://www.w3.org/2000/svg";
+
+/// The width lines are drawn with, as in the editor.
+const STROKE_WIDTH: f64 = 4.0;
+
+/// The space left around the track in an exported document.
+const MARGIN: f64 = 16.0;
+
+/// The class of the circle marking the rider's start.
+const RIDER_START_CLASS: &'static str = "rider-start";
+const RIDER_START_RADIUS: f64 = 5.0;
+
+/// The attribute of the root element holding the track's author.
+const AUTHOR_ATTRIBUTE: &'static str = "data-author";
+
+/// Elements whose contents are not drawn where they appear.
+const UNRENDERED_ELEMENTS: [&'static str; 7] = [
+  "defs",
+  "clipPath",
+  "mask",
+  "marker",
+  "pattern",
+  "symbol",
+  "metadata",
+];
+
+/// The class that segments of a kind are exported with.
+fn kind_class(kind: LineKind) -> &'static str {
+  match kind {
+    LineKind::Normal => "normal",
+    LineKind::Acceleration => "acceleration",
+    LineKind::Scenery => "scenery",
+  }
+}
+
+/// The kind named by the first class in `classes` that names one.
+fn class_kind(classes: &str) -> Option<LineKind> {
+  classes
+    .split_whitespace()
+    .filter_map(|class| match class {
+      "normal" => Some(LineKind::Normal),
+      "acceleration" => Some(LineKind::Acceleration),
+      "scenery" => Some(LineKind::Scenery),
+      _ => None,
+    })
+    .next()
+}
+
+/// An affine transform, as SVG's `matrix(a, b, c, d, e, f)`.
+#[derive(Clone, Copy, Debug)]
+struct Transform {
+  a: f64,
+  b: f64,
+  c: f64,
+  d: f64,
+  e: f64,
+  f: f64,
+}
+
+impl Transform {
+  fn identity() -> Transform {
+    Transform::translation(0.0, 0.0)
+  }
+
+  fn translation(x: f64, y: f64) -> Transform {
+    Transform {
+      a: 1.0,
+      b: 0.0,
+      c: 0.0,
+      d: 1.0,
+      e: x,
+      f: y,
+    }
+  }
+
+  /// The transform that applies `other`, then this one.
+  fn multiply(&self, other: &Transform) -> Transform {
+    Transform {
+      a: self.a * other.a + self.c * other.b,
+      b: self.b * other.a + self.d * other.b,
+      c: self.a * other.c + self.c * other.d,
+      d: self.b * other.c + self.d * other.d,
+      e: self.a * other.e + self.c * other.f + self.e,
+      f: self.b * other.e + self.d * other.f + self.f,
+    }
+  }
+
+  fn apply(&self, point: &Point) -> Point {
+    Point::new(
+      self.a * point.x + self.c * point.y + self.e,
+      self.b * point.x + self.d * point.y + self.f,
+    )
+  }
+
+  /// Roughly the most the transform stretches any distance.
+  fn scale(&self) -> f64 {
+    (self.a * self.a + self.b * self.b)
+      .max(self.c * self.c + self.d * self.d)
+      .sqrt()
+  }
+
+  /// Parse a `transform` attribute, such as `translate(10 20) scale(2)`.
+  fn parse(text: &str) -> error::Result<Transform> {
+    let invalid =
+      || error::Error::from(format!("Invalid SVG transform: {}", text));
+
+    let mut transform = Transform::identity();
+    let mut rest = text;
+    loop {
+      rest = rest.trim_left_matches(|c: char| c.is_whitespace() || c == ',');
+      if rest.is_empty() {
+        return Ok(transform);
+      }
+      let open = rest.find('(').ok_or_else(&invalid)?;
+      let close = rest.find(')').ok_or_else(&invalid)?;
+      if close < open {
+        return Err(invalid());
+      }
+
+      let mut tokens = Tokens::new(&rest[open + 1..close]);
+      let mut arguments = Vec::new();
+      while !tokens.at_end() {
+        arguments.push(tokens.number()?);
+      }
+
+      let next = match (rest[..open].trim(), arguments.len()) {
+        ("matrix", 6) => Transform {
+          a: arguments[0],
+          b: arguments[1],
+          c: arguments[2],
+          d: arguments[3],
+          e: arguments[4],
+          f: arguments[5],
+        },
+        ("translate", 1) => Transform::translation(arguments[0], 0.0),
+        ("translate", 2) => Transform::translation(arguments[0], arguments[1]),
+        ("scale", 1) | ("scale", 2) => Transform {
+          a: arguments[0],
+          d: *arguments.last().unwrap(),
+          ..Transform::identity()
+        },
+        ("rotate", 1) | ("rotate", 3) => {
+          let (sin, cos) = arguments[0].to_radians().sin_cos();
+          let rotation = Transform {
+            a: cos,
+            b: sin,
+            c: -sin,
+            d: cos,
+            ..Transform::identity()
+          };
+          if arguments.len() == 3 {
+            // Rotate about the given point rather than the origin.
+            Transform::translation(arguments[1], arguments[2])
+              .multiply(&rotation)
+              .multiply(&Transform::translation(-arguments[1], -arguments[2]))
+          } else {
+            rotation
+          }
+        },
+        ("skewX", 1) => Transform {
+          c: arguments[0].to_radians().tan(),
+          ..Transform::identity()
+        },
+        ("skewY", 1) => Transform {
+          b: arguments[0].to_radians().tan(),
+          ..Transform::identity()
+        },
+        _ => return Err(invalid()),
+      };
+      transform = transform.multiply(&next);
+      rest = &rest[close + 1..];
+    }
+  }
+}
+
+fn is_digit(byte: u8) -> bool {
+  b'0' <= byte && byte <= b'9'
+}
+
+/// Reads the numbers, and in path data the commands, of an attribute.
+struct Tokens<'text> {
+  text: &'text str,
+  position: usize,
+}
+
+impl<'text> Tokens<'text> {
+  fn new(text: &'text str) -> Tokens<'text> {
+    Tokens {
+      text: text,
+      position: 0,
+    }
+  }
+
+  /// The next byte that is not whitespace or a comma.
+  fn peek(&mut self) -> Option<u8> {
+    let text = self.text;
+    let bytes = text.as_bytes();
+    while self.position < bytes.len() &&
+      (bytes[self.position] == b',' ||
+        (bytes[self.position] as char).is_whitespace())
+    {
+      self.position += 1;
+    }
+    bytes.get(self.position).cloned()
+  }
+
+  fn at_end(&mut self) -> bool {
+    self.peek().is_none()
+  }
+
+  fn at_number(&mut self) -> bool {
+    match self.peek() {
+      Some(byte) => {
+        is_digit(byte) || byte == b'.' || byte == b'-' || byte == b'+'
+      },
+      None => false,
+    }
+  }
+
+  fn error(&self, expected: &str) -> error::Error {
+    error::Error::from(format!(
+      "Expected {} at position {} of \"{}\"",
+      expected,
+      self.position,
+      self.text,
+    ))
+  }
+
+  /// Read a path command letter.
+  fn command(&mut self) -> error::Result<u8> {
+    match self.peek() {
+      Some(byte) if (byte as char).is_alphabetic() => {
+        self.position += 1;
+        Ok(byte)
+      },
+      _ => Err(self.error("a command")),
+    }
+  }
+
+  fn number(&mut self) -> error::Result<f64> {
+    self.peek();
+    let text = self.text;
+    let bytes = text.as_bytes();
+    let digits = |end: &mut usize| {
+      while *end < bytes.len() && is_digit(bytes[*end]) {
+        *end += 1;
+      }
+    };
+    let sign = |end: &mut usize| {
+      if *end < bytes.len() && (bytes[*end] == b'-' || bytes[*end] == b'+') {
+        *end += 1;
+      }
+    };
+
+    // Numbers may follow each other without a separator, as in "1.5.5-2",
+    // so read only as much as makes up one number.
+    let mut end = self.position;
+    sign(&mut end);
+    digits(&mut end);
+    if end < bytes.len() && bytes[end] == b'.' {
+      end += 1;
+      digits(&mut end);
+    }
+    if end < bytes.len() && (bytes[end] == b'e' || bytes[end] == b'E') {
+      let mut exponent_end = end + 1;
+      sign(&mut exponent_end);
+      if exponent_end < bytes.len() && is_digit(bytes[exponent_end]) {
+        digits(&mut exponent_end);
+        end = exponent_end;
+      }
+    }
+
+    match text[self.position..end].parse::<f64>() {
+      Ok(number) if number.is_finite() => {
+        self.position = end;
+        Ok(number)
+      },
+      _ => Err(self.error("a number")),
+    }
+  }
+
+  /// Read an arc flag, which need not be followed by a separator.
+  fn flag(&mut self) -> error::Result<bool> {
+    let flag = match self.peek() {
+      Some(b'0') => false,
+      Some(b'1') => true,
+      _ => return Err(self.error("a flag")),
+    };
+    self.position += 1;
+    Ok(flag)
+  }
+
+  /// Read a point, offset by `origin`.
+  fn point(&mut self, origin: &Point) -> error::Result<Point> {
+    let x = self.number()?;
+    let y = self.number()?;
+    Ok(Point::new(origin.x + x, origin.y + y))
+  }
+}
+
+/// Builds the polylines a path is made of, in world coordinates, from points
+/// in the path's own coordinates.
+struct PathBuilder<'transform> {
+  transform: &'transform Transform,
+  tolerance: f64,
+  polylines: Vec<Vec<Point>>,
+  /// The end of the last command.
+  current: Point,
+  /// The start of the current subpath.
+  start: Point,
+}
+
+impl<'transform> PathBuilder<'transform> {
+  fn new(
+    transform: &'transform Transform,
+    tolerance: f64,
+  ) -> PathBuilder<'transform> {
+    let origin = Point::new(0.0, 0.0);
+    PathBuilder {
+      transform: transform,
+      tolerance: tolerance,
+      polylines: Vec::new(),
+      current: origin,
+      start: origin,
+    }
+  }
+
+  fn move_to(&mut self, point: Point) {
+    self.polylines.push(vec![self.transform.apply(&point)]);
+    self.current = point;
+    self.start = point;
+  }
+
+  /// The polyline being built, which starts at the current point.
+  fn polyline(&mut self) -> &mut Vec<Point> {
+    self.polylines.last_mut().expect("Path drawn before moving")
+  }
+
+  fn line_to(&mut self, point: Point) {
+    let transformed = self.transform.apply(&point);
+    self.polyline().push(transformed);
+    self.current = point;
+  }
+
+  fn cubic_to(&mut self, control1: &Point, control2: &Point, point: Point) {
+    // Affine transforms map curves to curves, so flatten in world
+    // coordinates, where the tolerance applies.
+    let point0 = self.transform.apply(&self.current);
+    let point1 = self.transform.apply(control1);
+    let point2 = self.transform.apply(control2);
+    let point3 = self.transform.apply(&point);
+    let tolerance = self.tolerance;
+    geometry::flatten_cubic(
+      &point0,
+      &point1,
+      &point2,
+      &point3,
+      tolerance,
+      self.polyline(),
+    );
+    self.current = point;
+  }
+
+  fn quadratic_to(&mut self, control: &Point, point: Point) {
+    let point0 = self.transform.apply(&self.current);
+    let point1 = self.transform.apply(control);
+    let point2 = self.transform.apply(&point);
+    let tolerance = self.tolerance;
+    geometry::flatten_quadratic(
+      &point0,
+      &point1,
+      &point2,
+      tolerance,
+      self.polyline(),
+    );
+    self.current = point;
+  }
+
+  /// Draw an elliptical arc, as described in the "Elliptical arc
+  /// implementation notes" of the SVG specification. Fails if flattening the
+  /// arc to the tolerance would take more than MAX_ARC_STEPS chords.
+  fn arc_to(
+    &mut self,
+    radii: (f64, f64),
+    rotation: f64,
+    large_arc: bool,
+    sweep: bool,
+    point: Point,
+  ) -> error::Result<()> {
+    let start = self.current;
+    if start == point {
+      return Ok(());
+    }
+    let (mut rx, mut ry) = (radii.0.abs(), radii.1.abs());
+    if rx == 0.0 || ry == 0.0 {
+      self.line_to(point);
+      return Ok(());
+    }
+
+    // Find the ellipse's center, in coordinates rotated to its axes.
+    let (sin, cos) = rotation.to_radians().sin_cos();
+    let dx = (start.x - point.x) / 2.0;
+    let dy = (start.y - point.y) / 2.0;
+    let x1 = cos * dx + sin * dy;
+    let y1 = -sin * dx + cos * dy;
+    // Radii too small to reach the end are scaled up until they do.
+    let lambda = (x1 * x1) / (rx * rx) + (y1 * y1) / (ry * ry);
+    if lambda > 1.0 {
+      rx *= lambda.sqrt();
+      ry *= lambda.sqrt();
+    }
+    let numerator = rx * rx * ry * ry - rx * rx * y1 * y1 - ry * ry * x1 * x1;
+    let denominator = rx * rx * y1 * y1 + ry * ry * x1 * x1;
+    let mut coefficient = (numerator / denominator).max(0.0).sqrt();
+    if large_arc == sweep {
+      coefficient = -coefficient;
+    }
+    let center_x1 = coefficient * rx * y1 / ry;
+    let center_y1 = -coefficient * ry * x1 / rx;
+    let center = Point::new(
+      cos * center_x1 - sin * center_y1 + (start.x + point.x) / 2.0,
+      sin * center_x1 + cos * center_y1 + (start.y + point.y) / 2.0,
+    );
+
+    let angle = |ux: f64, uy: f64, vx: f64, vy: f64| {
+      (ux * vy - uy * vx).atan2(ux * vx + uy * vy)
+    };
+    let start_angle =
+      angle(1.0, 0.0, (x1 - center_x1) / rx, (y1 - center_y1) / ry);
+    let mut sweep_angle = angle(
+      (x1 - center_x1) / rx,
+      (y1 - center_y1) / ry,
+      (-x1 - center_x1) / rx,
+      (-y1 - center_y1) / ry,
+    );
+    if !sweep && sweep_angle > 0.0 {
+      sweep_angle -= 2.0 * std::f64::consts::PI;
+    } else if sweep && sweep_angle < 0.0 {
+      sweep_angle += 2.0 * std::f64::consts::PI;
+    }
+
+    // Take steps small enough that each chord stays within the tolerance of
+    // the arc.
+    let radius = rx.max(ry) * self.transform.scale();
+    let step = if radius > self.tolerance {
+      2.0 * (1.0 - self.tolerance / radius).acos()
+    } else {
+      std::f64::consts::FRAC_PI_2
+    };
+    // A radius so large that the tolerance is lost against it leaves no step
+    // at all.
+    let steps = (sweep_angle.abs() / step).ceil().max(1.0);
+    if steps > MAX_ARC_STEPS {
+      return Err(error::Error::from(format!(
+        "SVG arc with radii {} and {} is too large to flatten",
+        radii.0,
+        radii.1
+      )));
+    }
+    let steps = steps as usize;
+    for index in 1..steps {
+      let theta = start_angle + sweep_angle * index as f64 / steps as f64;
+      let (theta_sin, theta_cos) = theta.sin_cos();
+      let on_arc = Point::new(
+        center.x + rx * theta_cos * cos - ry * theta_sin * sin,
+        center.y + rx * theta_cos * sin + ry * theta_sin * cos,
+      );
+      self.line_to(on_arc);
+    }
+    self.line_to(point);
+    Ok(())
+  }
+
+  fn close(&mut self) {
+    let start = self.start;
+    if self.current != start {
+      self.line_to(start);
+    }
+    // Drawing may go on from the start without moving first.
+    self.move_to(start);
+  }
+}
+
+/// The polylines described by a path's data, in world coordinates.
+fn path_polylines(
+  data: &str,
+  transform: &Transform,
+  tolerance: f64,
+) -> error::Result<Vec<Vec<Point>>> {
+  let mut tokens = Tokens::new(data);
+  let mut path = PathBuilder::new(transform, tolerance);
+  let mut command = None;
+  // The second control point of the last cubic curve, or the control point
+  // of the last quadratic curve, which smooth curves reflect.
+  let mut last_cubic_control = None;
+  let mut last_quadratic_control = None;
+
+  while !tokens.at_end() {
+    // A command's letter may be left out when it repeats.
+    if !tokens.at_number() {
+      command = Some(tokens.command()?);
+    }
+    let letter = match command {
+      Some(letter) => letter,
+      None => return Err(tokens.error("a command")),
+    };
+    if path.polylines.is_empty() && letter != b'M' && letter != b'm' {
+      return Err(tokens.error("a move"));
+    }
+
+    let origin = if (letter as char).is_lowercase() {
+      path.current
+    } else {
+      Point::new(0.0, 0.0)
+    };
+    let current = path.current;
+    let reflect = |control: Option<Point>| match control {
+      Some(control) => current + (current - control),
+      None => current,
+    };
+    let (mut cubic_control, mut quadratic_control) = (None, None);
+
+    match letter.to_ascii_uppercase() {
+      b'M' => {
+        let point = tokens.point(&origin)?;
+        path.move_to(point);
+        // Pairs after the first are lines.
+        command = Some(if letter == b'M' { b'L' } else { b'l' });
+      },
+      b'L' => {
+        let point = tokens.point(&origin)?;
+        path.line_to(point);
+      },
+      b'H' => {
+        let x = origin.x + tokens.number()?;
+        path.line_to(Point::new(x, current.y));
+      },
+      b'V' => {
+        let y = origin.y + tokens.number()?;
+        path.line_to(Point::new(current.x, y));
+      },
+      b'C' => {
+        let control1 = tokens.point(&origin)?;
+        let control2 = tokens.point(&origin)?;
+        let point = tokens.point(&origin)?;
+        path.cubic_to(&control1, &control2, point);
+        cubic_control = Some(control2);
+      },
+      b'S' => {
+        let control1 = reflect(last_cubic_control);
+        let control2 = tokens.point(&origin)?;
+        let point = tokens.point(&origin)?;
+        path.cubic_to(&control1, &control2, point);
+        cubic_control = Some(control2);
+      },
+      b'Q' => {
+        let control = tokens.point(&origin)?;
+        let point = tokens.point(&origin)?;
+        path.quadratic_to(&control, point);
+        quadratic_control = Some(control);
+      },
+      b'T' => {
+        let control = reflect(last_quadratic_control);
+        let point = tokens.point(&origin)?;
+        path.quadratic_to(&control, point);
+        quadratic_control = Some(control);
+      },
+      b'A' => {
+        let rx = tokens.number()?;
+        let ry = tokens.number()?;
+        let rotation = tokens.number()?;
+        let large_arc = tokens.flag()?;
+        let sweep = tokens.flag()?;
+        let point = tokens.point(&origin)?;
+        path.arc_to((rx, ry), rotation, large_arc, sweep, point)?;
+      },
+      b'Z' => {
+        path.close();
+        // Z takes no numbers, so it is never repeated implicitly.
+        command = None;
+      },
+      _ => {
+        return Err(error::Error::from(
+          format!("Unknown SVG path command: {}", letter as char),
+        ));
+      },
+    }
+    last_cubic_control = cubic_control;
+    last_quadratic_control = quadratic_control;
+  }
+
+  Ok(path.polylines)
+}
+
+/// The value of the attribute called `name`, if there is one.
+fn attribute<'attributes>(
+  attributes: &'attributes [OwnedAttribute],
+  name: &str,
+) -> Option<&'attributes str> {
+  attributes
+    .iter()
+    .find(|attribute| attribute.name.local_name == name)
+    .map(|attribute| attribute.value.as_str())
+}
+
+/// The length in the attribute called `name`, which is 0 if there is none.
+fn length(attributes: &[OwnedAttribute], name: &str) -> error::Result<f64> {
+  match attribute(attributes, name) {
+    Some(value) => {
+      let value = value.trim();
+      match value.trim_right_matches("px").parse::<f64>() {
+        Ok(length) if length.is_finite() => Ok(length),
+        _ => Err(error::Error::from(format!("Invalid SVG length: {}", value))),
+      }
+    },
+    None => Ok(0.0),
+  }
+}
+
+/// The points in a `points` attribute, in world coordinates.
+fn points(
+  attributes: &[OwnedAttribute],
+  transform: &Transform,
+) -> error::Result<Vec<Point>> {
+  let mut tokens = Tokens::new(attribute(attributes, "points").unwrap_or(""));
+  let mut points = Vec::new();
+  let origin = Point::new(0.0, 0.0);
+  while !tokens.at_end() {
+    points.push(transform.apply(&tokens.point(&origin)?));
+  }
+  Ok(points)
+}
+
+/// What an element passes on to the elements inside it.
+struct Scope {
+  transform: Transform,
+  kind: Option<LineKind>,
+  /// Whether the element is not drawn, and nor is anything inside it.
+  hidden: bool,
+}
+
+/// Text of the document that becomes part of the track's metadata.
+#[derive(Clone, Copy, PartialEq)]
+enum Text {
+  Title,
+  Description,
+}
+
+/// Read a track drawn in an SVG document from a reader, flattening curves to
+/// within `tolerance` world units.
+pub fn from_reader<Reader>(
+  reader: Reader,
+  tolerance: f64,
+) -> error::Result<track::Track>
+where
+  Reader: std::io::Read,
+{
+  use error::ResultExt; // chain_err
+
+  let mut track = track::Track::new();
+  let mut scopes: Vec<Scope> = Vec::new();
+  let mut text = None;
+
+  for event in EventReader::new(reader) {
+    match event? {
+      ReadEvent::StartElement {
+        name, attributes, ..
+      } => {
+        let name = name.local_name;
+        let (parent_transform, parent_kind, parent_hidden) =
+          match scopes.last() {
+            Some(scope) => (scope.transform, scope.kind, scope.hidden),
+            None => (Transform::identity(), None, false),
+          };
+        let transform = match attribute(&attributes, "transform") {
+          Some(value) => parent_transform.multiply(&Transform::parse(value)?),
+          None => parent_transform,
+        };
+        let classes = attribute(&attributes, "class").unwrap_or("");
+        let kind = class_kind(classes).or(parent_kind);
+        let hidden =
+          parent_hidden || UNRENDERED_ELEMENTS.contains(&name.as_str());
+
+        let mut polylines = Vec::new();
+        if !hidden {
+          match name.as_str() {
+            "svg" if scopes.is_empty() => {
+              if let Some(author) = attribute(&attributes, AUTHOR_ATTRIBUTE) {
+                track.metadata.author = String::from(author);
+              }
+            },
+            "title" if scopes.len() == 1 => text = Some(Text::Title),
+            "desc" if scopes.len() == 1 => text = Some(Text::Description),
+            "line" => {
+              let point1 = Point::new(
+                length(&attributes, "x1")?,
+                length(&attributes, "y1")?,
+              );
+              let point2 = Point::new(
+                length(&attributes, "x2")?,
+                length(&attributes, "y2")?,
+              );
+              polylines.push(vec![
+                transform.apply(&point1),
+                transform.apply(&point2),
+              ]);
+            },
+            "polyline" => polylines.push(points(&attributes, &transform)?),
+            "polygon" => {
+              let mut points = points(&attributes, &transform)?;
+              if let Some(&first) = points.first() {
+                points.push(first);
+              }
+              polylines.push(points);
+            },
+            "path" => {
+              let data = attribute(&attributes, "d").unwrap_or("");
+              polylines = path_polylines(data, &transform, tolerance)
+                .chain_err(|| format!("Invalid SVG path data: {}", data))?;
+            },
+            "circle" if classes
+              .split_whitespace()
+              .any(|class| class == RIDER_START_CLASS) =>
+            {
+              track.rider_start = transform.apply(&Point::new(
+                length(&attributes, "cx")?,
+                length(&attributes, "cy")?,
+              ));
+            },
+            _ => {},
+          }
+        }
+
+        for polyline in polylines.iter() {
+          for pair in polyline.windows(2) {
+            if pair[0] != pair[1] {
+              track.line_segments.push(LineSegment::new(
+                pair[0],
+                pair[1],
+                kind.unwrap_or(LineKind::Normal),
+              ));
+            }
+          }
+        }
+
+        scopes.push(Scope {
+          transform: transform,
+          kind: kind,
+          hidden: hidden,
+        });
+      },
+      ReadEvent::EndElement { .. } => {
+        scopes.pop();
+        text = None;
+      },
+      ReadEvent::Characters(characters) | ReadEvent::CData(characters) => {
+        match text {
+          Some(Text::Title) => track.metadata.name.push_str(&characters),
+          Some(Text::Description) => {
+            track.metadata.description.push_str(&characters)
+          },
+          None => {},
+        }
+      },
+      _ => {},
+    }
+  }
+
+  track_file::check_points(&track)?;
+  Ok(track)
+}
+
+/// Write a Track to a writer as an SVG document.
+pub fn to_writer<Writer>(
+  writer: Writer,
+  track: &track::Track,
+) -> error::Result<()>
+where
+  Writer: Write,
+{
+  let mut bounds =
+    geometry::Aabb::from_corners(&track.rider_start, &track.rider_start);
+  for line_segment in track.line_segments.iter() {
+    bounds.include(line_segment.point1());
+    bounds.include(line_segment.point2());
+  }
+  let bounds = bounds.expanded(MARGIN);
+  let view_box = format!(
+    "{} {} {} {}",
+    bounds.min.x,
+    bounds.min.y,
+    bounds.width(),
+    bounds.height(),
+  );
+  let width = bounds.width().to_string();
+  let height = bounds.height().to_string();
+
+  let mut writer = EmitterConfig::new()
+    .perform_indent(true)
+    .create_writer(writer);
+  let mut root = WriteEvent::start_element("svg")
+    .default_ns(SVG_NAMESPACE)
+    .attr("width", &width)
+    .attr("height", &height)
+    .attr("viewBox", &view_box);
+  if !track.metadata.author.is_empty() {
+    root = root.attr(AUTHOR_ATTRIBUTE, &track.metadata.author);
+  }
+  writer.write(root)?;
+
+  let texts = [
+    ("title", &track.metadata.name),
+    ("desc", &track.metadata.description),
+  ];
+  for &(name, text) in texts.iter() {
+    if !text.is_empty() {
+      writer.write(WriteEvent::start_element(name))?;
+      writer.write(WriteEvent::characters(text))?;
+      writer.write(WriteEvent::end_element())?;
+    }
+  }
+
+  // Lines are colored as in the editor.
+  let style = format!(
+    "line {{ stroke-width: {}; stroke-linecap: round; }} \
+     .normal {{ stroke: black; }} \
+     .acceleration {{ stroke: red; }} \
+     .scenery {{ stroke: lime; }} \
+     .{} {{ fill: blue; }}",
+    STROKE_WIDTH,
+    RIDER_START_CLASS,
+  );
+  writer.write(WriteEvent::start_element("style"))?;
+  writer.write(WriteEvent::characters(&style))?;
+  writer.write(WriteEvent::end_element())?;
+
+  let cx = track.rider_start.x.to_string();
+  let cy = track.rider_start.y.to_string();
+  let radius = RIDER_START_RADIUS.to_string();
+  writer.write(
+    WriteEvent::start_element("circle")
+      .attr("class", RIDER_START_CLASS)
+      .attr("cx", &cx)
+      .attr("cy", &cy)
+      .attr("r", &radius),
+  )?;
+  writer.write(WriteEvent::end_element())?;
+
+  for line_segment in track.line_segments.iter() {
+    let x1 = line_segment.point1().x.to_string();
+    let y1 = line_segment.point1().y.to_string();
+    let x2 = line_segment.point2().x.to_string();
+    let y2 = line_segment.point2().y.to_string();
+    writer.write(
+      WriteEvent::start_element("line")
+        .attr("class", kind_class(line_segment.kind()))
+        .attr("x1", &x1)
+        .attr("y1", &y1)
+        .attr("x2", &x2)
+        .attr("y2", &y2),
+    )?;
+    writer.write(WriteEvent::end_element())?;
+  }
+
+  writer.write(WriteEvent::end_element())?;
+  writer.into_inner().flush()?;
+  Ok(())
+}
+
+/// Save a Track to the SVG document at `path`.
+pub fn save(
+  path: &std::path::Path,
+  track: &track::Track,
+) -> error::Result<()> {
+  let file = std::fs::File::create(path)?;
+  to_writer(std::io::BufWriter::new(file), track)
+}
+
+/// Load a Track from the SVG document at `path`, flattening curves to within
+/// DEFAULT_TOLERANCE.
+pub fn load(path: &std::path::Path) -> error::Result<track::Track> {
+  let file = std::fs::File::open(path)?;
+  from_reader(std::io::BufReader::new(file), DEFAULT_TOLERANCE)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn read(svg: &str) -> track::Track {
+    from_reader(svg.as_bytes(), DEFAULT_TOLERANCE).unwrap()
+  }
+
+  /// The ends of each of a track's segments, and its kind.
+  fn segments(track: &track::Track) -> Vec<([f64; 4], LineKind)> {
+    track
+      .line_segments
+      .iter()
+      .map(|line_segment| {
+        let (point1, point2) = (line_segment.point1(), line_segment.point2());
+        ([point1.x, point1.y, point2.x, point2.y], line_segment.kind())
+      })
+      .collect()
+  }
+
+  #[test]
+  fn round_trip_keeps_the_track() {
+    let mut track = track::Track::new();
+    track.metadata.name = String::from("Hill & dale");
+    track.metadata.author = String::from("Someone");
+    track.metadata.description = String::from("<Steep>");
+    track.rider_start = Point::new(10.0, -20.0);
+    track.line_segments = vec![
+      LineSegment::new(
+        Point::new(0.0, 0.0),
+        Point::new(100.5, 50.25),
+        LineKind::Normal,
+      ),
+      LineSegment::new(
+        Point::new(100.5, 50.25),
+        Point::new(200.0, 1.0 / 3.0),
+        LineKind::Acceleration,
+      ),
+      LineSegment::new(
+        Point::new(-1.0e6, -50.0),
+        Point::new(10.0, -60.0),
+        LineKind::Scenery,
+      ),
+    ];
+
+    let mut bytes = Vec::new();
+    to_writer(&mut bytes, &track).unwrap();
+    let read = from_reader(bytes.as_slice(), DEFAULT_TOLERANCE).unwrap();
+
+    assert_eq!(read.metadata.name, track.metadata.name);
+    assert_eq!(read.metadata.author, track.metadata.author);
+    assert_eq!(read.metadata.description, track.metadata.description);
+    assert_eq!(read.rider_start, track.rider_start);
+    assert_eq!(read.line_segments, track.line_segments);
+  }
+
+  #[test]
+  fn shapes_become_segments_of_their_class() {
+    let track = read(
+      r#"<svg xmlns="http://www.w3.org/2000/svg">
+        <defs><line x1="0" y1="0" x2="1" y2="1"/></defs>
+        <polyline points="0,0 10,0 10,10"/>
+        <g class="acceleration" transform="translate(100 0)">
+          <polygon points="0,0 10,0 0,10"/>
+          <path class="scenery" d="M 0 20 h 10 v 10 z"/>
+        </g>
+        <rect x="0" y="0" width="10" height="10"/>
+      </svg>"#,
+    );
+
+    assert_eq!(
+      segments(&track),
+      vec![
+        ([0.0, 0.0, 10.0, 0.0], LineKind::Normal),
+        ([10.0, 0.0, 10.0, 10.0], LineKind::Normal),
+        ([100.0, 0.0, 110.0, 0.0], LineKind::Acceleration),
+        ([110.0, 0.0, 100.0, 10.0], LineKind::Acceleration),
+        ([100.0, 10.0, 100.0, 0.0], LineKind::Acceleration),
+        ([100.0, 20.0, 110.0, 20.0], LineKind::Scenery),
+        ([110.0, 20.0, 110.0, 30.0], LineKind::Scenery),
+        ([110.0, 30.0, 100.0, 20.0], LineKind::Scenery),
+      ]
+    );
+  }
+
+  #[test]
+  fn curves_are_flattened_onto_the_curve() {
+    let track = read(
+      r#"<svg xmlns="http://www.w3.org/2000/svg">
+        <path d="M 0 0 Q 50 100 100 0"/>
+      </svg>"#,
+    );
+
+    // The curve is y = 2x(1 - x/100), from (0, 0) to (100, 0).
+    let segments = segments(&track);
+    assert!(segments.len() > 4);
+    assert_eq!(segments.first().unwrap().0[..2], [0.0, 0.0]);
+    assert_eq!(segments.last().unwrap().0[2..], [100.0, 0.0]);
+    for &(ends, _) in segments.iter() {
+      let (x, y) = (ends[2], ends[3]);
+      assert!((y - 2.0 * x * (1.0 - x / 100.0)).abs() < 1e-9);
+    }
+  }
+
+  #[test]
+  fn numbers_that_are_not_finite_are_rejected() {
+    let svgs = [
+      r#"<svg xmlns="http://www.w3.org/2000/svg">
+        <line x1="0" y1="0" x2="inf" y2="0"/>
+      </svg>"#,
+      r#"<svg xmlns="http://www.w3.org/2000/svg">
+        <path d="M 0 0 L 1e999 0"/>
+      </svg>"#,
+    ];
+    for svg in svgs.iter() {
+      assert!(from_reader(svg.as_bytes(), DEFAULT_TOLERANCE).is_err());
+    }
+  }
+
+  #[test]
+  fn arcs_too_large_to_flatten_are_rejected() {
+    let svg = r#"<svg xmlns="http://www.w3.org/2000/svg">
+      <path d="M0 0 A1e20 1e20 0 1 1 10 0"/>
+    </svg>"#;
+    assert!(from_reader(svg.as_bytes(), DEFAULT_TOLERANCE).is_err());
+
+    // A large arc that can be flattened still is.
+    let track = read(
+      r#"<svg xmlns="http://www.w3.org/2000/svg">
+        <path d="M0 0 A1e4 1e4 0 0 1 10 0"/>
+      </svg>"#,
+    );
+    let segments = segments(&track);
+    assert_eq!(segments.last().unwrap().0[2..], [10.0, 0.0]);
+  }
+}
@@ -0,0 +1,157 @@
+use simulation;
+use track;
+
+/// How many steps apart a Timeline's snapshots are. Seeking re-simulates at
+/// most this many steps. Snapshots are taken on the Simulation's keyframes,
+/// so that restoring one picks the ride up exactly where it was.
+const SNAPSHOT_INTERVAL: u64 = simulation::KEYFRAME_INTERVAL;
+
+/// A Simulation that can be paused, stepped one tick at a time in either
+/// direction, and moved to any tick it has reached. A snapshot is kept every
+/// SNAPSHOT_INTERVAL steps, and seeking restores the nearest one before the
+/// target and simulates forward from there.
+pub struct Timeline {
+  simulation: simulation::Simulation,
+  /// The snapshot at tick `i * SNAPSHOT_INTERVAL` is at index `i`.
+  snapshots: Vec<simulation::Snapshot>,
+  furthest_tick: u64,
+  paused: bool,
+}
+
+/// Keep a snapshot of `simulation` if it has just reached the next tick that
+/// needs one.
+fn take_snapshot(
+  snapshots: &mut Vec<simulation::Snapshot>,
+  simulation: &simulation::Simulation,
+) {
+  if simulation.tick() == snapshots.len() as u64 * SNAPSHOT_INTERVAL {
+    snapshots.push(simulation.snapshot());
+  }
+}
+
+impl Timeline {
+  /// Create a Timeline for a ride of `track`, playing from its start.
+  pub fn new(track: &track::Track) -> Timeline {
+    let simulation = simulation::Simulation::new(track);
+    let snapshots = vec![simulation.snapshot()];
+    Timeline {
+      simulation: simulation,
+      snapshots: snapshots,
+      furthest_tick: 0,
+      paused: false,
+    }
+  }
+
+  pub fn simulation(&self) -> &simulation::Simulation {
+    &self.simulation
+  }
+
+  /// The furthest tick the ride has been simulated to.
+  pub fn furthest_tick(&self) -> u64 {
+    self.furthest_tick
+  }
+
+  pub fn paused(&self) -> bool {
+    self.paused
+  }
+
+  pub fn set_paused(&mut self, paused: bool) {
+    if paused {
+      self.simulation.hold();
+    }
+    self.paused = paused;
+  }
+
+  /// Advance the ride by `dt` seconds of wall-clock time, unless paused.
+  pub fn update(&mut self, dt: f64) {
+    if self.paused {
+      return;
+    }
+
+    let snapshots = &mut self.snapshots;
+    self
+      .simulation
+      .update_with(dt, |simulation| take_snapshot(snapshots, simulation));
+    self.furthest_tick = self.furthest_tick.max(self.simulation.tick());
+  }
+
+  /// Pause, and move the ride to `tick`.
+  pub fn seek(&mut self, tick: u64) {
+    self.paused = true;
+
+    // Simulate forward from where the ride is if that is no further than from
+    // the nearest snapshot.
+    let index = ((tick / SNAPSHOT_INTERVAL) as usize)
+      .min(self.snapshots.len() - 1);
+    let current_tick = self.simulation.tick();
+    if current_tick > tick || self.snapshots[index].tick() > current_tick {
+      self.simulation.restore(&self.snapshots[index]);
+    }
+
+    while self.simulation.tick() < tick {
+      self.simulation.step();
+      take_snapshot(&mut self.snapshots, &self.simulation);
+    }
+    self.simulation.hold();
+    self.furthest_tick = self.furthest_tick.max(tick);
+  }
+
+  /// Pause, and move the ride one tick forward.
+  pub fn step_forward(&mut self) {
+    let tick = self.simulation.tick() + 1;
+    self.seek(tick);
+  }
+
+  /// Pause, and move the ride one tick back.
+  pub fn step_back(&mut self) {
+    let tick = self.simulation.tick().saturating_sub(1);
+    self.seek(tick);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use track::Point;
+
+  /// A slope the rider lands on and slides down.
+  fn slope() -> track::Track {
+    track::Track::with_line(Point::new(0.0, 150.0), Point::new(1000.0, 400.0))
+  }
+
+  /// The checksum of a new Timeline of `track` after seeking to `tick`.
+  fn fresh_checksum(track: &track::Track, tick: u64) -> u64 {
+    let mut timeline = Timeline::new(track);
+    timeline.seek(tick);
+    timeline.simulation().checksum()
+  }
+
+  #[test]
+  fn seeking_back_matches_a_fresh_run() {
+    let track = slope();
+    let mut timeline = Timeline::new(&track);
+    timeline.seek(300);
+    for &tick in [250, 130, 121, 120, 119, 59, 0].iter() {
+      timeline.seek(tick);
+      assert_eq!(timeline.simulation().tick(), tick);
+      assert_eq!(
+        timeline.simulation().checksum(),
+        fresh_checksum(&track, tick)
+      );
+    }
+  }
+
+  #[test]
+  fn stepping_back_then_forward_returns_to_the_same_tick() {
+    let mut timeline = Timeline::new(&slope());
+    timeline.seek(181);
+    let checksum = timeline.simulation().checksum();
+    timeline.step_back();
+    timeline.step_back();
+    timeline.step_forward();
+    timeline.step_forward();
+
+    assert_eq!(timeline.simulation().checksum(), checksum);
+    assert_eq!(timeline.furthest_tick(), 181);
+  }
+}
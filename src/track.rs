@@ -0,0 +1,421 @@
+extern crate nalgebra;
+extern crate std;
+
+use std::collections::BTreeMap;
+
+use geometry;
+
+pub type Point = nalgebra::Point2<f64>;
+pub type Vector = nalgebra::Vector2<f64>;
+
+/// Serialize points as `[x, y]` pairs, so that saved tracks do not depend on
+/// how nalgebra chooses to represent its types.
+mod point_serde {
+  extern crate serde;
+
+  use super::Point;
+
+  pub fn serialize<S>(point: &Point, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: serde::Serializer,
+  {
+    use self::serde::Serialize; // serialize
+
+    [point.x, point.y].serialize(serializer)
+  }
+
+  pub fn deserialize<'de, D>(deserializer: D) -> Result<Point, D::Error>
+  where
+    D: serde::Deserializer<'de>,
+  {
+    use self::serde::Deserialize; // deserialize
+
+    let coordinates = <[f64; 2]>::deserialize(deserializer)?;
+    Ok(Point::new(coordinates[0], coordinates[1]))
+  }
+}
+
+/// Where the rider is placed on a new track.
+pub const DEFAULT_RIDER_START: [f64; 2] = [100.0, 100.0];
+
+/// The furthest a point of a track may be from the origin along either axis.
+/// Further out, positions are too coarse to ride on.
+pub const MAX_COORDINATE: f64 = 1.0e9;
+
+/// The most line segments a curve may be tessellated into.
+pub const MAX_CURVE_SEGMENT_COUNT: usize = 256;
+
+/// Descriptive information about a track that does not affect how it rides.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Metadata {
+  #[serde(default)]
+  pub name: String,
+  #[serde(default)]
+  pub author: String,
+  #[serde(default)]
+  pub description: String,
+}
+
+/// How a LineSegment interacts with the rider.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineKind {
+  /// A solid line that the rider collides with.
+  Normal,
+  /// A solid line that also speeds the rider up in the direction from its
+  /// first point to its second point.
+  Acceleration,
+  /// A decorative line that the rider passes through.
+  Scenery,
+}
+
+impl Default for LineKind {
+  fn default() -> LineKind {
+    LineKind::Normal
+  }
+}
+
+/// One of the two ends of a LineSegment.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Endpoint {
+  Point1,
+  Point2,
+}
+
+/// A straight line between two points, which the rider may ride on.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LineSegment {
+  #[serde(with = "point_serde")]
+  point1: Point,
+  #[serde(with = "point_serde")]
+  point2: Point,
+  kind: LineKind,
+  /// The ID of the curve in Track::curves this segment was tessellated from,
+  /// if any.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  curve: Option<u64>,
+}
+
+impl LineSegment {
+  pub fn new(point1: Point, point2: Point, kind: LineKind) -> LineSegment {
+    LineSegment {
+      point1: point1,
+      point2: point2,
+      kind: kind,
+      curve: None,
+    }
+  }
+
+  /// This segment, as part of the curve with the ID `curve`.
+  pub fn in_curve(mut self, curve: u64) -> LineSegment {
+    self.curve = Some(curve);
+    self
+  }
+
+  pub fn point1(&self) -> &Point {
+    &self.point1
+  }
+
+  pub fn point2(&self) -> &Point {
+    &self.point2
+  }
+
+  pub fn kind(&self) -> LineKind {
+    self.kind
+  }
+
+  /// The ID of the curve this segment is part of, if any.
+  pub fn curve(&self) -> Option<u64> {
+    self.curve
+  }
+
+  pub fn endpoint(&self, endpoint: Endpoint) -> &Point {
+    match endpoint {
+      Endpoint::Point1 => &self.point1,
+      Endpoint::Point2 => &self.point2,
+    }
+  }
+
+  /// A copy of this segment with one endpoint moved to `point`. The copy is
+  /// not part of any curve, since it no longer follows one.
+  pub fn with_endpoint(&self, endpoint: Endpoint, point: Point) -> LineSegment {
+    match endpoint {
+      Endpoint::Point1 => LineSegment::new(point, self.point2, self.kind),
+      Endpoint::Point2 => LineSegment::new(self.point1, point, self.kind),
+    }
+  }
+
+  /// A copy of this segment moved by `offset`, which is not part of any
+  /// curve.
+  pub fn translated(&self, offset: &Vector) -> LineSegment {
+    LineSegment::new(self.point1 + *offset, self.point2 + *offset, self.kind)
+  }
+
+  pub fn distance_to(&self, point: &Point) -> f64 {
+    geometry::distance_to_segment(point, &self.point1, &self.point2)
+  }
+}
+
+/// The shape of a curve, given by the handles it is edited with.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CurveShape {
+  /// A quadratic Bézier curve.
+  Quadratic {
+    #[serde(with = "point_serde")]
+    start: Point,
+    #[serde(with = "point_serde")]
+    control: Point,
+    #[serde(with = "point_serde")]
+    end: Point,
+  },
+  /// A cubic Bézier curve.
+  Cubic {
+    #[serde(with = "point_serde")]
+    start: Point,
+    #[serde(with = "point_serde")]
+    control1: Point,
+    #[serde(with = "point_serde")]
+    control2: Point,
+    #[serde(with = "point_serde")]
+    end: Point,
+  },
+  /// The arc of a circle from `start` to `end` that passes through `through`.
+  /// If the three are on one line, the arc is a straight line.
+  Arc {
+    #[serde(with = "point_serde")]
+    start: Point,
+    #[serde(with = "point_serde")]
+    through: Point,
+    #[serde(with = "point_serde")]
+    end: Point,
+  },
+}
+
+impl CurveShape {
+  /// The points the curve is edited by, in order from start to end.
+  pub fn handles(&self) -> Vec<Point> {
+    match *self {
+      CurveShape::Quadratic {
+        start,
+        control,
+        end,
+      } => vec![start, control, end],
+      CurveShape::Cubic {
+        start,
+        control1,
+        control2,
+        end,
+      } => vec![start, control1, control2, end],
+      CurveShape::Arc {
+        start,
+        through,
+        end,
+      } => vec![start, through, end],
+    }
+  }
+
+  /// A copy of this shape with the handle at `index` moved to `point`.
+  pub fn with_handle(&self, index: usize, point: Point) -> CurveShape {
+    let mut handles = self.handles();
+    handles[index] = point;
+    match *self {
+      CurveShape::Quadratic { .. } => CurveShape::Quadratic {
+        start: handles[0],
+        control: handles[1],
+        end: handles[2],
+      },
+      CurveShape::Cubic { .. } => CurveShape::Cubic {
+        start: handles[0],
+        control1: handles[1],
+        control2: handles[2],
+        end: handles[3],
+      },
+      CurveShape::Arc { .. } => CurveShape::Arc {
+        start: handles[0],
+        through: handles[1],
+        end: handles[2],
+      },
+    }
+  }
+
+  /// The point a fraction `t` of the way along the curve.
+  fn point_at(&self, t: f64) -> Point {
+    let along = |from: &Point, to: &Point, t: f64| from + (to - from) * t;
+    match *self {
+      CurveShape::Quadratic {
+        ref start,
+        ref control,
+        ref end,
+      } => along(&along(start, control, t), &along(control, end, t), t),
+      CurveShape::Cubic {
+        ref start,
+        ref control1,
+        ref control2,
+        ref end,
+      } => {
+        let middle = along(control1, control2, t);
+        along(
+          &along(&along(start, control1, t), &middle, t),
+          &along(&middle, &along(control2, end, t), t),
+          t,
+        )
+      },
+      CurveShape::Arc {
+        ref start,
+        ref through,
+        ref end,
+      } => match geometry::circle_through(start, through, end) {
+        Some((center, radius)) => {
+          let angle =
+            |point: &Point| (point.y - center.y).atan2(point.x - center.x);
+          let tau = 2.0 * std::f64::consts::PI;
+          let start_angle = angle(start);
+          let to_through = (angle(through) - start_angle + tau) % tau;
+          let to_end = (angle(end) - start_angle + tau) % tau;
+          // Go around whichever way passes through `through`.
+          let sweep = if to_through < to_end {
+            to_end
+          } else {
+            to_end - tau
+          };
+          let theta = start_angle + sweep * t;
+          Point::new(
+            center.x + radius * theta.cos(),
+            center.y + radius * theta.sin(),
+          )
+        },
+        None => along(start, end, t),
+      },
+    }
+  }
+
+  /// The `segment_count + 1` points that divide the curve into
+  /// `segment_count` parts, evenly by parameter.
+  pub fn tessellate(&self, segment_count: usize) -> Vec<Point> {
+    let segment_count = segment_count.max(1);
+    (0..segment_count + 1)
+      .map(|index| self.point_at(index as f64 / segment_count as f64))
+      .collect()
+  }
+}
+
+/// A curve drawn in the editor. The track rides on the segments the curve was
+/// tessellated into; the curve is kept so that it can be reshaped later.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Curve {
+  pub shape: CurveShape,
+  /// How many line segments the curve is tessellated into.
+  pub segment_count: usize,
+  pub kind: LineKind,
+}
+
+impl Curve {
+  /// The line segments this curve is tessellated into, as part of the curve
+  /// with the ID `id`.
+  pub fn line_segments(&self, id: u64) -> Vec<LineSegment> {
+    self
+      .shape
+      .tessellate(self.segment_count)
+      .windows(2)
+      .filter(|pair| pair[0] != pair[1])
+      .map(|pair| LineSegment::new(pair[0], pair[1], self.kind).in_curve(id))
+      .collect()
+  }
+}
+
+/// A course for the rider: the line segments it rides on and where it starts.
+/// A Track is the save data of a game.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Track {
+  #[serde(default)]
+  pub metadata: Metadata,
+  pub line_segments: Vec<LineSegment>,
+  #[serde(with = "point_serde")]
+  pub rider_start: Point,
+  /// The curves that line segments were tessellated from, by ID. A curve is
+  /// never changed once added: reshaping one adds a new curve, so that undoing
+  /// the change can restore the segments of the old one.
+  #[serde(default)]
+  pub curves: BTreeMap<u64, Curve>,
+}
+
+impl Default for Track {
+  fn default() -> Track {
+    Track::new()
+  }
+}
+
+impl Track {
+  /// Create an empty Track with the rider at its default start point.
+  pub fn new() -> Track {
+    Track {
+      metadata: Metadata::default(),
+      line_segments: Vec::new(),
+      rider_start: Point::new(DEFAULT_RIDER_START[0], DEFAULT_RIDER_START[1]),
+      curves: BTreeMap::new(),
+    }
+  }
+
+  /// A Track with one normal line from `point1` to `point2`, for tests to
+  /// ride on.
+  #[cfg(test)]
+  pub fn with_line(point1: Point, point2: Point) -> Track {
+    let mut track = Track::new();
+    track
+      .line_segments
+      .push(LineSegment::new(point1, point2, LineKind::Normal));
+    track
+  }
+
+  /// An ID that no curve has.
+  pub fn next_curve_id(&self) -> u64 {
+    self.curves.keys().next_back().map_or(0, |&id| id + 1)
+  }
+
+  /// A point of the track that is not finite, or is further than
+  /// MAX_COORDINATE from the origin along either axis, if there is one.
+  pub fn out_of_range_point(&self) -> Option<Point> {
+    let in_range = |point: &Point| {
+      point.x.abs() <= MAX_COORDINATE && point.y.abs() <= MAX_COORDINATE
+    };
+
+    if !in_range(&self.rider_start) {
+      return Some(self.rider_start);
+    }
+    for line_segment in self.line_segments.iter() {
+      for point in [line_segment.point1, line_segment.point2].iter() {
+        if !in_range(point) {
+          return Some(*point);
+        }
+      }
+    }
+    for curve in self.curves.values() {
+      for point in curve.shape.handles() {
+        if !in_range(&point) {
+          return Some(point);
+        }
+      }
+    }
+
+    None
+  }
+
+  /// Forget every curve that no line segment is part of.
+  pub fn remove_unused_curves(&mut self) {
+    let used: std::collections::BTreeSet<u64> = self
+      .line_segments
+      .iter()
+      .filter_map(|line_segment| line_segment.curve())
+      .collect();
+    let unused: Vec<u64> = self
+      .curves
+      .keys()
+      .filter(|id| !used.contains(id))
+      .cloned()
+      .collect();
+    for id in unused.iter() {
+      self.curves.remove(id);
+    }
+  }
+}
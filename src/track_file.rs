@@ -0,0 +1,403 @@
+extern crate serde_json;
+extern crate std;
+
+use error;
+use lra_json;
+use svg_file;
+use track;
+use trk_file;
+
+/// The version written by `save`. Bump this whenever the layout of a saved
+/// track changes, and teach `migrate` how to upgrade the previous version.
+pub const CURRENT_VERSION: u64 = 3;
+
+/// The file name suffix used for saved tracks.
+pub const EXTENSION: &'static str = ".track.json";
+
+/// A format a track file may be in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+  /// Rust Rider's own format, read and written by this module.
+  RustRider,
+  /// The JSON format of Line Rider Advanced, handled by `lra_json`.
+  LineRiderJson,
+  /// The binary .trk format of Line Rider Advanced, handled by `trk_file`.
+  Trk,
+  /// An SVG document, handled by `svg_file`.
+  Svg,
+}
+
+impl Format {
+  /// Guess the format of the track file at `path` from its name. Names ending
+  /// in EXTENSION, and names that match no other format, are Rust Rider's.
+  pub fn from_path(path: &std::path::Path) -> Format {
+    let name = match path.file_name().and_then(|name| name.to_str()) {
+      Some(name) => name.to_lowercase(),
+      None => return Format::RustRider,
+    };
+
+    if name.ends_with(EXTENSION) {
+      Format::RustRider
+    } else if name.ends_with(".trk") {
+      Format::Trk
+    } else if name.ends_with(".json") {
+      Format::LineRiderJson
+    } else if name.ends_with(".svg") {
+      Format::Svg
+    } else {
+      Format::RustRider
+    }
+  }
+}
+
+/// The on-disk layout of a track, as of CURRENT_VERSION.
+#[derive(Deserialize)]
+struct TrackFile {
+  track: track::Track,
+}
+
+/// The on-disk layout of a track, borrowing the Track being saved.
+#[derive(Serialize)]
+struct TrackFileRef<'track> {
+  version: u64,
+  track: &'track track::Track,
+}
+
+/// Check that every point of a track read from a file can be ridden and
+/// indexed. Every format's reader checks the tracks it reads.
+pub fn check_points(track: &track::Track) -> error::Result<()> {
+  match track.out_of_range_point() {
+    Some(point) => Err(error::Error::from(format!(
+      "Track has a point out of range at ({}, {})",
+      point.x,
+      point.y
+    ))),
+    None => Ok(()),
+  }
+}
+
+/// Check that every curve of a track read from a file is tessellated into
+/// between 1 and MAX_CURVE_SEGMENT_COUNT line segments, as the editor would
+/// have.
+pub fn check_curves(track: &track::Track) -> error::Result<()> {
+  for (id, curve) in track.curves.iter() {
+    if curve.segment_count == 0 ||
+      curve.segment_count > track::MAX_CURVE_SEGMENT_COUNT
+    {
+      return Err(error::Error::from(format!(
+        "Curve {} has {} line segments, not between 1 and {}",
+        id,
+        curve.segment_count,
+        track::MAX_CURVE_SEGMENT_COUNT
+      )));
+    }
+  }
+
+  Ok(())
+}
+
+/// Version 2 gave every line segment a kind. Lines from version 1 tracks were
+/// all solid, so they become normal lines.
+fn migrate_from_version_1(
+  mut track_file: serde_json::Value,
+) -> error::Result<serde_json::Value> {
+  match track_file
+    .pointer_mut("/track/line_segments")
+    .and_then(|line_segments| line_segments.as_array_mut())
+  {
+    Some(line_segments) => {
+      for line_segment in line_segments.iter_mut() {
+        match line_segment.as_object_mut() {
+          Some(line_segment) => {
+            line_segment.insert(
+              String::from("kind"),
+              serde_json::Value::from("normal"),
+            );
+          },
+          None => {
+            return Err(error::Error::from("Line segment is not an object"));
+          },
+        }
+      }
+    },
+    None => return Err(error::Error::from("Track has no line segments")),
+  }
+
+  Ok(track_file)
+}
+
+/// Version 3 added the curves that line segments may be tessellated from.
+/// Tracks from version 2 have none.
+fn migrate_from_version_2(
+  mut track_file: serde_json::Value,
+) -> error::Result<serde_json::Value> {
+  match track_file
+    .get_mut("track")
+    .and_then(|track| track.as_object_mut())
+  {
+    Some(track) => {
+      track.insert(
+        String::from("curves"),
+        serde_json::Value::Object(serde_json::Map::new()),
+      );
+    },
+    None => return Err(error::Error::from("Track is not an object")),
+  }
+
+  Ok(track_file)
+}
+
+/// Upgrade a track of the given version to the layout of the next version.
+fn migrate(
+  version: u64,
+  track_file: serde_json::Value,
+) -> error::Result<serde_json::Value> {
+  match version {
+    1 => migrate_from_version_1(track_file),
+    2 => migrate_from_version_2(track_file),
+    _ => Err(error::Error::from(
+      format!("No migration exists from track version {}", version),
+    )),
+  }
+}
+
+/// Write a Track to a writer as a track of CURRENT_VERSION.
+pub fn to_writer<Writer>(
+  writer: Writer,
+  track: &track::Track,
+) -> error::Result<()>
+where
+  Writer: std::io::Write,
+{
+  let track_file = TrackFileRef {
+    version: CURRENT_VERSION,
+    track: track,
+  };
+  serde_json::to_writer_pretty(writer, &track_file)?;
+  Ok(())
+}
+
+/// Read a track of any known version from a reader, migrating it forward to
+/// CURRENT_VERSION as necessary.
+pub fn from_reader<Reader>(reader: Reader) -> error::Result<track::Track>
+where
+  Reader: std::io::Read,
+{
+  let mut track_file: serde_json::Value = serde_json::from_reader(reader)?;
+
+  let mut version = match track_file.get("version") {
+    Some(version) => match version.as_u64() {
+      Some(version) => version,
+      None => {
+        return Err(error::Error::from("Track version is not an integer"));
+      },
+    },
+    None => return Err(error::Error::from("Track has no version")),
+  };
+  if version > CURRENT_VERSION {
+    return Err(error::Error::from(format!(
+      "Track version {} is newer than the supported version {}",
+      version,
+      CURRENT_VERSION,
+    )));
+  }
+
+  while version < CURRENT_VERSION {
+    track_file = migrate(version, track_file)?;
+    version += 1;
+    track_file["version"] = serde_json::Value::from(version);
+  }
+
+  let track_file: TrackFile = serde_json::from_value(track_file)?;
+  // Curves replaced while editing are kept for undoing, and may have been
+  // saved along with the track.
+  let mut track = track_file.track;
+  track.remove_unused_curves();
+  check_points(&track)?;
+  check_curves(&track)?;
+  Ok(track)
+}
+
+/// Save a Track to the track file at `path`, in the format its name implies.
+pub fn save(
+  path: &std::path::Path,
+  track: &track::Track,
+) -> error::Result<()> {
+  match Format::from_path(path) {
+    Format::RustRider => {
+      let file = std::fs::File::create(path)?;
+      to_writer(std::io::BufWriter::new(file), track)
+    },
+    Format::LineRiderJson => lra_json::save(path, track),
+    Format::Trk => trk_file::save(path, track),
+    Format::Svg => svg_file::save(path, track),
+  }
+}
+
+/// Load a Track from the track file at `path`, in the format its name
+/// implies.
+pub fn load(path: &std::path::Path) -> error::Result<track::Track> {
+  match Format::from_path(path) {
+    Format::RustRider => {
+      let file = std::fs::File::open(path)?;
+      from_reader(std::io::BufReader::new(file))
+    },
+    Format::LineRiderJson => lra_json::load(path),
+    Format::Trk => trk_file::load(path),
+    Format::Svg => svg_file::load(path),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use track::{LineKind, LineSegment, Point};
+
+  fn read(json: &str) -> error::Result<track::Track> {
+    from_reader(json.as_bytes())
+  }
+
+  #[test]
+  fn round_trip_keeps_the_track() {
+    let mut track = track::Track::new();
+    track.metadata.name = String::from("Hill");
+    track.rider_start = Point::new(10.0, -20.0);
+    track.line_segments.push(LineSegment::new(
+      Point::new(0.0, 0.0),
+      Point::new(100.0, 50.0),
+      LineKind::Acceleration,
+    ));
+
+    let mut bytes = Vec::new();
+    to_writer(&mut bytes, &track).unwrap();
+    let read = from_reader(bytes.as_slice()).unwrap();
+
+    assert_eq!(read.metadata.name, track.metadata.name);
+    assert_eq!(read.rider_start, track.rider_start);
+    assert_eq!(read.line_segments, track.line_segments);
+  }
+
+  #[test]
+  fn unknown_versions_are_rejected() {
+    let newer = format!(
+      r#"{{"version": {}, "track": {{}}}}"#,
+      CURRENT_VERSION + 1
+    );
+    assert!(read(&newer).is_err());
+    assert!(read(r#"{"version": 0, "track": {}}"#).is_err());
+    assert!(read(r#"{"track": {}}"#).is_err());
+    assert!(read(r#"{"version": "1", "track": {}}"#).is_err());
+  }
+
+  #[test]
+  fn version_1_lines_become_normal_lines() {
+    let track = read(
+      r#"{
+        "version": 1,
+        "track": {
+          "line_segments": [{"point1": [0, 0], "point2": [100, 50]}],
+          "rider_start": [10, -20]
+        }
+      }"#,
+    ).unwrap();
+
+    assert_eq!(track.rider_start, Point::new(10.0, -20.0));
+    assert_eq!(
+      track.line_segments,
+      vec![
+        LineSegment::new(
+          Point::new(0.0, 0.0),
+          Point::new(100.0, 50.0),
+          LineKind::Normal,
+        ),
+      ]
+    );
+  }
+
+  #[test]
+  fn version_1_tracks_without_line_segments_are_rejected() {
+    let json = r#"{"version": 1, "track": {"rider_start": [0, 0]}}"#;
+    assert!(read(json).is_err());
+  }
+
+  #[test]
+  fn version_2_tracks_have_no_curves() {
+    let track = read(
+      r#"{
+        "version": 2,
+        "track": {
+          "line_segments": [
+            {"point1": [0, 0], "point2": [100, 50], "kind": "scenery"}
+          ],
+          "rider_start": [10, -20]
+        }
+      }"#,
+    ).unwrap();
+
+    assert!(track.curves.is_empty());
+    assert_eq!(track.line_segments[0].kind(), LineKind::Scenery);
+    assert_eq!(track.line_segments[0].curve(), None);
+  }
+
+  #[test]
+  fn only_curves_in_use_are_loaded() {
+    let curve = |control: Point| track::Curve {
+      shape: track::CurveShape::Quadratic {
+        start: Point::new(0.0, 0.0),
+        control: control,
+        end: Point::new(100.0, 0.0),
+      },
+      segment_count: 4,
+      kind: LineKind::Normal,
+    };
+    let mut track = track::Track::new();
+    // Curve 0 was reshaped into curve 1, which the track now rides on.
+    track.curves.insert(0, curve(Point::new(50.0, 20.0)));
+    track.curves.insert(1, curve(Point::new(50.0, 40.0)));
+    track.line_segments = track.curves[&1].line_segments(1);
+
+    let mut bytes = Vec::new();
+    to_writer(&mut bytes, &track).unwrap();
+    let read = from_reader(bytes.as_slice()).unwrap();
+
+    assert_eq!(read.curves.keys().collect::<Vec<_>>(), vec![&1]);
+    assert_eq!(read.curves[&1], track.curves[&1]);
+    assert_eq!(read.line_segments, track.line_segments);
+  }
+
+  #[test]
+  fn curves_with_too_many_or_no_segments_are_rejected() {
+    let with_segment_count = |segment_count: &str| {
+      format!(
+        r#"{{
+          "version": 3,
+          "track": {{
+            "line_segments": [
+              {{"point1": [0, 0], "point2": [100, 0], "kind": "normal",
+                "curve": 0}}
+            ],
+            "rider_start": [10, -20],
+            "curves": {{
+              "0": {{
+                "shape": {{
+                  "type": "quadratic",
+                  "start": [0, 0],
+                  "control": [50, 20],
+                  "end": [100, 0]
+                }},
+                "segment_count": {},
+                "kind": "normal"
+              }}
+            }}
+          }}
+        }}"#,
+        segment_count
+      )
+    };
+
+    assert!(read(&with_segment_count("1")).is_ok());
+    assert!(read(&with_segment_count("0")).is_err());
+    assert!(read(&with_segment_count("257")).is_err());
+    assert!(read(&with_segment_count("18446744073709551615")).is_err());
+  }
+}
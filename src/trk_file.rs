@@ -0,0 +1,438 @@
+//! Tracks in the binary .trk format saved by Line Rider Advanced.
+//!
+//! A .trk file starts with a header naming the optional features it uses,
+//! followed by the rider's start position, every line, and finally an optional
+//! block of `key=value` metadata. All numbers are little-endian. Features that
+//! only change how Line Rider draws a track are ignored, and anything else
+//! Rust Rider cannot represent is reported as ErrorKind::UnsupportedFeatures.
+
+extern crate byteorder;
+extern crate std;
+
+use std::collections::BTreeSet;
+use std::io::{Read, Write};
+
+use self::byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use error;
+use track;
+use track::{LineKind, LineSegment, Point};
+use track_file;
+
+/// The name of the format, as used in error messages.
+const FORMAT: &'static str = ".trk";
+
+/// The bytes every .trk file starts with.
+const MAGIC: [u8; 4] = [b'T', b'R', b'K', 0xf2];
+
+/// The only version of the format.
+const VERSION: u8 = 1;
+
+/// The bytes that start the optional metadata block.
+const META: [u8; 4] = [b'M', b'E', b'T', b'A'];
+
+/// Line types, as numbered in the format.
+const SCENERY_LINE: u8 = 0;
+const NORMAL_LINE: u8 = 1;
+const ACCELERATION_LINE: u8 = 2;
+
+/// The bits of a line's flags holding its type.
+const LINE_TYPE_MASK: u8 = 0x1f;
+/// The bit of a line's flags set when it is flipped.
+const FLIPPED_FLAG: u8 = 0x80;
+/// The bits of a line's flags set when it is extended at either end.
+const EXTENSION_FLAGS: u8 = 0x60;
+
+/// Features that change how lines are stored.
+const REDMULTIPLIER: &'static str = "REDMULTIPLIER";
+const SCENERYWIDTH: &'static str = "SCENERYWIDTH";
+const SONGINFO: &'static str = "SONGINFO";
+const IGNORABLE_TRIGGER: &'static str = "IGNORABLE_TRIGGER";
+/// The rider starts at rest. Rust Rider's rider always does, so tracks without
+/// this feature are unsupported.
+const ZEROSTART: &'static str = "ZEROSTART";
+/// Features that only mark the version of Line Rider Advanced that saved the
+/// track.
+const VERSION_FEATURES: [&'static str; 2] = ["6.1", "6.2"];
+
+/// Metadata keys, and the values that leave the physics unchanged.
+const GRAVITY_KEYS: [(&'static str, f64); 3] = [
+  ("XGRAVITY", 0.0),
+  ("YGRAVITY", 1.0),
+  ("GRAVITYWELLSIZE", 10.0),
+];
+const TRIGGERS_KEY: &'static str = "TRIGGERS";
+/// Metadata keys that only change how Line Rider draws a track.
+const IGNORED_KEYS: [&'static str; 7] = [
+  "STARTZOOM",
+  "BGCOLORR",
+  "BGCOLORG",
+  "BGCOLORB",
+  "LINECOLORR",
+  "LINECOLORG",
+  "LINECOLORB",
+];
+
+/// Read a string with an i16 length.
+fn read_short_string<Reader>(reader: &mut Reader) -> error::Result<String>
+where
+  Reader: Read,
+{
+  let length = reader.read_i16::<LittleEndian>()?;
+  if length < 0 {
+    return Err(error::Error::from("String has a negative length"));
+  }
+  let mut bytes = vec![0; length as usize];
+  reader.read_exact(&mut bytes)?;
+  String::from_utf8(bytes)
+    .map_err(|_| error::Error::from("String is not valid UTF-8"))
+}
+
+/// Write a string with an i16 length.
+fn write_short_string<Writer>(
+  writer: &mut Writer,
+  string: &str,
+) -> error::Result<()>
+where
+  Writer: Write,
+{
+  if string.len() > std::i16::MAX as usize {
+    return Err(error::Error::from("String is too long"));
+  }
+  writer.write_i16::<LittleEndian>(string.len() as i16)?;
+  writer.write_all(string.as_bytes())?;
+  Ok(())
+}
+
+/// Read a string whose length is written seven bits at a time, lowest bits
+/// first, as .NET does.
+fn read_dotnet_string<Reader>(reader: &mut Reader) -> error::Result<String>
+where
+  Reader: Read,
+{
+  let mut length = 0usize;
+  let mut shift = 0;
+  loop {
+    let byte = reader.read_u8()?;
+    length |= ((byte & 0x7f) as usize) << shift;
+    if byte & 0x80 == 0 {
+      break;
+    }
+    shift += 7;
+    if shift > 28 {
+      return Err(error::Error::from("String length is too long"));
+    }
+  }
+  let mut bytes = vec![0; length];
+  reader.read_exact(&mut bytes)?;
+  String::from_utf8(bytes)
+    .map_err(|_| error::Error::from("String is not valid UTF-8"))
+}
+
+/// Read the metadata block at the end of a track, noting any metadata that
+/// changes how the track rides.
+fn read_metadata<Reader>(
+  reader: &mut Reader,
+  unsupported: &mut BTreeSet<String>,
+) -> error::Result<()>
+where
+  Reader: Read,
+{
+  let mut meta = [0; 4];
+  reader.read_exact(&mut meta)?;
+  if meta != META {
+    return Err(error::Error::from("Unexpected data after the lines"));
+  }
+
+  let count = reader.read_i16::<LittleEndian>()?;
+  for _ in 0..count {
+    let entry = read_short_string(reader)?;
+    let mut parts = entry.splitn(2, '=');
+    let key = parts.next().unwrap_or("");
+    let value = parts.next().unwrap_or("");
+
+    if key == TRIGGERS_KEY {
+      if !value.is_empty() {
+        unsupported.insert(String::from("triggers"));
+      }
+    } else if let Some(&(key, default)) =
+      GRAVITY_KEYS.iter().find(|&&(name, _)| name == key)
+    {
+      if value.parse::<f64>().ok() != Some(default) {
+        unsupported.insert(format!("{} {}", key, value));
+      }
+    } else if !IGNORED_KEYS.contains(&key) {
+      unsupported.insert(format!("metadata {}", key));
+    }
+  }
+
+  Ok(())
+}
+
+/// Write a Track to a writer in the format.
+pub fn to_writer<Writer>(
+  mut writer: Writer,
+  track: &track::Track,
+) -> error::Result<()>
+where
+  Writer: Write,
+{
+  writer.write_all(&MAGIC)?;
+  writer.write_u8(VERSION)?;
+  write_short_string(&mut writer, &format!("{};", ZEROSTART))?;
+  writer.write_f64::<LittleEndian>(track.rider_start.x)?;
+  writer.write_f64::<LittleEndian>(track.rider_start.y)?;
+
+  writer.write_u32::<LittleEndian>(track.line_segments.len() as u32)?;
+  for (index, line_segment) in track.line_segments.iter().enumerate() {
+    let line_type = match line_segment.kind() {
+      LineKind::Normal => NORMAL_LINE,
+      LineKind::Acceleration => ACCELERATION_LINE,
+      LineKind::Scenery => SCENERY_LINE,
+    };
+    writer.write_u8(line_type)?;
+    if line_type != SCENERY_LINE {
+      writer.write_i32::<LittleEndian>(index as i32 + 1)?;
+    }
+    writer.write_f64::<LittleEndian>(line_segment.point1().x)?;
+    writer.write_f64::<LittleEndian>(line_segment.point1().y)?;
+    writer.write_f64::<LittleEndian>(line_segment.point2().x)?;
+    writer.write_f64::<LittleEndian>(line_segment.point2().y)?;
+  }
+
+  writer.flush()?;
+  Ok(())
+}
+
+/// Read a track in the format from a reader.
+pub fn from_reader<Reader>(mut reader: Reader) -> error::Result<track::Track>
+where
+  Reader: Read,
+{
+  // Metadata is only present when there is data after the lines, so read the
+  // whole track up front to know where it ends.
+  let mut bytes = Vec::new();
+  reader.read_to_end(&mut bytes)?;
+  let length = bytes.len() as u64;
+  let mut reader = std::io::Cursor::new(bytes);
+
+  let mut magic = [0; 4];
+  reader.read_exact(&mut magic)?;
+  if magic != MAGIC {
+    return Err(error::Error::from("Not a .trk file"));
+  }
+  let version = reader.read_u8()?;
+  if version != VERSION {
+    return Err(error::Error::from(
+      format!(".trk version {} is not supported", version),
+    ));
+  }
+
+  let mut unsupported = BTreeSet::new();
+  let features = read_short_string(&mut reader)?;
+  let features: Vec<&str> = features
+    .split(';')
+    .filter(|feature| !feature.is_empty())
+    .collect();
+  for feature in features.iter() {
+    match *feature {
+      REDMULTIPLIER | SCENERYWIDTH | SONGINFO | IGNORABLE_TRIGGER |
+      ZEROSTART => {},
+      feature if VERSION_FEATURES.contains(&feature) => {},
+      feature => {
+        unsupported.insert(feature.to_lowercase());
+      },
+    }
+  }
+  let has_feature = |name| features.contains(&name);
+  // Without ZEROSTART, Line Rider starts the rider moving forward.
+  if !has_feature(ZEROSTART) {
+    unsupported.insert(String::from("a moving start"));
+  }
+
+  if has_feature(SONGINFO) {
+    read_dotnet_string(&mut reader)?;
+  }
+  let rider_start = Point::new(
+    reader.read_f64::<LittleEndian>()?,
+    reader.read_f64::<LittleEndian>()?,
+  );
+
+  let count = reader.read_u32::<LittleEndian>()?;
+  let mut line_segments = Vec::new();
+  for _ in 0..count {
+    let flags = reader.read_u8()?;
+    let line_type = flags & LINE_TYPE_MASK;
+    let kind = match line_type {
+      NORMAL_LINE => LineKind::Normal,
+      ACCELERATION_LINE => LineKind::Acceleration,
+      SCENERY_LINE => LineKind::Scenery,
+      line_type => {
+        // The layout of an unknown line is unknown too, so nothing after it
+        // can be read.
+        unsupported.insert(format!("line type {}", line_type));
+        break;
+      },
+    };
+
+    if kind == LineKind::Acceleration && has_feature(REDMULTIPLIER) &&
+      reader.read_u8()? != 1
+    {
+      unsupported.insert(String::from("acceleration multipliers"));
+    }
+    if kind != LineKind::Scenery {
+      if has_feature(IGNORABLE_TRIGGER) && reader.read_u8()? != 0 {
+        unsupported.insert(String::from("triggers"));
+        // The zoom and the number of frames to zoom over.
+        reader.read_f32::<LittleEndian>()?;
+        reader.read_i16::<LittleEndian>()?;
+      }
+      // The line's ID, and those of the lines it extends to.
+      reader.read_i32::<LittleEndian>()?;
+      if flags & EXTENSION_FLAGS != 0 {
+        reader.read_i32::<LittleEndian>()?;
+        reader.read_i32::<LittleEndian>()?;
+      }
+    } else if has_feature(SCENERYWIDTH) {
+      reader.read_u8()?;
+    }
+
+    let point1 = Point::new(
+      reader.read_f64::<LittleEndian>()?,
+      reader.read_f64::<LittleEndian>()?,
+    );
+    let point2 = Point::new(
+      reader.read_f64::<LittleEndian>()?,
+      reader.read_f64::<LittleEndian>()?,
+    );
+    // Rust Rider's lines are solid on both sides, so flipping a line only
+    // matters for which way it accelerates the rider.
+    line_segments.push(if flags & FLIPPED_FLAG != 0 {
+      LineSegment::new(point2, point1, kind)
+    } else {
+      LineSegment::new(point1, point2, kind)
+    });
+  }
+
+  if unsupported.is_empty() && reader.position() < length {
+    read_metadata(&mut reader, &mut unsupported)?;
+  }
+
+  if !unsupported.is_empty() {
+    return Err(
+      error::ErrorKind::UnsupportedFeatures(
+        String::from(FORMAT),
+        unsupported.into_iter().collect(),
+      ).into(),
+    );
+  }
+
+  let mut track = track::Track::new();
+  track.line_segments = line_segments;
+  track.rider_start = rider_start;
+  track_file::check_points(&track)?;
+  Ok(track)
+}
+
+/// Save a Track to the file at `path` in the format.
+pub fn save(
+  path: &std::path::Path,
+  track: &track::Track,
+) -> error::Result<()> {
+  let file = std::fs::File::create(path)?;
+  to_writer(std::io::BufWriter::new(file), track)
+}
+
+/// Load a Track from a file in the format at `path`.
+pub fn load(path: &std::path::Path) -> error::Result<track::Track> {
+  let file = std::fs::File::open(path)?;
+  from_reader(std::io::BufReader::new(file))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// A track with one line of each kind.
+  fn three_lines() -> track::Track {
+    let mut track = track::Track::new();
+    track.rider_start = Point::new(10.0, -20.0);
+    track.line_segments = vec![
+      LineSegment::new(
+        Point::new(0.0, 0.0),
+        Point::new(100.0, 50.0),
+        LineKind::Normal,
+      ),
+      LineSegment::new(
+        Point::new(100.0, 50.0),
+        Point::new(200.0, 50.0),
+        LineKind::Acceleration,
+      ),
+      LineSegment::new(
+        Point::new(0.0, -50.0),
+        Point::new(10.0, -60.0),
+        LineKind::Scenery,
+      ),
+    ];
+    track
+  }
+
+  /// A track in the format with the given features and no lines.
+  fn with_features(features: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&MAGIC);
+    bytes.push(VERSION);
+    write_short_string(&mut bytes, features).unwrap();
+    bytes.write_f64::<LittleEndian>(0.0).unwrap();
+    bytes.write_f64::<LittleEndian>(0.0).unwrap();
+    bytes.write_u32::<LittleEndian>(0).unwrap();
+    bytes
+  }
+
+  /// The features `from_reader` reports as unsupported in `bytes`.
+  fn unsupported(bytes: &[u8]) -> Vec<String> {
+    let error = from_reader(bytes).unwrap_err();
+    match *error.kind() {
+      error::ErrorKind::UnsupportedFeatures(_, ref features) => {
+        features.clone()
+      },
+      ref kind => panic!("Expected unsupported features, got {}", kind),
+    }
+  }
+
+  #[test]
+  fn round_trip_keeps_lines_and_start() {
+    let track = three_lines();
+    let mut bytes = Vec::new();
+    to_writer(&mut bytes, &track).unwrap();
+    let read = from_reader(bytes.as_slice()).unwrap();
+
+    assert_eq!(read.rider_start, track.rider_start);
+    assert_eq!(read.line_segments, track.line_segments);
+  }
+
+  #[test]
+  fn moving_starts_are_unsupported() {
+    assert!(from_reader(with_features("ZEROSTART;").as_slice()).is_ok());
+    assert_eq!(
+      unsupported(&with_features("6.2;")),
+      vec![String::from("a moving start")]
+    );
+  }
+
+  #[test]
+  fn unknown_features_are_unsupported() {
+    assert_eq!(
+      unsupported(&with_features("ZEROSTART;FRICTIONLESS;")),
+      vec![String::from("frictionless")]
+    );
+  }
+
+  #[test]
+  fn truncated_tracks_are_errors() {
+    let mut bytes = Vec::new();
+    to_writer(&mut bytes, &three_lines()).unwrap();
+    let length = bytes.len();
+    assert!(from_reader(&bytes[..length - 1]).is_err());
+  }
+}